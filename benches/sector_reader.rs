@@ -0,0 +1,231 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+// Compare the former `BufReader<SectorReader>` stack with the `ClusterCache`, read with the same
+// buffer size, on a raw image (or a volume such as `\\.\C:`) for sequential reads, and on NTFS
+// for the directory walk, for file streaming and for the walk reading the content of every file:
+//
+//     set ARALEZ_BENCH_IMAGE=D:\images\server.raw
+//     cargo bench --bench sector_reader
+
+#[allow(dead_code, unused_imports)]
+#[path = "../src/sector_reader.rs"]
+mod sector_reader;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ntfs::{Ntfs, NtfsFile, NtfsReadSeek};
+use sector_reader::{ClusterCache, SectorReader};
+use std::env;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+
+/// Number of MFT records read by the directory walk
+const MAX_RECORDS: usize = 20_000;
+/// Number of bytes of $MFT content read by the streaming benchmark
+const STREAM_LIMIT: u64 = 256 * 1024 * 1024;
+/// Number of bytes of the image read by the sequential benchmark
+const READ_LIMIT: u64 = 8 * 1024 * 1024 * 1024;
+/// Size of the reads issued by the streaming benchmarks, as in the collection
+const STREAM_BUFFER_SIZE: usize = 1024 * 1024;
+const CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+fn open_buffered(path: &str) -> BufReader<SectorReader<File>> {
+    BufReader::new(SectorReader::new(File::open(path).unwrap(), 4096).unwrap())
+}
+
+fn open_cached(path: &str, sector_size: usize, cluster_size: usize) -> ClusterCache<File> {
+    let sr = SectorReader::new(File::open(path).unwrap(), sector_size).unwrap();
+    let read_ahead = (64 * 1024 / cluster_size).max(1);
+    ClusterCache::new(sr, cluster_size, read_ahead, CACHE_SIZE)
+}
+
+fn init<T: Read + Seek>(fs: &mut T) -> Ntfs {
+    let mut ntfs = Ntfs::new(fs).unwrap();
+    ntfs.read_upcase_table(fs).unwrap();
+    ntfs
+}
+
+/// Walk the directory tree like the collection does, reading one MFT record per entry, and the
+/// content of the files when `read_files` is set. Return the number of bytes of content read.
+fn walk<T: Read + Seek>(fs: &mut T, ntfs: &Ntfs, file: &NtfsFile, count: &mut usize, read_files: bool) -> u64 {
+    let records: Vec<u64> = {
+        let index = match file.directory_index(fs) {
+            Ok(index) => index,
+            Err(_) => return 0,
+        };
+        let mut iter = index.entries();
+        let mut records = Vec::new();
+        while let Some(Ok(entry)) = iter.next(fs) {
+            if let Some(Ok(key)) = entry.key() {
+                if key.name().to_string_lossy() != "." {
+                    records.push(entry.file_reference().file_record_number());
+                }
+            }
+        }
+        records
+    };
+
+    let mut total: u64 = 0;
+    for record in records {
+        if *count >= MAX_RECORDS {
+            break;
+        }
+        *count += 1;
+        if let Ok(sub_file) = ntfs.file(fs, record) {
+            if sub_file.is_directory() {
+                total += walk(fs, ntfs, &sub_file, count, read_files);
+            } else if read_files {
+                total += read_file(fs, &sub_file);
+            }
+        }
+    }
+    total
+}
+
+fn walk_volume<T: Read + Seek>(fs: &mut T) -> usize {
+    let ntfs = init(fs);
+    let root = ntfs.root_directory(fs).unwrap();
+    let mut count = 0;
+    walk(fs, &ntfs, &root, &mut count, false);
+    count
+}
+
+fn collect_volume<T: Read + Seek>(fs: &mut T) -> u64 {
+    let ntfs = init(fs);
+    let root = ntfs.root_directory(fs).unwrap();
+    let mut count = 0;
+    walk(fs, &ntfs, &root, &mut count, true)
+}
+
+/// Read the unnamed $DATA of a file by chunks of `STREAM_BUFFER_SIZE` bytes, as the collection does
+fn read_file<T: Read + Seek>(fs: &mut T, file: &NtfsFile) -> u64 {
+    let Some(Ok(data_item)) = file.data(fs, "") else {
+        return 0;
+    };
+    let Ok(attribute) = data_item.to_attribute() else {
+        return 0;
+    };
+    let Ok(mut value) = attribute.value(fs) else {
+        return 0;
+    };
+    let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut total: u64 = 0;
+    while let Ok(n) = value.read(fs, &mut buf) {
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+    }
+    total
+}
+
+/// Read the image from its start by chunks of `STREAM_BUFFER_SIZE` bytes
+fn read_image<T: Read>(fs: &mut T) -> u64 {
+    let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut total: u64 = 0;
+    while total < READ_LIMIT {
+        let n = fs.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+    }
+    total
+}
+
+/// Stream the content of $MFT by chunks of `STREAM_BUFFER_SIZE` bytes
+fn stream_mft<T: Read + Seek>(fs: &mut T) -> u64 {
+    let ntfs = init(fs);
+    let mft = ntfs.file(fs, 0).unwrap();
+    let data_item = mft.data(fs, "").unwrap().unwrap();
+    let attribute = data_item.to_attribute().unwrap();
+    let mut value = attribute.value(fs).unwrap();
+    let mut buf = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut total: u64 = 0;
+    while total < STREAM_LIMIT {
+        let n = value.read(fs, &mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+    }
+    total
+}
+
+fn bench_readers(c: &mut Criterion) {
+    let path = match env::var("ARALEZ_BENCH_IMAGE") {
+        Ok(path) => path,
+        Err(_) => {
+            eprintln!("Set ARALEZ_BENCH_IMAGE to a raw image to run the benchmarks");
+            return;
+        }
+    };
+
+    // Other images are read with 512 bytes sectors and 4 KiB clusters, without the NTFS benchmarks
+    let ntfs_geometry = {
+        let mut fs = open_buffered(&path);
+        Ntfs::new(&mut fs)
+            .ok()
+            .map(|ntfs| (ntfs.sector_size() as usize, ntfs.cluster_size() as usize))
+    };
+    let (sector_size, cluster_size) = ntfs_geometry.unwrap_or((512, 4096));
+
+    let mut group = c.benchmark_group("sector_reader");
+    group.sample_size(10);
+
+    group.bench_function("read/bufreader", |b| {
+        b.iter(|| read_image(&mut open_buffered(&path)))
+    });
+    group.bench_function("read/cluster_cache", |b| {
+        b.iter(|| read_image(&mut open_cached(&path, sector_size, cluster_size)))
+    });
+
+    if ntfs_geometry.is_none() {
+        group.finish();
+        return;
+    }
+
+    {
+        let mut fs = open_cached(&path, sector_size, cluster_size);
+        let records = walk_volume(&mut fs);
+        eprintln!(
+            "Walk of {} records: {} cache hits, {} device reads",
+            records, fs.hits, fs.misses
+        );
+        let mut fs = open_cached(&path, sector_size, cluster_size);
+        let bytes = collect_volume(&mut fs);
+        eprintln!(
+            "Collection of {} bytes: {} cache hits, {} device reads",
+            bytes, fs.hits, fs.misses
+        );
+    }
+
+    group.bench_function("walk/bufreader", |b| {
+        b.iter(|| walk_volume(&mut open_buffered(&path)))
+    });
+    group.bench_function("walk/cluster_cache", |b| {
+        b.iter(|| walk_volume(&mut open_cached(&path, sector_size, cluster_size)))
+    });
+    group.bench_function("collect/bufreader", |b| {
+        b.iter(|| collect_volume(&mut open_buffered(&path)))
+    });
+    group.bench_function("collect/cluster_cache", |b| {
+        b.iter(|| collect_volume(&mut open_cached(&path, sector_size, cluster_size)))
+    });
+    group.bench_function("stream/bufreader", |b| {
+        b.iter(|| stream_mft(&mut open_buffered(&path)))
+    });
+    group.bench_function("stream/cluster_cache", |b| {
+        b.iter(|| stream_mft(&mut open_cached(&path, sector_size, cluster_size)))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_readers);
+criterion_main!(benches);
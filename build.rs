@@ -57,7 +57,7 @@ fn main() -> io::Result<()> {
             };
 
             let status = Command::new(windres)
-                .args(&["app.rc", "-O", "coff", "-o", "app.res"])
+                .args(["app.rc", "-O", "coff", "-o", "app.res"])
                 .status()
                 .expect("Failed to run windres");
 
@@ -109,7 +109,7 @@ fn main() -> io::Result<()> {
         }
     }
 
-    if let Err(e) = extract_sysinternals(&tools_dir, &exe_files) {
+    if let Err(e) = extract_sysinternals(tools_dir, &exe_files) {
         eprintln!("Error extracting Sysinternals tools: {}", e);
     }
 
@@ -120,7 +120,10 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn populate_tools_and_files(target_arch: &Arch) -> (Vec<(&'static str, &'static str)>, Vec<(&'static str, &'static str)>) {
+// (source, output name) pairs of the downloaded tools and of the extracted executables
+type ToolFiles = Vec<(&'static str, &'static str)>;
+
+fn populate_tools_and_files(target_arch: &Arch) -> (ToolFiles, ToolFiles) {
     match target_arch {
         Arch::X86_64 => (
             vec![
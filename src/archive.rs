@@ -0,0 +1,849 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+mod spool;
+mod volumes;
+
+use crate::config::{Config, OutputFormat};
+use crate::encryption::{encrypt_output, ENCRYPTED_EXTENSION};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use filetime::{set_file_handle_times, FileTime};
+use once_cell::sync::Lazy;
+use sevenz_rust::{SevenZArchiveEntry, SevenZMethod, SevenZMethodConfiguration, SevenZWriter};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Seek, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use spool::Spool;
+use zip::write::FileOptions;
+use volumes::VolumeWriter;
+use zip::{CompressionMethod, DateTime as ZipDateTime, ZipArchive, ZipWriter};
+
+/// Compression level of zstd without `compression_level`
+const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Suffix of the index of the volumes written next to a split archive
+const VOLUME_INDEX_SUFFIX: &str = "_index.csv";
+
+/// Upper size of a ZIP central directory record without its name
+const ZIP_DIRECTORY_RECORD_SIZE: u64 = 128;
+
+/// Size of the end records of a ZIP central directory, the Zip64 ones included
+const ZIP_DIRECTORY_END_SIZE: u64 = 98;
+
+/// zstd stream of the tar archive. When the archive is split, each entry starts a new frame,
+/// so it can be decompressed from its own volumes.
+struct FrameWriter {
+    encoder: Option<zstd::Encoder<'static, VolumeWriter>>,
+    compression_level: i32,
+}
+
+impl FrameWriter {
+    fn new(volumes: VolumeWriter, compression_level: i32) -> io::Result<Self> {
+        Ok(FrameWriter {
+            encoder: Some(Self::encoder(volumes, compression_level)?),
+            compression_level,
+        })
+    }
+
+    fn encoder(volumes: VolumeWriter, compression_level: i32) -> io::Result<zstd::Encoder<'static, VolumeWriter>> {
+        let mut encoder = zstd::Encoder::new(volumes, compression_level)?;
+        let workers = thread::available_parallelism().map_or(1, |workers| workers.get());
+        encoder.multithread(workers as u32)?;
+        Ok(encoder)
+    }
+
+    fn encoder_mut(&mut self) -> io::Result<&mut zstd::Encoder<'static, VolumeWriter>> {
+        self.encoder
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the zstd stream is finished"))
+    }
+
+    /// End the current frame and start a new one
+    fn new_frame(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            self.encoder = Some(Self::encoder(encoder.finish()?, self.compression_level)?);
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for FrameWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder_mut()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder_mut()?.flush()
+    }
+}
+
+/// Writer of the archive in its format
+enum Container {
+    Zip(Box<ZipWriter<VolumeWriter>>),
+    TarZst(tar::Builder<FrameWriter>),
+    SevenZ(SevenZWriter<VolumeWriter>),
+}
+
+impl Container {
+    fn create(volumes: VolumeWriter, format: OutputFormat, compression_level: Option<i32>) -> io::Result<Self> {
+        Ok(match format {
+            OutputFormat::Zip => Container::Zip(Box::new(ZipWriter::new(volumes))),
+            OutputFormat::TarZst => {
+                let compression_level = compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL);
+                Container::TarZst(tar::Builder::new(FrameWriter::new(volumes, compression_level)?))
+            }
+            OutputFormat::SevenZ => {
+                let mut writer = SevenZWriter::new(volumes).map_err(other_error)?;
+                writer.set_content_methods(vec![SevenZMethodConfiguration::new(SevenZMethod::LZMA2)]);
+                Container::SevenZ(writer)
+            }
+        })
+    }
+
+    /// Add a file of the disk as the entry `name`, with its modification time
+    fn add_file(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        match self {
+            Container::Zip(zip) => {
+                let modified = file.metadata()?.modified().ok();
+                zip.start_file(name, entry_options(modified)).map_err(other_error)?;
+                io::copy(&mut file, zip)?;
+            }
+            Container::TarZst(builder) => builder.append_file(name, &mut file)?,
+            Container::SevenZ(writer) => {
+                writer
+                    .push_archive_entry(SevenZArchiveEntry::from_path(path, name.to_string()), Some(file))
+                    .map_err(other_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add the bytes of a reader as the entry `name`
+    fn add_data(&mut self, reader: &mut impl io::Read, size: u64, name: &str, modified: Option<SystemTime>) -> io::Result<()> {
+        match self {
+            Container::Zip(zip) => {
+                zip.start_file(name, entry_options(modified)).map_err(other_error)?;
+                io::copy(reader, zip)?;
+            }
+            Container::TarZst(builder) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(size);
+                header.set_mode(0o644);
+                let mtime = modified.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+                header.set_mtime(mtime.map_or(0, |mtime| mtime.as_secs()));
+                builder.append_data(&mut header, name, reader)?;
+            }
+            Container::SevenZ(writer) => {
+                let mut entry = SevenZArchiveEntry::new();
+                entry.name = name.to_string();
+                entry.has_stream = true;
+                if let Some(date) = modified.and_then(|modified| modified.try_into().ok()) {
+                    entry.last_modified_date = date;
+                    entry.has_last_modified_date = true;
+                }
+                writer.push_archive_entry(entry, Some(reader)).map_err(other_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_directory(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        match self {
+            Container::Zip(zip) => zip.add_directory(name, entry_options(None)).map_err(other_error)?,
+            Container::TarZst(builder) => builder.append_dir(name, path)?,
+            Container::SevenZ(writer) => {
+                writer
+                    .push_archive_entry::<File>(
+                        SevenZArchiveEntry::from_path(path, name.trim_end_matches('/').to_string()),
+                        None,
+                    )
+                    .map_err(other_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Container::Zip(zip) => {
+                zip.finish().map_err(other_error)?;
+            }
+            Container::TarZst(builder) => {
+                builder.into_inner()?.finish()?;
+            }
+            Container::SevenZ(writer) => {
+                writer.finish().map_err(other_error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Output archive, the files not streamed in it are staged in its root folder
+struct Archive {
+    /// Staging folder, the names of the entries are relative to it
+    root_output: String,
+    container: Container,
+    volumes: VolumeWriter,
+    /// Stream the outputs in the archive, otherwise everything is staged until it is closed
+    streaming: bool,
+    /// Names of the entries already in the archive
+    names: HashSet<String>,
+    /// Entries of a split archive with their offset, for the index of the volumes
+    entries: Vec<(String, u64)>,
+    /// Public keys the archive and its index are encrypted to
+    recipients: Option<Vec<String>>,
+}
+
+impl Archive {
+    /// Note where an entry starts in a split archive, return its offset
+    fn start_entry(&mut self, name: &str) -> io::Result<u64> {
+        let is_split = self.volumes.lock().is_split();
+        if is_split {
+            if let Container::TarZst(builder) = &mut self.container {
+                builder.get_mut().new_frame()?;
+            }
+        }
+        let offset = self.volumes.lock().size();
+        if is_split {
+            self.entries.push((name.to_string(), offset));
+        }
+        Ok(offset)
+    }
+
+    /// Encrypt the archive up to the ZIP entry starting at `offset`, the previous entries
+    /// are finished once it is started
+    fn commit_entries(&self, offset: u64) -> io::Result<()> {
+        if let Container::Zip(_) = self.container {
+            self.volumes.lock().commit(offset)?;
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let offset = self.start_entry(name)?;
+        self.container.add_file(path, name)?;
+        self.commit_entries(offset)
+    }
+
+    fn add_directory(&mut self, path: &Path, name: &str) -> io::Result<()> {
+        let offset = self.start_entry(name)?;
+        self.container.add_directory(path, name)?;
+        self.commit_entries(offset)
+    }
+
+    fn add_spooled_file(&mut self, mut spooled: SpooledFile) -> io::Result<()> {
+        if !self.names.insert(spooled.name.clone()) {
+            dprintln!("[WARN] `{}` is already in the archive, the spooled file is ignored", spooled.name);
+            return Ok(());
+        }
+        let offset = self.start_entry(&spooled.name)?;
+        spooled.spool.rewind()?;
+        let size = spooled.spool.len();
+        self.container.add_data(&mut spooled.spool, size, &spooled.name, spooled.modified)?;
+        self.commit_entries(offset)
+    }
+
+    /// Copy the compressed entry of a single entry ZIP at the end of the archive
+    fn merge_entry(&mut self, name: &str, source: ZipArchive<Spool>) -> io::Result<()> {
+        let offset = self.start_entry(name)?;
+        match &mut self.container {
+            Container::Zip(zip) => zip.merge_archive(source).map_err(other_error)?,
+            _ => return Err(io::Error::other("the archive is not a ZIP archive")),
+        }
+        self.commit_entries(offset)
+    }
+}
+
+static ARCHIVE: Lazy<Mutex<Option<Archive>>> = Lazy::new(|| Mutex::new(None));
+
+fn lock_archive() -> MutexGuard<'static, Option<Archive>> {
+    ARCHIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Root folder of an archive encrypted to recipients. Its outputs are spooled, so they never reach
+/// the disk in plaintext.
+static ENCRYPTED_ROOT: Mutex<Option<String>> = Mutex::new(None);
+
+fn lock_encrypted_root() -> MutexGuard<'static, Option<String>> {
+    ENCRYPTED_ROOT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// A file of the output, streamed in the archive, spooled for an encrypted archive or staged on disk
+pub enum OutputFile {
+    Entry(Box<ArchiveEntry>),
+    Spooled(Box<SpooledFile>),
+    Staged(StagedFile),
+}
+
+/// A ZIP entry compressed by its thread in a single entry ZIP spool, without the archive lock.
+/// Its compressed bytes are copied in the archive when it is finished.
+pub struct ArchiveEntry {
+    zip: ZipWriter<Spool>,
+    name: String,
+}
+
+impl ArchiveEntry {
+    fn finish(self) -> io::Result<()> {
+        let spool = self.zip.finish().map_err(other_error)?;
+        let source = ZipArchive::new(spool).map_err(other_error)?;
+        match lock_archive().as_mut() {
+            Some(archive) => archive.merge_entry(&self.name, source),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A file of an encrypted archive, kept in a spool until it is finished
+pub struct SpooledFile {
+    spool: Spool,
+    name: String,
+    modified: Option<SystemTime>,
+}
+
+impl SpooledFile {
+    fn finish(self) -> io::Result<()> {
+        match lock_archive().as_mut() {
+            Some(archive) => archive.add_spooled_file(self),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A file written on disk, moved in the archive once finished when the format can't be streamed
+pub struct StagedFile {
+    file: File,
+    path: String,
+    modified: Option<SystemTime>,
+    entry_name: Option<String>,
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Entry(entry) => entry.zip.write(buf),
+            OutputFile::Spooled(spooled) => spooled.spool.write(buf),
+            OutputFile::Staged(staged) => staged.file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Entry(entry) => entry.zip.flush(),
+            OutputFile::Spooled(spooled) => spooled.spool.flush(),
+            OutputFile::Staged(staged) => staged.file.flush(),
+        }
+    }
+}
+
+impl OutputFile {
+    /// Finish the file, the staged files get their modification time
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        let staged = match self {
+            OutputFile::Entry(entry) => return (*entry).finish(),
+            OutputFile::Spooled(spooled) => return (*spooled).finish(),
+            OutputFile::Staged(staged) => staged,
+        };
+        if let Some(modified) = staged.modified {
+            set_file_handle_times(&staged.file, None, Some(FileTime::from_system_time(modified)))?;
+        }
+        let Some(name) = staged.entry_name else {
+            return Ok(());
+        };
+        drop(staged.file);
+
+        let mut guard = lock_archive();
+        let Some(archive) = guard.as_mut() else {
+            return Ok(());
+        };
+        archive.add_file(Path::new(&staged.path), &name)?;
+        archive.names.insert(name);
+        fs::remove_file(&staged.path)
+    }
+}
+
+fn other_error<E>(e: E) -> io::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    io::Error::other(e)
+}
+
+fn entry_options(modified: Option<SystemTime>) -> FileOptions<'static, ()> {
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    let Some(modified) = modified else {
+        return options;
+    };
+    let datetime = DateTime::<Utc>::from(modified).naive_utc();
+    let zip_datetime = ZipDateTime::from_date_and_time(
+        datetime.year() as u16,
+        datetime.month() as u8,
+        datetime.day() as u8,
+        datetime.hour() as u8,
+        datetime.minute() as u8,
+        datetime.second() as u8,
+    )
+    .unwrap_or_else(|_| ZipDateTime::default_for_write());
+    options.last_modified_time(zip_datetime)
+}
+
+/// Name of the entry of a path in the root folder, `None` for a path outside of it
+fn entry_name(root_output: &str, path: &str) -> Option<String> {
+    let relative = path.strip_prefix(root_output)?;
+    if !relative.starts_with(['/', '\\']) {
+        return None;
+    }
+    let name = relative.replace('\\', "/").trim_start_matches('/').to_string();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Create the archive `{root_output}.{output_format}`, in volumes of `output_split_size` bytes if set.
+/// With `output_staging`, the outputs are staged in `root_output` and compressed when the archive
+/// is closed, which needs twice the disk space. With `output_recipients`, the volumes are encrypted
+/// to the public keys as they are written.
+pub fn open_archive(root_output: &str, config: &Config) -> io::Result<()> {
+    let format = config.output_format.unwrap_or(OutputFormat::Zip);
+    let path = format!("{}.{}", root_output, format.as_str());
+    let recipients = config.output_recipients.as_deref();
+    let volumes = VolumeWriter::create(&path, format, config.output_split_size, recipients)?;
+    *lock_encrypted_root() = recipients.map(|_| root_output.to_string());
+    *lock_archive() = Some(Archive {
+        root_output: root_output.to_string(),
+        container: Container::create(volumes.clone(), format, config.compression_level)?,
+        volumes,
+        streaming: config.output_staging != Some(true),
+        names: HashSet::new(),
+        entries: Vec::new(),
+        recipients: config.output_recipients.clone(),
+    });
+    Ok(())
+}
+
+/// Create a file of the output, streamed in the archive when it is in the root folder.
+/// ZIP entries are compressed in a spool by the thread writing them and copied in the archive
+/// when they are finished, so the threads never wait for each other's sources.
+/// The other formats need the size of an entry first, so each file is moved in the archive
+/// when it is finished, from a spool when the archive is encrypted. Return `None` if the file already exists.
+pub fn create_output(path: &str, modified: Option<SystemTime>) -> io::Result<Option<OutputFile>> {
+    let mut entry = None;
+    let mut zip_entry = None;
+    if let Some(archive) = lock_archive().as_mut().filter(|archive| archive.streaming) {
+        if let Some(name) = entry_name(&archive.root_output, path) {
+            if archive.names.contains(&name) {
+                return Ok(None);
+            }
+            // The name of a ZIP entry is taken now, another thread may write the same path meanwhile
+            if matches!(archive.container, Container::Zip(_)) {
+                archive.names.insert(name.clone());
+                zip_entry = Some(name);
+            } else {
+                entry = Some(name);
+            }
+        }
+    }
+
+    if let Some(parent) = Path::new(path).parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let output = if let Some(name) = zip_entry {
+        let zip_output = Spool::create(path).and_then(|spool| {
+            let mut zip = ZipWriter::new(spool);
+            zip.start_file(name.as_str(), entry_options(modified)).map_err(other_error)?;
+            Ok(OutputFile::Entry(Box::new(ArchiveEntry { zip, name: name.clone() })))
+        });
+        if zip_output.is_err() {
+            if let Some(archive) = lock_archive().as_mut() {
+                archive.names.remove(&name);
+            }
+        }
+        zip_output
+    } else {
+        let spooled_name = match lock_encrypted_root().as_deref() {
+            Some(root_output) => entry.clone().or_else(|| entry_name(root_output, path)),
+            None => None,
+        };
+        match spooled_name {
+            Some(name) => Spool::create(path).map(|spool| OutputFile::Spooled(Box::new(SpooledFile { spool, name, modified }))),
+            None => OpenOptions::new().write(true).create_new(true).open(path).map(|file| {
+                OutputFile::Staged(StagedFile {
+                    file,
+                    path: path.to_string(),
+                    modified,
+                    entry_name: entry,
+                })
+            }),
+        }
+    };
+    match output {
+        Ok(output) => Ok(Some(output)),
+        Err(ref e) if e.kind() == ErrorKind::AlreadyExists => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Add the staged files of a folder, the entries already streamed are kept
+fn add_staged_files(archive: &mut Archive, dir: &Path, parent_dir: &str) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = format!("{}{}", parent_dir, entry.file_name().to_string_lossy());
+
+        if path.is_dir() {
+            let dir_name = format!("{}/", name);
+            if archive.names.insert(dir_name.clone()) {
+                archive.add_directory(&path, &dir_name)?;
+            }
+            add_staged_files(archive, &path, &dir_name)?;
+        } else if path.is_file() {
+            if !archive.names.insert(name.clone()) {
+                dprintln!("[WARN] `{}` is already in the archive, the staged file is ignored", name);
+                continue;
+            }
+            archive.add_file(&path, &name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the staged files of the root folder to the archive and finish it,
+/// a split archive gets the index of its volumes, encrypted with `output_recipients`.
+/// With `output_recipients`, only the files an external tool writes itself in the root folder
+/// are in plaintext until then.
+pub fn close_archive() -> anyhow::Result<()> {
+    let Some(mut archive) = lock_archive().take() else {
+        return Ok(());
+    };
+    *lock_encrypted_root() = None;
+    let root_path = Path::new(&archive.root_output).to_path_buf();
+    if root_path.is_dir() {
+        add_staged_files(&mut archive, &root_path, "")?;
+    }
+
+    let Archive {
+        root_output,
+        container,
+        volumes,
+        entries,
+        recipients,
+        ..
+    } = archive;
+    let data_end = volumes.lock().size();
+    if let Container::Zip(_) = container {
+        // Keep the central directory in the last volume when it fits in one
+        let directory_size: u64 = entries
+            .iter()
+            .map(|(name, _)| ZIP_DIRECTORY_RECORD_SIZE + name.len() as u64)
+            .sum();
+        volumes.lock().reserve(directory_size + ZIP_DIRECTORY_END_SIZE)?;
+    }
+    container.finish()?;
+    let mut volumes = volumes.lock();
+    let paths = volumes.finish()?;
+
+    if volumes.is_split() {
+        // Index of the volumes holding each entry, the directory at the end of ZIP and 7z is in the last ones.
+        // The offset is the one of the entry in its first volume (decrypted with `output_recipients`):
+        // its ZIP local header, its zstd frame or its 7z packed stream.
+        let volume = |offset: u64| {
+            let (index, volume_offset, _) = volumes.locate(offset);
+            let path = Path::new(&paths[index]);
+            let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+            (name, volume_offset)
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["entry", "first_volume", "offset", "last_volume"])?;
+        let mut rows: Vec<(&str, u64, u64)> = entries
+            .iter()
+            .enumerate()
+            .map(|(position, (name, start))| {
+                let end = entries.get(position + 1).map_or(data_end, |(_, next_start)| *next_start);
+                (name.as_str(), *start, end.max(start + 1) - 1)
+            })
+            .collect();
+        if volumes.size() > data_end {
+            rows.push(("(directory)", data_end, volumes.size() - 1));
+        }
+        for (name, start, last) in rows {
+            let (first_volume, offset) = volume(start);
+            writer.write_record([name, &first_volume, &offset.to_string(), &volume(last).0])?;
+        }
+        let index = writer.into_inner()?;
+
+        let index_path = format!("{}{}", root_output, VOLUME_INDEX_SUFFIX);
+        match recipients {
+            Some(recipients) => {
+                let file = File::create(format!("{}{}", index_path, ENCRYPTED_EXTENSION))?;
+                let mut stream = encrypt_output(file, &recipients)?;
+                stream.write_all(&index)?;
+                stream.finish()?;
+            }
+            None => fs::write(&index_path, index)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encryption::decrypt_files;
+    use age::secrecy::ExposeSecret;
+    use std::io::Read;
+    use std::sync::Barrier;
+
+    /// The archive is global, the tests writing one run one at a time
+    static ARCHIVE_TESTS: Mutex<()> = Mutex::new(());
+
+    /// Read the entries of a decrypted archive
+    fn read_entries(path: &Path, format: OutputFormat) -> Vec<(String, Vec<u8>)> {
+        let mut entries = Vec::new();
+        match format {
+            OutputFormat::Zip => {
+                let mut zip = zip::ZipArchive::new(File::open(path).unwrap()).unwrap();
+                for index in 0..zip.len() {
+                    let mut file = zip.by_index(index).unwrap();
+                    let mut data = Vec::new();
+                    file.read_to_end(&mut data).unwrap();
+                    entries.push((file.name().to_string(), data));
+                }
+            }
+            OutputFormat::TarZst => {
+                let tar = zstd::decode_all(File::open(path).unwrap()).unwrap();
+                for entry in tar::Archive::new(tar.as_slice()).entries().unwrap() {
+                    let mut entry = entry.unwrap();
+                    let mut data = Vec::new();
+                    entry.read_to_end(&mut data).unwrap();
+                    entries.push((entry.path().unwrap().to_string_lossy().to_string(), data));
+                }
+            }
+            OutputFormat::SevenZ => {
+                let dest = path.with_extension("extracted");
+                sevenz_rust::decompress_file(path, &dest).unwrap();
+                for name in ["logs/", "logs/main.txt", "logs/main.txt.FileSlack"] {
+                    let data = fs::read(dest.join(name)).unwrap_or_default();
+                    entries.push((name.to_string(), data));
+                }
+            }
+        }
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn encrypts_the_outputs_to_the_recipients() {
+        let _lock = ARCHIVE_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aralez-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let identity = age::x25519::Identity::generate();
+        let identity_path = dir.join("identity.txt");
+        fs::write(&identity_path, identity.to_string().expose_secret()).unwrap();
+
+        for format in [OutputFormat::Zip, OutputFormat::TarZst, OutputFormat::SevenZ] {
+            let root_output = dir.join(format!("out_{}", format.as_str())).to_string_lossy().to_string();
+            let yaml = format!(
+                "output_filename: out\noutput_format: {}\noutput_recipients: [\"{}\"]\ntasks: {{}}\n",
+                format.as_str(),
+                identity.to_public()
+            );
+            open_archive(&root_output, &Config::from_yaml(&yaml, Path::new(".")).unwrap()).unwrap();
+            // The slack file is written while the main entry is being written
+            let main_path = format!("{}/logs/main.txt", root_output);
+            let mut main = create_output(&main_path, None).unwrap().unwrap();
+            main.write_all(b"collected data of the main file").unwrap();
+            let mut slack = create_output(&format!("{}.FileSlack", main_path), None).unwrap().unwrap();
+            slack.write_all(b"collected slack").unwrap();
+            slack.finish().unwrap();
+            main.finish().unwrap();
+            close_archive().unwrap();
+            fs::remove_dir_all(&root_output).unwrap();
+
+            let archive_path = format!("{}.{}.age", root_output, format.as_str());
+            let encrypted = fs::read(&archive_path).unwrap();
+            assert!(!encrypted.windows(9).any(|window| window == b"collected"));
+            let decrypted = decrypt_files(&[archive_path], &identity_path.to_string_lossy(), None).unwrap();
+            let entries = read_entries(Path::new(&decrypted[0]), format);
+            assert_eq!(
+                entries,
+                [
+                    ("logs/".to_string(), Vec::new()),
+                    ("logs/main.txt".to_string(), b"collected data of the main file".to_vec()),
+                    ("logs/main.txt.FileSlack".to_string(), b"collected slack".to_vec()),
+                ],
+                "{}",
+                format.as_str()
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn writes_entries_from_several_threads_at_the_same_time() {
+        let _lock = ARCHIVE_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aralez-threads-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let root_output = dir.join("out").to_string_lossy().to_string();
+        let config = Config::from_yaml("output_filename: out\ntasks: {}\n", Path::new(".")).unwrap();
+        open_archive(&root_output, &config).unwrap();
+
+        // Both entries are open while the other thread writes, the archive is locked only to add them
+        let barrier = Barrier::new(2);
+        thread::scope(|scope| {
+            for index in 0..2 {
+                let (barrier, root_output) = (&barrier, &root_output);
+                scope.spawn(move || {
+                    let mut output = create_output(&format!("{}/thread{}.txt", root_output, index), None)
+                        .unwrap()
+                        .unwrap();
+                    output.write_all(format!("first part of thread {}, ", index).as_bytes()).unwrap();
+                    barrier.wait();
+                    output.write_all(b"second part").unwrap();
+                    barrier.wait();
+                    output.finish().unwrap();
+                });
+            }
+        });
+        close_archive().unwrap();
+
+        let entries = read_entries(Path::new(&format!("{}.zip", root_output)), OutputFormat::Zip);
+        assert_eq!(
+            entries,
+            [
+                ("thread0.txt".to_string(), b"first part of thread 0, second part".to_vec()),
+                ("thread1.txt".to_string(), b"first part of thread 1, second part".to_vec()),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn indexes_the_offsets_of_the_entries_in_their_volumes() {
+        let _lock = ARCHIVE_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aralez-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Random bytes are not compressed, the entries span several volumes
+        let mut state: u32 = 1;
+        let data: Vec<u8> = (0..50_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+
+        for format in [OutputFormat::Zip, OutputFormat::TarZst] {
+            let root_output = dir.join(format!("out_{}", format.as_str())).to_string_lossy().to_string();
+            let yaml = format!(
+                "output_filename: out\noutput_format: {}\noutput_split_size: 64KiB\ntasks: {{}}\n",
+                format.as_str()
+            );
+            open_archive(&root_output, &Config::from_yaml(&yaml, Path::new(".")).unwrap()).unwrap();
+            for name in ["first.bin", "second.bin", "third.bin"] {
+                let mut output = create_output(&format!("{}/{}", root_output, name), None).unwrap().unwrap();
+                output.write_all(&data).unwrap();
+                output.finish().unwrap();
+            }
+            close_archive().unwrap();
+
+            let index = fs::read(format!("{}{}", root_output, VOLUME_INDEX_SUFFIX)).unwrap();
+            let mut reader = csv::Reader::from_reader(index.as_slice());
+            assert_eq!(reader.headers().unwrap(), vec!["entry", "first_volume", "offset", "last_volume"]);
+            let mut names = Vec::new();
+            for row in reader.records() {
+                let row = row.unwrap();
+                if &row[0] == "(directory)" {
+                    continue;
+                }
+                let volume = fs::read(dir.join(&row[1])).unwrap();
+                let offset: usize = row[2].parse().unwrap();
+                match format {
+                    OutputFormat::Zip => {
+                        assert_eq!(volume[offset..offset + 4], [0x50, 0x4b, 0x03, 0x04]);
+                        assert_eq!(&volume[offset + 30..offset + 30 + row[0].len()], row[0].as_bytes());
+                    }
+                    _ => assert_eq!(volume[offset..offset + 4], [0x28, 0xb5, 0x2f, 0xfd]),
+                }
+                names.push(row[0].to_string());
+            }
+            assert_eq!(names, ["first.bin", "second.bin", "third.bin"], "{}", format.as_str());
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_no_plaintext_on_the_disk_before_the_archive_is_closed() {
+        let _lock = ARCHIVE_TESTS.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aralez-plaintext-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let identity = age::x25519::Identity::generate();
+        // Random bytes are not compressed, they would be found in a plaintext archive
+        let mut state: u32 = 7;
+        let data: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+
+        /// Check the files of a folder for a part of the collected data
+        fn assert_no_plaintext(dir: &Path, data: &[u8]) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    assert_no_plaintext(&path, data);
+                } else if let Ok(content) = fs::read(&path) {
+                    for part in data.chunks(64).step_by(500) {
+                        assert!(!content.windows(part.len()).any(|window| window == part), "{}", path.display());
+                    }
+                }
+            }
+        }
+
+        for format in [OutputFormat::Zip, OutputFormat::TarZst, OutputFormat::SevenZ] {
+            let root_output = dir.join(format!("out_{}", format.as_str())).to_string_lossy().to_string();
+            let yaml = format!(
+                "output_filename: out\noutput_format: {}\noutput_split_size: 64KiB\noutput_recipients: [\"{}\"]\ntasks: {{}}\n",
+                format.as_str(),
+                identity.to_public()
+            );
+            open_archive(&root_output, &Config::from_yaml(&yaml, Path::new(".")).unwrap()).unwrap();
+            for name in ["first.bin", "second.bin"] {
+                let mut output = create_output(&format!("{}/files/{}", root_output, name), None).unwrap().unwrap();
+                output.write_all(&data).unwrap();
+                output.finish().unwrap();
+            }
+            // The process is killed while a file is written
+            let mut output = create_output(&format!("{}/files/third.bin", root_output), None).unwrap().unwrap();
+            output.write_all(&data).unwrap();
+            assert_no_plaintext(&dir, &data);
+
+            output.finish().unwrap();
+            close_archive().unwrap();
+            assert_no_plaintext(&dir, &data);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
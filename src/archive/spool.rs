@@ -0,0 +1,267 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
+
+/// Bytes encrypted together in the spool
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// Place of a chunk in the file, with its nonce and tag
+const SLOT_SIZE: u64 = (NONCE_SIZE + CHUNK_SIZE + TAG_SIZE) as u64;
+
+#[cfg(windows)]
+const FILE_FLAG_DELETE_ON_CLOSE: u32 = 0x0400_0000;
+
+/// Chunk last accessed, written in the file when another one is
+struct Chunk {
+    index: u64,
+    data: Vec<u8>,
+    modified: bool,
+}
+
+/// Temporary file encrypted with a random key only kept in memory, for the bytes of an encrypted
+/// archive which are not final yet. Each chunk gets a new nonce when it is written again.
+pub struct Spool {
+    file: File,
+    cipher: Aes256Gcm,
+    len: u64,
+    chunk: Option<Chunk>,
+    /// Chunks were written in the file since it was cleared
+    stored: bool,
+    /// Position of the `Read` and `Write` implementations
+    position: u64,
+}
+
+impl Spool {
+    /// Create the spool in a new file, removed when it is closed
+    pub fn create(path: &str) -> io::Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+        #[cfg(windows)]
+        options.custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+        let file = options.open(path)?;
+        #[cfg(not(windows))]
+        std::fs::remove_file(path)?;
+        Ok(Spool {
+            file,
+            cipher: Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng)),
+            len: 0,
+            chunk: None,
+            stored: false,
+            position: 0,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn store(&mut self) -> io::Result<()> {
+        let Some(chunk) = self.chunk.as_mut().filter(|chunk| chunk.modified) else {
+            return Ok(());
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, chunk.data.as_slice())
+            .map_err(|_| io::Error::other("encryption of the spool failed"))?;
+        self.file.seek(SeekFrom::Start(chunk.index * SLOT_SIZE))?;
+        self.file.write_all(&nonce)?;
+        self.file.write_all(&ciphertext)?;
+        chunk.modified = false;
+        self.stored = true;
+        Ok(())
+    }
+
+    fn load(&mut self, index: u64) -> io::Result<Chunk> {
+        let start = index * CHUNK_SIZE as u64;
+        let size = self.len.saturating_sub(start).min(CHUNK_SIZE as u64) as usize;
+        if size == 0 {
+            return Ok(Chunk { index, data: Vec::new(), modified: false });
+        }
+        let mut slot = vec![0u8; NONCE_SIZE + size + TAG_SIZE];
+        self.file.seek(SeekFrom::Start(index * SLOT_SIZE))?;
+        self.file.read_exact(&mut slot)?;
+        let data = self
+            .cipher
+            .decrypt(Nonce::from_slice(&slot[..NONCE_SIZE]), &slot[NONCE_SIZE..])
+            .map_err(|_| io::Error::new(ErrorKind::InvalidData, "corrupted spool"))?;
+        Ok(Chunk { index, data, modified: false })
+    }
+
+    fn chunk(&mut self, index: u64) -> io::Result<&mut Chunk> {
+        let chunk = match self.chunk.take() {
+            Some(chunk) if chunk.index == index => chunk,
+            other => {
+                self.chunk = other;
+                self.store()?;
+                self.load(index)?
+            }
+        };
+        Ok(self.chunk.insert(chunk))
+    }
+
+    /// Write in the chunk holding `offset`, return the number of bytes written
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        if offset > self.len {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "write after the end of the spool"));
+        }
+        let start = (offset % CHUNK_SIZE as u64) as usize;
+        let size = (CHUNK_SIZE - start).min(buf.len());
+        let chunk = self.chunk(offset / CHUNK_SIZE as u64)?;
+        if chunk.data.len() < start + size {
+            chunk.data.resize(start + size, 0);
+        }
+        chunk.data[start..start + size].copy_from_slice(&buf[..size]);
+        chunk.modified = true;
+        self.len = self.len.max(offset + size as u64);
+        Ok(size)
+    }
+
+    /// Read from the chunk holding `offset`, return the number of bytes read, 0 at the end of the spool
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= self.len {
+            return Ok(0);
+        }
+        let start = (offset % CHUNK_SIZE as u64) as usize;
+        let chunk = self.chunk(offset / CHUNK_SIZE as u64)?;
+        let size = (chunk.data.len() - start).min(buf.len());
+        buf[..size].copy_from_slice(&chunk.data[start..start + size]);
+        Ok(size)
+    }
+
+    pub fn read_exact_at(&mut self, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let size = self.read_at(offset, buf)?;
+            if size == 0 {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "read after the end of the spool"));
+            }
+            offset += size as u64;
+            buf = &mut buf[size..];
+        }
+        Ok(())
+    }
+
+    pub fn write_all_at(&mut self, mut offset: u64, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let size = self.write_at(offset, buf)?;
+            offset += size as u64;
+            buf = &buf[size..];
+        }
+        Ok(())
+    }
+
+    /// Drop the content of the spool
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.chunk = None;
+        self.len = 0;
+        self.position = 0;
+        if self.stored {
+            self.file.set_len(0)?;
+            self.stored = false;
+        }
+        Ok(())
+    }
+
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.write_at(self.position, buf)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.position.checked_add_signed(n),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))?;
+        Ok(self.position)
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.read_at(self.position, buf)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spool(name: &str) -> Spool {
+        let path = std::env::temp_dir().join(format!("aralez-spool-{}-{}", name, std::process::id()));
+        Spool::create(&path.to_string_lossy()).unwrap()
+    }
+
+    #[test]
+    fn rewrites_bytes_across_chunks() {
+        let mut spool = spool("rewrite");
+        let data: Vec<u8> = (0..3 * CHUNK_SIZE + 100).map(|i| (i % 251) as u8).collect();
+        spool.write_all(&data).unwrap();
+        spool.write_all_at(CHUNK_SIZE as u64 - 2, b"patch").unwrap();
+        spool.write_all_at(10, b"head").unwrap();
+
+        let mut expected = data.clone();
+        expected[CHUNK_SIZE - 2..CHUNK_SIZE + 3].copy_from_slice(b"patch");
+        expected[10..14].copy_from_slice(b"head");
+        let mut read = Vec::new();
+        spool.rewind().unwrap();
+        spool.read_to_end(&mut read).unwrap();
+        assert_eq!(spool.len(), data.len() as u64);
+        assert!(read == expected);
+    }
+
+    #[test]
+    fn keeps_no_plaintext_in_the_file() {
+        let mut spool = spool("plaintext");
+        let data = b"plaintext of the collected file ".repeat(4096);
+        spool.write_all(&data).unwrap();
+        spool.store().unwrap();
+        let mut stored = Vec::new();
+        spool.file.seek(SeekFrom::Start(0)).unwrap();
+        spool.file.read_to_end(&mut stored).unwrap();
+        assert!(!stored.is_empty());
+        assert!(!stored.windows(9).any(|window| window == b"plaintext"));
+    }
+
+    #[test]
+    fn starts_again_when_cleared() {
+        let mut spool = spool("clear");
+        spool.write_all(&[1u8; CHUNK_SIZE * 2]).unwrap();
+        spool.clear().unwrap();
+        assert_eq!(spool.len(), 0);
+        assert!(spool.write_at(1, b"x").is_err());
+        spool.write_all(b"new").unwrap();
+        let mut read = [0u8; 3];
+        spool.read_exact_at(0, &mut read).unwrap();
+        assert_eq!(&read, b"new");
+        assert_eq!(spool.read_at(3, &mut read).unwrap(), 0);
+    }
+}
@@ -0,0 +1,826 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use super::spool::{Spool, CHUNK_SIZE};
+use crate::config::OutputFormat;
+use crate::encryption::{encrypt_output, ENCRYPTED_EXTENSION};
+use age::stream::StreamWriter;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// First bytes of the first volume of a split ZIP
+const SPLIT_ZIP_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x07, 0x08];
+
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_EXTRA_FIELD_ID: u16 = 0x0001;
+
+/// Size of the end of central directory record without comment
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+const ZIP64_LOCATOR_SIZE: u64 = 20;
+/// Size of a central directory record without its name, extra field and comment
+const CENTRAL_DIRECTORY_RECORD_SIZE: u64 = 46;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Volumes encrypted to recipients as the archive is written, so no plaintext byte reaches the disk.
+/// The bytes the archive can still rewrite are kept in an encrypted spool: the last ZIP entry,
+/// whose local header is updated when the next one starts, or a whole 7z archive until it is finished.
+struct Encryption {
+    recipients: Vec<String>,
+    /// The bytes of the archive before this offset are encrypted in the volumes
+    committed: u64,
+    spool: Spool,
+    /// Offset in the archive of the first byte of the spool
+    spool_start: u64,
+    /// Volume the committed bytes are written to
+    stream: Option<(usize, StreamWriter<BufWriter<File>>)>,
+}
+
+impl Encryption {
+    fn spool_offset(&self, offset: u64) -> io::Result<u64> {
+        if offset < self.committed {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "bytes of the archive already encrypted"));
+        }
+        Ok(offset - self.spool_start)
+    }
+
+    /// Stream of a volume, the previous one is finished when the next one starts
+    fn stream(&mut self, index: usize, path: &str, header: &[u8]) -> io::Result<&mut StreamWriter<BufWriter<File>>> {
+        let stream = match self.stream.take() {
+            Some((current, stream)) if current == index => stream,
+            previous => {
+                if let Some((_, stream)) = previous {
+                    stream.finish()?.flush()?;
+                }
+                let mut stream = encrypt_output(BufWriter::new(File::create(path)?), &self.recipients)?;
+                stream.write_all(header)?;
+                stream
+            }
+        };
+        Ok(&mut self.stream.insert((index, stream)).1)
+    }
+
+    /// Start the spool again at the first byte not encrypted yet, when the bytes left fit in a chunk
+    fn restart_spool(&mut self, len: u64) -> io::Result<()> {
+        let left = len - self.committed;
+        if self.committed == self.spool_start || left > CHUNK_SIZE as u64 {
+            return Ok(());
+        }
+        let mut bytes = vec![0u8; left as usize];
+        self.spool.read_exact_at(self.committed - self.spool_start, &mut bytes)?;
+        self.spool.clear()?;
+        self.spool.write_all_at(0, &bytes)?;
+        self.spool_start = self.committed;
+        Ok(())
+    }
+}
+
+/// The archive file, or its volumes of at most `split_size` bytes. A split ZIP is named
+/// `.z01`, `.z02`... and its last volume `.zip`, the volumes of the other formats `.001`, `.002`...
+pub struct Volumes {
+    /// Path of the archive, the volumes are named after it
+    path: String,
+    format: OutputFormat,
+    split_size: Option<u64>,
+    files: Vec<File>,
+    /// Offset in the archive of the first byte of each volume
+    starts: Vec<u64>,
+    position: u64,
+    len: u64,
+    encryption: Option<Encryption>,
+}
+
+impl Volumes {
+    fn new(path: &str, format: OutputFormat, split_size: Option<u64>, recipients: Option<&[String]>) -> io::Result<Self> {
+        let encryption = match recipients {
+            Some(recipients) => Some(Encryption {
+                recipients: recipients.to_vec(),
+                committed: 0,
+                spool: Spool::create(&format!("{}.spool", path))?,
+                spool_start: 0,
+                stream: None,
+            }),
+            None => None,
+        };
+        let mut volumes = Volumes {
+            path: path.to_string(),
+            format,
+            split_size,
+            files: Vec::new(),
+            starts: Vec::new(),
+            position: 0,
+            len: 0,
+            encryption,
+        };
+        volumes.new_volume()?;
+        Ok(volumes)
+    }
+
+    pub fn is_split(&self) -> bool {
+        self.split_size.is_some()
+    }
+
+    /// Size of the archive
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+
+    fn volume_path(&self, index: usize) -> String {
+        match (self.split_size, self.format) {
+            (None, _) => self.path.clone(),
+            (Some(_), OutputFormat::Zip) => {
+                format!("{}.z{:02}", self.path.trim_end_matches(".zip"), index + 1)
+            }
+            (Some(_), _) => format!("{}.{:03}", self.path, index + 1),
+        }
+    }
+
+    /// Path of a volume on disk, with the extension of the encrypted files
+    fn file_path(&self, path: &str) -> String {
+        match self.encryption {
+            Some(_) => format!("{}{}", path, ENCRYPTED_EXTENSION),
+            None => path.to_string(),
+        }
+    }
+
+    /// Bytes of a volume before the data of the archive
+    fn header_len(&self, index: usize) -> u64 {
+        if index == 0 && self.is_split() && self.format == OutputFormat::Zip {
+            SPLIT_ZIP_SIGNATURE.len() as u64
+        } else {
+            0
+        }
+    }
+
+    /// Start a volume at the end of the archive, an encrypted one is created with its first byte
+    fn new_volume(&mut self) -> io::Result<()> {
+        let index = self.starts.len();
+        if self.encryption.is_some() {
+            self.starts.push(self.len);
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.volume_path(index))?;
+        if self.header_len(index) > 0 {
+            file.write_all(&SPLIT_ZIP_SIGNATURE)?;
+        }
+        self.files.push(file);
+        self.starts.push(self.len);
+        Ok(())
+    }
+
+    /// Volume holding an offset of the archive, the offset in this volume and the bytes left in it
+    pub fn locate(&self, offset: u64) -> (usize, u64, u64) {
+        let index = self.starts.partition_point(|start| *start <= offset).saturating_sub(1);
+        let end = match (self.starts.get(index + 1), self.split_size) {
+            (Some(next_start), _) => *next_start,
+            (None, Some(split_size)) => self.starts[index] + split_size - self.header_len(index),
+            (None, None) => u64::MAX,
+        };
+        (index, offset - self.starts[index] + self.header_len(index), end.saturating_sub(offset))
+    }
+
+    /// Start a new volume if less than `size` bytes are left in the last one,
+    /// so a record of this size is not split
+    pub fn reserve(&mut self, size: u64) -> io::Result<()> {
+        let Some(split_size) = self.split_size else {
+            return Ok(());
+        };
+        let (index, _, left) = self.locate(self.len);
+        if left < size && size <= split_size && self.len > self.starts[index] {
+            self.new_volume()?;
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        // A seek after the end leaves zeros, 7z writes its header last
+        while self.len < offset {
+            let zeros = vec![0u8; (offset - self.len).min(64 * 1024) as usize];
+            self.write_all_at(self.len, &zeros)?;
+        }
+        let (mut index, mut physical, mut left) = self.locate(offset);
+        if left == 0 {
+            // The end of the archive is at the end of a full volume
+            self.new_volume()?;
+            (index, physical, left) = self.locate(offset);
+        }
+        let size = left.min(buf.len() as u64) as usize;
+        let size = match &mut self.encryption {
+            Some(encryption) => {
+                let spool_offset = encryption.spool_offset(offset)?;
+                encryption.spool.write_at(spool_offset, &buf[..size])?
+            }
+            None => {
+                let file = &mut self.files[index];
+                file.seek(SeekFrom::Start(physical))?;
+                file.write_all(&buf[..size])?;
+                size
+            }
+        };
+        self.len = self.len.max(offset + size as u64);
+        if self.format == OutputFormat::TarZst {
+            // A tar.zst archive is only appended, its bytes are encrypted right away
+            self.commit(self.len)?;
+        }
+        Ok(size)
+    }
+
+    fn write_all_at(&mut self, mut offset: u64, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let size = self.write_at(offset, buf)?;
+            offset += size as u64;
+            buf = &buf[size..];
+        }
+        Ok(())
+    }
+
+    fn read_exact_at(&mut self, mut offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        if offset + buf.len() as u64 > self.len {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "read after the end of the archive"));
+        }
+        let mut done = 0;
+        while done < buf.len() {
+            let (index, physical, left) = self.locate(offset);
+            let size = left.min((buf.len() - done) as u64) as usize;
+            match &mut self.encryption {
+                Some(encryption) => {
+                    let spool_offset = encryption.spool_offset(offset)?;
+                    encryption.spool.read_exact_at(spool_offset, &mut buf[done..done + size])?;
+                }
+                None => {
+                    let file = &mut self.files[index];
+                    file.seek(SeekFrom::Start(physical))?;
+                    file.read_exact(&mut buf[done..done + size])?;
+                }
+            }
+            done += size;
+            offset += size as u64;
+        }
+        Ok(())
+    }
+
+    fn read_u16(&mut self, offset: u64) -> io::Result<u16> {
+        let mut bytes = [0u8; 2];
+        self.read_exact_at(offset, &mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn read_u32(&mut self, offset: u64) -> io::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.read_exact_at(offset, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self, offset: u64) -> io::Result<u64> {
+        let mut bytes = [0u8; 8];
+        self.read_exact_at(offset, &mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Number of a volume in the 16 bits fields of the ZIP records
+    fn volume_number(&self, index: usize) -> io::Result<u16> {
+        u16::try_from(index)
+            .ok()
+            .filter(|number| *number != u16::MAX)
+            .ok_or_else(|| invalid_data("too many volumes for a split ZIP"))
+    }
+
+    /// Position of the local header offset in the Zip64 extra field of a central directory record
+    fn zip64_offset_field(&mut self, record: u64, name_len: u64, extra_len: u64) -> io::Result<u64> {
+        // The Zip64 field holds the sizes too when they don't fit in their 32 bits field
+        let mut skipped = 0;
+        for size_field in [record + 24, record + 20] {
+            if self.read_u32(size_field)? == u32::MAX {
+                skipped += 8;
+            }
+        }
+        let mut field = record + CENTRAL_DIRECTORY_RECORD_SIZE + name_len;
+        let extra_end = field + extra_len;
+        while field + 4 <= extra_end {
+            let id = self.read_u16(field)?;
+            let size = self.read_u16(field + 2)? as u64;
+            if id == ZIP64_EXTRA_FIELD_ID && skipped + 8 <= size {
+                return Ok(field + 4 + skipped);
+            }
+            field += 4 + size;
+        }
+        Err(invalid_data("local header offset missing in the Zip64 extra field"))
+    }
+
+    /// Make the central directory of a ZIP written as one stream the one of a split ZIP:
+    /// the local headers and the central directory are located by volume and offset in it
+    fn split_zip_directory(&mut self) -> io::Result<()> {
+        if self.len < END_OF_CENTRAL_DIRECTORY_SIZE {
+            return Err(invalid_data("end of central directory not found"));
+        }
+        let end_record = self.len - END_OF_CENTRAL_DIRECTORY_SIZE;
+        if self.read_u32(end_record)? != END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+            return Err(invalid_data("end of central directory not found"));
+        }
+        let mut zip64_end_record = None;
+        if end_record >= ZIP64_LOCATOR_SIZE && self.read_u32(end_record - ZIP64_LOCATOR_SIZE)? == ZIP64_LOCATOR_SIGNATURE {
+            let offset = self.read_u64(end_record - ZIP64_LOCATOR_SIZE + 8)?;
+            if self.read_u32(offset)? != ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE {
+                return Err(invalid_data("Zip64 end of central directory not found"));
+            }
+            zip64_end_record = Some(offset);
+        }
+        let (entries, directory) = match zip64_end_record {
+            Some(offset) => (self.read_u64(offset + 32)?, self.read_u64(offset + 48)?),
+            None => (self.read_u16(end_record + 10)? as u64, self.read_u32(end_record + 16)? as u64),
+        };
+
+        let last_index = self.starts.len() - 1;
+        let last_volume = self.volume_number(last_index)?;
+        let mut entries_in_last_volume: u64 = 0;
+        let mut record = directory;
+        for _ in 0..entries {
+            if self.read_u32(record)? != CENTRAL_DIRECTORY_SIGNATURE {
+                return Err(invalid_data("invalid central directory record"));
+            }
+            let name_len = self.read_u16(record + 28)? as u64;
+            let extra_len = self.read_u16(record + 30)? as u64;
+            let comment_len = self.read_u16(record + 32)? as u64;
+
+            let (local_header, zip64_field) = match self.read_u32(record + 42)? {
+                u32::MAX => {
+                    let field = self.zip64_offset_field(record, name_len, extra_len)?;
+                    (self.read_u64(field)?, Some(field))
+                }
+                offset => (offset as u64, None),
+            };
+            let (index, relative, _) = self.locate(local_header);
+            let volume = self.volume_number(index)?;
+            self.write_all_at(record + 34, &volume.to_le_bytes())?;
+            match zip64_field {
+                Some(field) => self.write_all_at(field, &relative.to_le_bytes())?,
+                None => self.write_all_at(record + 42, &(relative as u32).to_le_bytes())?,
+            }
+            if self.locate(record).0 == last_index {
+                entries_in_last_volume += 1;
+            }
+            record += CENTRAL_DIRECTORY_RECORD_SIZE + name_len + extra_len + comment_len;
+        }
+
+        let (directory_index, directory_relative, _) = self.locate(directory);
+        let directory_volume = self.volume_number(directory_index)?;
+        self.write_all_at(end_record + 4, &last_volume.to_le_bytes())?;
+        self.write_all_at(end_record + 6, &directory_volume.to_le_bytes())?;
+        // The values too large for the end record are only in the Zip64 one
+        if self.read_u16(end_record + 8)? != u16::MAX {
+            self.write_all_at(end_record + 8, &(entries_in_last_volume as u16).to_le_bytes())?;
+        }
+        if self.read_u32(end_record + 16)? != u32::MAX {
+            self.write_all_at(end_record + 16, &(directory_relative as u32).to_le_bytes())?;
+        }
+        if let Some(offset) = zip64_end_record {
+            self.write_all_at(offset + 16, &(last_volume as u32).to_le_bytes())?;
+            self.write_all_at(offset + 20, &(directory_volume as u32).to_le_bytes())?;
+            self.write_all_at(offset + 24, &entries_in_last_volume.to_le_bytes())?;
+            self.write_all_at(offset + 48, &directory_relative.to_le_bytes())?;
+            let (zip64_index, zip64_relative, _) = self.locate(offset);
+            let locator = end_record - ZIP64_LOCATOR_SIZE;
+            self.write_all_at(locator + 4, &(self.volume_number(zip64_index)? as u32).to_le_bytes())?;
+            self.write_all_at(locator + 8, &zip64_relative.to_le_bytes())?;
+            self.write_all_at(locator + 16, &(self.starts.len() as u32).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Encrypt the bytes of the archive before `offset` in their volumes, they can't be rewritten after
+    pub fn commit(&mut self, offset: u64) -> io::Result<()> {
+        let offset = offset.min(self.len);
+        let mut buffer = Vec::new();
+        while let Some(committed) = self.encryption.as_ref().map(|e| e.committed).filter(|c| *c < offset) {
+            let (index, _, left) = self.locate(committed);
+            let path = self.file_path(&self.volume_path(index));
+            let header_len = self.header_len(index) as usize;
+            let Some(encryption) = self.encryption.as_mut() else {
+                break;
+            };
+            buffer.resize(left.min(offset - committed).min(CHUNK_SIZE as u64) as usize, 0);
+            let size = encryption.spool.read_at(committed - encryption.spool_start, &mut buffer)?;
+            if size == 0 {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "bytes of the archive missing in the spool"));
+            }
+            encryption
+                .stream(index, &path, &SPLIT_ZIP_SIGNATURE[..header_len])?
+                .write_all(&buffer[..size])?;
+            encryption.committed += size as u64;
+        }
+        match &mut self.encryption {
+            Some(encryption) => encryption.restart_spool(self.len),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the volumes and return their paths, the central directory of a split ZIP is rewritten first
+    pub fn finish(&mut self) -> io::Result<Vec<String>> {
+        if self.is_split() && self.format == OutputFormat::Zip {
+            self.split_zip_directory()?;
+        }
+        self.commit(self.len)?;
+        if let Some((_, stream)) = self.encryption.as_mut().and_then(|encryption| encryption.stream.take()) {
+            stream.finish()?.flush()?;
+        }
+        for file in &mut self.files {
+            file.flush()?;
+        }
+        self.files.clear();
+        let count = self.starts.len();
+        let mut paths: Vec<String> = (0..count)
+            .map(|index| self.file_path(&self.volume_path(index)))
+            .collect();
+        if self.is_split() && self.format == OutputFormat::Zip {
+            if let Some(last_path) = paths.last_mut() {
+                let path = self.file_path(&self.path);
+                fs::rename(&*last_path, &path)?;
+                *last_path = path;
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Shared writer of the volumes, the archive keeps it to locate the entries
+#[derive(Clone)]
+pub struct VolumeWriter(Arc<Mutex<Volumes>>);
+
+impl VolumeWriter {
+    /// Create the volumes, encrypted to `recipients` if set
+    pub fn create(
+        path: &str,
+        format: OutputFormat,
+        split_size: Option<u64>,
+        recipients: Option<&[String]>,
+    ) -> io::Result<Self> {
+        Ok(VolumeWriter(Arc::new(Mutex::new(Volumes::new(path, format, split_size, recipients)?))))
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, Volumes> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut volumes = self.lock();
+        let position = volumes.position;
+        let size = volumes.write_at(position, buf)?;
+        volumes.position += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().files.iter_mut().try_for_each(|file| file.flush())
+    }
+}
+
+impl Seek for VolumeWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut volumes = self.lock();
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => volumes.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => volumes.position.checked_add_signed(delta),
+        }
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid seek in the archive"))?;
+        volumes.position = position;
+        Ok(position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aralez-volumes-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn u16_at(data: &[u8], offset: u64) -> u16 {
+        let offset = offset as usize;
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: u64) -> u32 {
+        let offset = offset as usize;
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: u64) -> u64 {
+        let offset = offset as usize;
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Volumes of a split ZIP as written on the disk
+    struct SplitZip {
+        volumes: Vec<Vec<u8>>,
+    }
+
+    impl SplitZip {
+        fn read(paths: &[String]) -> Self {
+            SplitZip { volumes: paths.iter().map(|path| fs::read(path).unwrap()).collect() }
+        }
+
+        /// The archive without the signature of the first volume
+        fn data(&self) -> Vec<u8> {
+            self.volumes.concat()[SPLIT_ZIP_SIGNATURE.len()..].to_vec()
+        }
+
+        /// Offset in the archive of an offset in a volume
+        fn offset(&self, volume: u32, relative: u64) -> u64 {
+            let start: usize = self.volumes[..volume as usize].iter().map(Vec::len).sum();
+            start as u64 + relative - SPLIT_ZIP_SIGNATURE.len() as u64
+        }
+
+        /// Check that an entry of the central directory points at its local header
+        fn assert_local_header(&self, volume: u32, relative: u64, name: &str) {
+            let data = &self.volumes[volume as usize];
+            assert_eq!(u32_at(data, relative), LOCAL_HEADER_SIGNATURE, "no local header of `{}`", name);
+            let name_len = u16_at(data, relative + 26) as usize;
+            let start = relative as usize + 30;
+            assert_eq!(&data[start..start + name_len], name.as_bytes());
+        }
+    }
+
+    #[test]
+    fn names_the_volumes() {
+        let dir = test_dir("names");
+        let zip_path = dir.join("out.zip").to_string_lossy().to_string();
+        let mut writer = VolumeWriter::create(&zip_path, OutputFormat::Zip, None, None).unwrap();
+        writer.write_all(b"data").unwrap();
+        assert_eq!(writer.lock().finish().unwrap(), [zip_path.as_str()]);
+
+        let tar_path = dir.join("out.tar.zst").to_string_lossy().to_string();
+        let mut writer = VolumeWriter::create(&tar_path, OutputFormat::TarZst, Some(4), None).unwrap();
+        writer.write_all(b"0123456789").unwrap();
+        let paths = writer.lock().finish().unwrap();
+        let contents: Vec<Vec<u8>> = paths.iter().map(|path| fs::read(path).unwrap()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(paths, [1, 2, 3].map(|number| format!("{}.00{}", tar_path, number)));
+        assert_eq!(contents, [b"0123".to_vec(), b"4567".to_vec(), b"89".to_vec()]);
+    }
+
+    #[test]
+    fn fills_a_seek_after_the_end_with_zeros() {
+        let dir = test_dir("seek");
+        let path = dir.join("out.7z").to_string_lossy().to_string();
+        let mut writer = VolumeWriter::create(&path, OutputFormat::SevenZ, Some(8), None).unwrap();
+        writer.seek(SeekFrom::Start(6)).unwrap();
+        writer.write_all(b"data").unwrap();
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        writer.write_all(b"head").unwrap();
+        let paths = writer.lock().finish().unwrap();
+        let contents: Vec<Vec<u8>> = paths.iter().map(|path| fs::read(path).unwrap()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(contents, [b"head\0\0da".to_vec(), b"ta".to_vec()]);
+    }
+
+    #[test]
+    fn locates_the_offsets_after_the_signature_of_the_first_volume() {
+        let dir = test_dir("locate");
+        let path = dir.join("out.zip").to_string_lossy().to_string();
+        let mut writer = VolumeWriter::create(&path, OutputFormat::Zip, Some(16), None).unwrap();
+        writer.write_all(&[0u8; 40]).unwrap();
+        let mut volumes = writer.lock();
+        // 12 bytes of data in the first volume after the signature, 16 in the next ones
+        assert_eq!(volumes.locate(0), (0, 4, 12));
+        assert_eq!(volumes.locate(11), (0, 15, 1));
+        assert_eq!(volumes.locate(12), (1, 0, 16));
+        assert_eq!(volumes.locate(39), (2, 11, 5));
+        volumes.reserve(8).unwrap();
+        assert_eq!(volumes.locate(40), (3, 0, 16));
+        let volume_paths: Vec<String> = (0..4).map(|index| volumes.volume_path(index)).collect();
+        drop(volumes);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(volume_paths[0], dir.join("out.z01").to_string_lossy());
+        assert_eq!(volume_paths[3], dir.join("out.z04").to_string_lossy());
+    }
+
+    /// Write a ZIP of three stored entries in volumes of 100 bytes, encrypting the entries
+    /// before the one started as the archive does
+    fn write_split_zip(path: &str, recipients: Option<&[String]>) -> Vec<String> {
+        let writer = VolumeWriter::create(path, OutputFormat::Zip, Some(100), recipients).unwrap();
+        let mut zip = ZipWriter::new(writer.clone());
+        let options: FileOptions<'static, ()> = FileOptions::default().compression_method(CompressionMethod::Stored);
+        for name in ZIP_ENTRIES {
+            let offset = writer.lock().size();
+            zip.start_file(name, options).unwrap();
+            writer.lock().commit(offset).unwrap();
+            zip.write_all(&ZIP_ENTRY_DATA.repeat(6)).unwrap();
+        }
+        zip.finish().unwrap();
+        let paths = writer.lock().finish().unwrap();
+        paths
+    }
+
+    const ZIP_ENTRIES: [&str; 3] = ["first.txt", "second.txt", "third.txt"];
+    const ZIP_ENTRY_DATA: &[u8; 10] = b"collected ";
+
+    fn check_split_zip(split: &SplitZip) {
+        assert!(split.volumes.len() > 2);
+        assert_eq!(split.volumes[0][..4], SPLIT_ZIP_SIGNATURE);
+        assert!(split.volumes.iter().all(|volume| volume.len() <= 100));
+
+        let data = split.data();
+        let end_record = data.len() as u64 - END_OF_CENTRAL_DIRECTORY_SIZE;
+        assert_eq!(u32_at(&data, end_record), END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+        let last_volume = split.volumes.len() as u16 - 1;
+        assert_eq!(u16_at(&data, end_record + 4), last_volume);
+        assert_eq!(u16_at(&data, end_record + 10), ZIP_ENTRIES.len() as u16);
+
+        let directory_volume = u16_at(&data, end_record + 6) as u32;
+        let mut record = split.offset(directory_volume, u32_at(&data, end_record + 16) as u64);
+        let mut entries_in_last_volume = 0;
+        let mut volumes_of_entries = Vec::new();
+        for name in ZIP_ENTRIES {
+            assert_eq!(u32_at(&data, record), CENTRAL_DIRECTORY_SIGNATURE);
+            let volume = u16_at(&data, record + 34) as u32;
+            split.assert_local_header(volume, u32_at(&data, record + 42) as u64, name);
+            volumes_of_entries.push(volume);
+            if record >= split.offset(last_volume as u32, 0) {
+                entries_in_last_volume += 1;
+            }
+            record += CENTRAL_DIRECTORY_RECORD_SIZE
+                + u16_at(&data, record + 28) as u64
+                + u16_at(&data, record + 30) as u64
+                + u16_at(&data, record + 32) as u64;
+        }
+        assert_eq!(volumes_of_entries, [0, 1, 2]);
+        assert_eq!(u16_at(&data, end_record + 8), entries_in_last_volume);
+    }
+
+    #[test]
+    fn rewrites_the_central_directory_of_a_split_zip() {
+        let dir = test_dir("zip");
+        let path = dir.join("out.zip").to_string_lossy().to_string();
+        let paths = write_split_zip(&path, None);
+        let split = SplitZip::read(&paths);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths.last().unwrap(), &path);
+        assert_eq!(paths[0], dir.join("out.z01").to_string_lossy());
+        check_split_zip(&split);
+    }
+
+    #[test]
+    fn encrypts_the_volumes_as_they_are_written() {
+        let dir = test_dir("encrypted");
+        let path = dir.join("out.zip").to_string_lossy().to_string();
+        let identity = age::x25519::Identity::generate();
+        let paths = write_split_zip(&path, Some(&[identity.to_public().to_string()]));
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        let encrypted: Vec<Vec<u8>> = paths.iter().map(|path| fs::read(path).unwrap()).collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(paths.last().unwrap(), &format!("{}.age", path));
+        assert_eq!(paths[0], format!("{}.age", dir.join("out.z01").to_string_lossy()));
+        assert_eq!(files.len(), paths.len());
+        assert!(files.iter().all(|file| file.ends_with(".age")));
+        assert!(encrypted.iter().all(|volume| !volume.windows(9).any(|window| window == b"collected")));
+
+        let volumes = encrypted
+            .iter()
+            .map(|volume| {
+                let age::Decryptor::Recipients(decryptor) = age::Decryptor::new(volume.as_slice()).unwrap() else {
+                    panic!("volume not encrypted to recipients");
+                };
+                let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).unwrap();
+                let mut plaintext = Vec::new();
+                reader.read_to_end(&mut plaintext).unwrap();
+                plaintext
+            })
+            .collect();
+        check_split_zip(&SplitZip { volumes });
+    }
+
+    /// Local header of a stored entry
+    fn local_header(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&[0u8; 14]);
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(data);
+        header
+    }
+
+    /// Central directory record of a stored entry with its local header offset in a Zip64 extra field
+    fn zip64_directory_record(name: &str, size: u32, local_header: u64) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.extend_from_slice(&CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        record.extend_from_slice(&[0u8; 16]);
+        record.extend_from_slice(&size.to_le_bytes());
+        record.extend_from_slice(&size.to_le_bytes());
+        record.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        record.extend_from_slice(&12u16.to_le_bytes());
+        record.extend_from_slice(&[0u8; 10]);
+        record.extend_from_slice(&u32::MAX.to_le_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(&ZIP64_EXTRA_FIELD_ID.to_le_bytes());
+        record.extend_from_slice(&8u16.to_le_bytes());
+        record.extend_from_slice(&local_header.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn rewrites_the_zip64_records_of_a_split_zip() {
+        let dir = test_dir("zip64");
+        let path = dir.join("out.zip").to_string_lossy().to_string();
+        let mut writer = VolumeWriter::create(&path, OutputFormat::Zip, Some(64), None).unwrap();
+
+        let names = ["a", "b"];
+        let mut offsets = Vec::new();
+        let mut archive = Vec::new();
+        for name in names {
+            offsets.push(archive.len() as u64);
+            archive.extend(local_header(name, &[b'x'; 40]));
+        }
+        let directory = archive.len() as u64;
+        for (name, offset) in names.iter().zip(&offsets) {
+            archive.extend(zip64_directory_record(name, 40, *offset));
+        }
+        let directory_size = archive.len() as u64 - directory;
+        let zip64_end_record = archive.len() as u64;
+        archive.extend_from_slice(&ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        archive.extend_from_slice(&44u64.to_le_bytes());
+        archive.extend_from_slice(&[0u8; 12]);
+        archive.extend_from_slice(&(names.len() as u64).to_le_bytes());
+        archive.extend_from_slice(&(names.len() as u64).to_le_bytes());
+        archive.extend_from_slice(&directory_size.to_le_bytes());
+        archive.extend_from_slice(&directory.to_le_bytes());
+        archive.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+        archive.extend_from_slice(&0u32.to_le_bytes());
+        archive.extend_from_slice(&zip64_end_record.to_le_bytes());
+        archive.extend_from_slice(&1u32.to_le_bytes());
+        archive.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        archive.extend_from_slice(&[0u8; 4]);
+        archive.extend_from_slice(&[0xff; 12]);
+        archive.extend_from_slice(&0u16.to_le_bytes());
+        writer.write_all(&archive).unwrap();
+
+        let paths = writer.lock().finish().unwrap();
+        let split = SplitZip::read(&paths);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let data = split.data();
+        assert_eq!(data.len(), archive.len());
+        let last_volume = paths.len() as u32 - 1;
+        let end_record = data.len() as u64 - END_OF_CENTRAL_DIRECTORY_SIZE;
+        assert_eq!(u16_at(&data, end_record + 4) as u32, last_volume);
+        // The fields too small for the values stay in the Zip64 record
+        assert_eq!(u16_at(&data, end_record + 8), u16::MAX);
+        assert_eq!(u32_at(&data, end_record + 16), u32::MAX);
+
+        let locator = end_record - ZIP64_LOCATOR_SIZE;
+        assert_eq!(u32_at(&data, locator + 16), paths.len() as u32);
+        let zip64_record = split.offset(u32_at(&data, locator + 4), u64_at(&data, locator + 8));
+        assert_eq!(zip64_record, zip64_end_record);
+        assert_eq!(u32_at(&data, zip64_record + 16), last_volume);
+        let directory_volume = u32_at(&data, zip64_record + 20);
+        let mut record = split.offset(directory_volume, u64_at(&data, zip64_record + 48));
+        assert_eq!(record, directory);
+
+        let mut volumes_of_entries = Vec::new();
+        for name in names {
+            assert_eq!(u32_at(&data, record + 42), u32::MAX);
+            let volume = u16_at(&data, record + 34) as u32;
+            let relative = u64_at(&data, record + CENTRAL_DIRECTORY_RECORD_SIZE + name.len() as u64 + 4);
+            split.assert_local_header(volume, relative, name);
+            volumes_of_entries.push(volume);
+            record += CENTRAL_DIRECTORY_RECORD_SIZE + name.len() as u64 + 12;
+        }
+        assert_eq!(volumes_of_entries, [0, 1]);
+    }
+}
@@ -0,0 +1,160 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::archive::create_output;
+use crate::utils::format_size;
+use anyhow::Result;
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::Mutex;
+
+/// Name of the CSV report listing the files skipped by a budget
+pub const SKIPPED_FILES_REPORT: &str = "skipped_files.csv";
+
+/// Limits on the total size and the number of collected files, of a task or of the whole collection
+#[derive(Debug)]
+pub struct Budget {
+    scope: String,
+    max_total_size: Option<u64>,
+    max_files: Option<u64>,
+    /// Collected bytes and files
+    used: Mutex<(u64, u64)>,
+}
+
+impl Budget {
+    pub fn new(scope: &str, max_total_size: Option<u64>, max_files: Option<u64>) -> Self {
+        Budget {
+            scope: scope.to_string(),
+            max_total_size,
+            max_files,
+            used: Mutex::new((0, 0)),
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.max_total_size.is_some() || self.max_files.is_some()
+    }
+
+    /// Reserve room for a file, return the reason when it would exceed the budget
+    pub fn reserve(&self, size: u64) -> Result<(), String> {
+        let mut used = self.used.lock().unwrap();
+        if let Some(max_files) = self.max_files {
+            if used.1 >= max_files {
+                return Err(format!("{} max_files of {} reached", self.scope, max_files));
+            }
+        }
+        if let Some(max_total_size) = self.max_total_size {
+            if used.0.saturating_add(size) > max_total_size {
+                return Err(format!("{} max_total_size of {} reached", self.scope, format_size(max_total_size)));
+            }
+        }
+        used.0 += size;
+        used.1 += 1;
+        Ok(())
+    }
+
+    /// Give back the room of a file that was not saved
+    pub fn release(&self, size: u64) {
+        let mut used = self.used.lock().unwrap();
+        used.0 = used.0.saturating_sub(size);
+        used.1 = used.1.saturating_sub(1);
+    }
+}
+
+static GLOBAL_BUDGET: OnceCell<Budget> = OnceCell::new();
+
+/// Set the budget shared by every collect task, from the top level `max_total_size` and `max_files`
+pub fn set_global_budget(budget: Budget) {
+    let _ = GLOBAL_BUDGET.set(budget);
+}
+
+pub fn get_global_budget() -> Option<&'static Budget> {
+    GLOBAL_BUDGET.get().filter(|budget| budget.is_limited())
+}
+
+/// A file matching the rules but left out of the collection
+#[derive(Debug, Clone)]
+pub struct SkippedFile {
+    pub folder: String,
+    pub path: String,
+    pub size: u64,
+    pub reason: String,
+}
+
+static SKIPPED_FILES: Lazy<Mutex<Vec<SkippedFile>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub fn add_skipped_file(skipped_file: SkippedFile) {
+    SKIPPED_FILES.lock().unwrap().push(skipped_file);
+}
+
+/// Write the skipped files in the output folder, nothing is written if no file was skipped
+pub fn save_skipped_files(output_folder: &str) -> Result<()> {
+    let skipped_files = SKIPPED_FILES.lock().unwrap();
+    if skipped_files.is_empty() {
+        return Ok(());
+    }
+
+    let report_path = format!("{}/{}", output_folder, SKIPPED_FILES_REPORT);
+    let Some(report_file) = create_output(&report_path, None)? else {
+        return Err(anyhow::anyhow!("[ERROR] `{}` already exists", report_path));
+    };
+    let mut writer = csv::Writer::from_writer(report_file);
+    writer.write_record(["folder", "path", "size", "reason"])?;
+    for skipped_file in skipped_files.iter() {
+        writer.write_record([
+            skipped_file.folder.as_str(),
+            skipped_file.path.as_str(),
+            &skipped_file.size.to_string(),
+            skipped_file.reason.as_str(),
+        ])?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| anyhow::anyhow!("[ERROR] Problem to save `{}`: {}", report_path, e))?
+        .finish()?;
+    dprintln!(
+        "[WARN] {} files were skipped by a budget, see `{}`",
+        skipped_files.len(),
+        SKIPPED_FILES_REPORT
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_files_above_max_files() {
+        let budget = Budget::new("task", None, Some(2));
+        assert!(budget.reserve(u64::MAX).is_ok());
+        assert!(budget.reserve(0).is_ok());
+        assert_eq!(budget.reserve(0).unwrap_err(), "task max_files of 2 reached");
+    }
+
+    #[test]
+    fn refuses_files_above_max_total_size() {
+        let budget = Budget::new("global", Some(100), None);
+        assert!(budget.reserve(60).is_ok());
+        assert!(budget.reserve(50).unwrap_err().starts_with("global max_total_size of"));
+        assert!(budget.reserve(40).is_ok());
+        assert!(budget.reserve(1).is_err());
+        assert_eq!(*budget.used.lock().unwrap(), (100, 2));
+    }
+
+    #[test]
+    fn gives_back_the_room_of_a_released_file() {
+        let budget = Budget::new("task", Some(100), Some(1));
+        budget.reserve(80).unwrap();
+        assert!(budget.reserve(10).is_err());
+        budget.release(80);
+        assert!(budget.reserve(100).is_ok());
+        budget.release(100);
+        budget.release(100);
+        assert_eq!(*budget.used.lock().unwrap(), (0, 0));
+    }
+}
@@ -0,0 +1,146 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::resource::extract_resource;
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Prefix of a reference to a profile embedded in the resources of the binary (e.g. `embedded:server.yml`)
+const EMBEDDED_PREFIX: &str = "embedded:";
+
+/// Resolve the `extends` and `include` keys of a configuration into a single configuration.
+///
+/// The `extends` profile is the base, the `include` files are merged over it in order,
+/// then the configuration itself. Tasks are merged by name and their fields are overridden,
+/// except `entries` which are merged by entry name. A task or an entry set to `null` is removed,
+/// `disabled: true` disables an inherited task. Relative paths are relative to the including file.
+pub fn resolve_config(data: &str, base_dir: &Path) -> Result<Value> {
+    let value: Value = serde_yaml::from_str(data)?;
+    resolve(value, base_dir, &mut Vec::new())
+}
+
+fn resolve(value: Value, base_dir: &Path, stack: &mut Vec<String>) -> Result<Value> {
+    let mut mapping = match value {
+        Value::Mapping(mapping) => mapping,
+        other => return Ok(other),
+    };
+
+    let mut sources = Vec::new();
+    match mapping.remove("extends") {
+        Some(Value::String(reference)) => sources.push(reference),
+        Some(Value::Null) | None => {}
+        Some(_) => return Err(anyhow::anyhow!("[ERROR] Config: `extends` should be the name of a profile")),
+    }
+    match mapping.remove("include") {
+        Some(Value::String(reference)) => sources.push(reference),
+        Some(Value::Sequence(references)) => {
+            for reference in references {
+                match reference {
+                    Value::String(reference) => sources.push(reference),
+                    _ => return Err(anyhow::anyhow!("[ERROR] Config: `include` should be a list of file names")),
+                }
+            }
+        }
+        Some(Value::Null) | None => {}
+        Some(_) => return Err(anyhow::anyhow!("[ERROR] Config: `include` should be a list of file names")),
+    }
+
+    let mut resolved = Value::Mapping(Mapping::new());
+    for reference in sources {
+        let (data, id, source_dir) = load_source(&reference, base_dir)?;
+        if stack.contains(&id) {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Config: `{}` includes itself through {}",
+                reference,
+                stack.join(" -> ")
+            ));
+        }
+        let value: Value = serde_yaml::from_str(&data)
+            .map_err(|e| anyhow::anyhow!("[ERROR] Config: `{}`: {}", reference, e))?;
+        stack.push(id);
+        let value = resolve(value, &source_dir, stack)?;
+        stack.pop();
+        merge_config(&mut resolved, value);
+    }
+    merge_config(&mut resolved, Value::Mapping(mapping));
+
+    Ok(resolved)
+}
+
+/// Read an included configuration, return its content, an identifier to detect cycles and its folder
+fn load_source(reference: &str, base_dir: &Path) -> Result<(String, String, PathBuf)> {
+    if let Some(name) = reference.strip_prefix(EMBEDDED_PREFIX) {
+        let data = extract_resource(name)
+            .map_err(|e| anyhow::anyhow!("[ERROR] Config: embedded profile `{}` not found: {}", name, e))?;
+        return Ok((
+            String::from_utf8(data)?,
+            reference.to_lowercase(),
+            base_dir.to_path_buf(),
+        ));
+    }
+
+    let path = base_dir.join(reference);
+    let data = fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("[ERROR] Config: `{}` can't be read: {}", path.display(), e))?;
+    let id = fs::canonicalize(&path)
+        .unwrap_or_else(|_| path.clone())
+        .to_string_lossy()
+        .to_lowercase();
+    let source_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    Ok((data, id, source_dir))
+}
+
+/// Merge a configuration over another one
+fn merge_config(base: &mut Value, overlay: Value) {
+    let (Value::Mapping(base), Value::Mapping(overlay)) = (base, overlay) else {
+        return;
+    };
+    for (key, value) in overlay {
+        if key.as_str() == Some("tasks") {
+            if let (Some(Value::Mapping(base_tasks)), Value::Mapping(tasks)) = (base.get_mut("tasks"), &value) {
+                merge_by_name(base_tasks, tasks.clone(), merge_task);
+                continue;
+            }
+        }
+        base.insert(key, value);
+    }
+}
+
+/// Merge a task over an inherited one, field by field and `entries` by entry name
+fn merge_task(base: &mut Value, overlay: Value) {
+    let (Value::Mapping(base), Value::Mapping(overlay)) = (base, overlay) else {
+        return;
+    };
+    for (key, value) in overlay {
+        if key.as_str() == Some("entries") {
+            if let (Some(Value::Mapping(base_entries)), Value::Mapping(entries)) = (base.get_mut("entries"), &value) {
+                merge_by_name(base_entries, entries.clone(), |base, overlay| *base = overlay);
+                continue;
+            }
+        }
+        base.insert(key, value);
+    }
+}
+
+/// Merge the items of a mapping by name, an item set to `null` is removed
+fn merge_by_name(base: &mut Mapping, overlay: Mapping, merge: fn(&mut Value, Value)) {
+    for (name, value) in overlay {
+        if value.is_null() {
+            base.remove(&name);
+            continue;
+        }
+        match base.get_mut(&name) {
+            Some(existing) => merge(existing, value),
+            None => {
+                base.insert(name, value);
+            }
+        }
+    }
+}
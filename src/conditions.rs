@@ -0,0 +1,263 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::profiles::{list_profiles, ProfileList, CURRENT_VERSION_KEY};
+use crate::utils::replace_env_vars;
+use crate::walker::{find_entry, read_file, Volume};
+use globset::GlobBuilder;
+use nt_hive::Hive;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+
+/// Folder of the offline registry hives on the system volume
+const HIVES_FOLDER: &str = "\\Windows\\System32\\config";
+
+const HIVE_NAMES: [&str; 5] = ["SOFTWARE", "SYSTEM", "SAM", "SECURITY", "DEFAULT"];
+
+/// `when` clause of a task or an entry, every condition given should be true
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    /// A file or a folder that should exist on the volume (e.g. `\inetpub`)
+    pub path_exists: Option<String>,
+    /// A key or a value that should exist in an offline hive
+    pub registry: Option<RegistryCondition>,
+    /// Build number of Windows from the SOFTWARE hive, with an optional comparison (e.g. `>= 17763`)
+    pub os_build: Option<String>,
+    /// Glob on the computer name, case insensitive (e.g. `MAIL-*`)
+    pub hostname: Option<String>,
+}
+
+/// A key or a value of an offline hive
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RegistryCondition {
+    /// Name of the hive in `\Windows\System32\config` (e.g. `SOFTWARE`)
+    pub hive: String,
+    /// Path of the key in the hive
+    pub key: String,
+    /// Value that should exist in the key
+    pub value: Option<String>,
+    /// Expected data of the value, case insensitive
+    pub equals: Option<String>,
+}
+
+/// Facts of a volume read for the conditions and the profile variables, each hive is read once
+pub struct VolumeFacts<'a, V: Volume> {
+    volume: &'a mut V,
+    hives: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl<'a, V: Volume> VolumeFacts<'a, V> {
+    pub fn new(volume: &'a mut V) -> Self {
+        VolumeFacts {
+            volume,
+            hives: HashMap::new(),
+        }
+    }
+
+    fn path_exists(&mut self, path: &str) -> bool {
+        matches!(find_entry(&mut *self.volume, &replace_env_vars(path)), Ok(Some(_)))
+    }
+
+    fn hive(&mut self, name: &str) -> Option<&[u8]> {
+        let name = name.to_uppercase();
+        if !self.hives.contains_key(&name) {
+            let path = format!("{}\\{}", HIVES_FOLDER, name);
+            let data = match read_file(&mut *self.volume, &path) {
+                Ok(data) => data,
+                Err(e) => {
+                    dprintln!("[ERROR] Problem to read the hive `{}`: {}", path, e);
+                    None
+                }
+            };
+            self.hives.insert(name.clone(), data);
+        }
+        self.hives.get(&name).and_then(|data| data.as_deref())
+    }
+
+    /// Folders of the profiles stored on the volume. A live drive gets them from the running system,
+    /// an image from its own SOFTWARE hive, whose profiles on other volumes can't be located.
+    pub fn profiles(&mut self) -> Vec<String> {
+        if let Some((drive, profile_list)) = self.volume.live_profiles() {
+            return profile_list.on_drive(drive);
+        }
+        let Some(profile_list) = self.profile_list() else {
+            dprintln!("[WARN] No SOFTWARE hive on the volume, the profile variables are not expanded");
+            return Vec::new();
+        };
+        let profiles = profile_list.on_drive(profile_list.system_drive);
+        if profiles.len() < profile_list.profiles.len() {
+            dprintln!(
+                "[WARN] Only the profiles on the drive {}: of the image are collected",
+                profile_list.system_drive
+            );
+        }
+        profiles
+    }
+
+    /// Profiles listed in the SOFTWARE hive of the volume, `None` if the volume has no hive
+    pub fn profile_list(&mut self) -> Option<ProfileList> {
+        list_profiles(self.hive("SOFTWARE")?)
+            .map_err(|e| dprintln!("[ERROR] Problem to read the profiles of the SOFTWARE hive: {}", e))
+            .ok()
+    }
+
+    /// Data of a registry value as a string, `None` if the key or the value does not exist
+    fn registry_value(&mut self, hive: &str, key: &str, value: Option<&str>) -> Option<String> {
+        let hive = Hive::without_validation(self.hive(hive)?).ok()?;
+        let root_key_node = hive.root_key_node().ok()?;
+        let key_node = match key.trim_matches('\\') {
+            "" => root_key_node,
+            key => root_key_node.subpath(key)?.ok()?,
+        };
+        let value = match value {
+            Some(value) => key_node.value(value)?.ok()?,
+            None => return Some(String::new()),
+        };
+        value
+            .string_data()
+            .ok()
+            .or_else(|| value.dword_data().ok().map(|data| data.to_string()))
+            .or_else(|| value.qword_data().ok().map(|data| data.to_string()))
+    }
+
+    fn os_build(&mut self) -> Option<u32> {
+        self.registry_value("SOFTWARE", CURRENT_VERSION_KEY, Some("CurrentBuildNumber"))
+            .or_else(|| self.registry_value("SOFTWARE", CURRENT_VERSION_KEY, Some("CurrentBuild")))
+            .and_then(|build| build.trim().parse().ok())
+    }
+
+    /// Computer name from the SYSTEM hive, the name of the running system if the volume has none
+    fn hostname(&mut self) -> Option<String> {
+        let control_set = self
+            .registry_value("SYSTEM", "Select", Some("Current"))
+            .and_then(|current| current.parse::<u32>().ok())
+            .unwrap_or(1);
+        let key = format!("ControlSet{:03}\\Control\\ComputerName\\ComputerName", control_set);
+        self.registry_value("SYSTEM", &key, Some("ComputerName"))
+            .or_else(|| hostname::get().ok().and_then(|name| name.into_string().ok()))
+    }
+}
+
+/// Split a build comparison like `>= 17763` into its operator and its build number
+fn parse_build_comparison(value: &str) -> Result<(&str, u32), String> {
+    let value = value.trim();
+    let operator_end = value
+        .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+        .unwrap_or(value.len());
+    let (operator, build) = value.split_at(operator_end);
+    let operator = match operator {
+        "" | "=" | "==" => "==",
+        ">" | ">=" | "<" | "<=" | "!=" => operator,
+        _ => return Err(format!("invalid operator `{}` in os_build `{}`", operator, value)),
+    };
+    let build = build
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid build number in os_build `{}`", value))?;
+    Ok((operator, build))
+}
+
+impl Condition {
+    /// Check the values of the clause, return a message for the first invalid one
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(os_build) = &self.os_build {
+            parse_build_comparison(os_build)?;
+        }
+        if let Some(hostname) = &self.hostname {
+            GlobBuilder::new(hostname)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("invalid hostname pattern `{}`: {}", hostname, e))?;
+        }
+        if let Some(registry) = &self.registry {
+            if !HIVE_NAMES.contains(&registry.hive.to_uppercase().as_str()) {
+                return Err(format!(
+                    "unknown hive `{}`, expected one of {}",
+                    registry.hive,
+                    HIVE_NAMES.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the clause on a volume, return the reason when it is false
+    pub fn check<V: Volume>(&self, facts: &mut VolumeFacts<V>) -> Result<(), String> {
+        if let Some(path) = &self.path_exists {
+            if !facts.path_exists(path) {
+                return Err(format!("`{}` does not exist", path));
+            }
+        }
+
+        if let Some(registry) = &self.registry {
+            let data = facts.registry_value(&registry.hive, &registry.key, registry.value.as_deref());
+            let location = match &registry.value {
+                Some(value) => format!("{}\\{}\\{}", registry.hive, registry.key, value),
+                None => format!("{}\\{}", registry.hive, registry.key),
+            };
+            match (data, &registry.equals) {
+                (None, _) => return Err(format!("registry `{}` does not exist", location)),
+                (Some(data), Some(expected)) if !data.eq_ignore_ascii_case(expected) => {
+                    return Err(format!("registry `{}` is `{}` and not `{}`", location, data, expected))
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(os_build) = &self.os_build {
+            let (operator, expected) = parse_build_comparison(os_build)?;
+            let build = facts
+                .os_build()
+                .ok_or_else(|| "the OS build is unknown".to_string())?;
+            let matches = match operator {
+                ">" => build > expected,
+                ">=" => build >= expected,
+                "<" => build < expected,
+                "<=" => build <= expected,
+                "!=" => build != expected,
+                _ => build == expected,
+            };
+            if !matches {
+                return Err(format!("OS build {} does not match `{}`", build, os_build));
+            }
+        }
+
+        if let Some(pattern) = &self.hostname {
+            let matcher = GlobBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| e.to_string())?
+                .compile_matcher();
+            let hostname = facts
+                .hostname()
+                .ok_or_else(|| "the hostname is unknown".to_string())?;
+            if !matcher.is_match(&hostname) {
+                return Err(format!("hostname `{}` does not match `{}`", hostname, pattern));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Deserialize a `when` clause and check its values
+pub fn deserialize_condition<'de, D>(deserializer: D) -> Result<Option<Condition>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let condition = Option::<Condition>::deserialize(deserializer)?;
+    if let Some(condition) = &condition {
+        condition
+            .validate()
+            .map_err(|e| serde::de::Error::custom(format!("[ERROR] Config: `when`: {}", e)))?;
+    }
+    Ok(condition)
+}
@@ -6,22 +6,31 @@
 // Author(s): Areg Baghinyan
 //
 
+use crate::archive::create_output;
+use crate::compose::resolve_config;
+use crate::conditions::{deserialize_condition, Condition};
+use crate::encryption::parse_recipients;
+use crate::filters::{compile_excludes, compile_regexes, parse_time_bound, EntryFilters, Signature, TimeWindow, SIGNATURE_NAMES};
+use crate::matcher::validate_objects;
+use crate::profiles::expand_with_placeholder;
 use crate::resource::extract_resource;
-use crate::utils::{remove_trailing_slash, replace_env_vars};
+use crate::utils::{format_size, parse_size, replace_env_vars};
 use anyhow::Result;
 use chrono::prelude::*;
 use hostname::get;
 use indexmap::IndexMap;
 use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
 use std::collections::HashSet;
-use std::fs::{create_dir_all, File};
+use std::fs::File;
 use std::io::{Read, Write};
-use std::ops::{Deref, DerefMut};
+use std::ops::{Deref, DerefMut, RangeInclusive};
 use std::path::Path;
 use std::fmt;
 use once_cell::sync::Lazy;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{ArrayValidation, InstanceType, Schema, SchemaObject, StringValidation};
+use schemars::{schema_for, JsonSchema};
 use std::sync::Mutex;
 
 // Global static config
@@ -29,6 +38,15 @@ static CONFIG: Lazy<Mutex<Config>> = Lazy::new(|| Mutex::new(Config {
     output_filename: "default.log".to_string(), // Placeholder
     tasks: IndexMap::new(),
     max_size: None,
+    max_total_size: None,
+    max_files: None,
+    selection_order: None,
+    max_parallel_tasks: None,
+    output_staging: None,
+    output_format: None,
+    compression_level: None,
+    output_split_size: None,
+    output_recipients: None,
     version: None
 }));
 
@@ -43,25 +61,91 @@ pub fn get_config() -> Config {
     CONFIG.lock().unwrap().clone()
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// Configuration of the aralez collection
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Tasks by name, run in the order of their priority
     pub tasks: IndexMap<String, SectionConfig>,
+    /// Name of the output archive, with the `{{hostname}}` and `{{datetime}}` variables
     pub output_filename: String,
+    /// Default maximum size of a collected file
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
     pub max_size: Option<u64>,
+    /// Maximum size of the whole collection
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
+    pub max_total_size: Option<u64>,
+    /// Maximum number of files of the whole collection
+    pub max_files: Option<u64>,
+    /// Order of the files collected when a budget is set
+    pub selection_order: Option<SelectionOrder>,
+    /// Number of tasks running at the same time, 1 by default
+    pub max_parallel_tasks: Option<usize>,
+    /// Write the outputs in a folder compressed at the end, instead of streaming them in the archive.
+    /// Needs twice the disk space
+    pub output_staging: Option<bool>,
+    /// Format of the output archive, `zip` by default
+    pub output_format: Option<OutputFormat>,
+    /// Compression level of the `tar.zst` archive, from 1 to 22, 3 by default
+    #[schemars(range(min = 1, max = 22))]
+    pub compression_level: Option<i32>,
+    /// Split the archive in volumes of this size, with an index of the volume and the offset where each entry starts
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
+    pub output_split_size: Option<u64>,
+    /// Public keys the archive and its index are encrypted to as they are written: age recipients (`age1...`)
+    /// or SSH public keys (`ssh-ed25519`, `ssh-rsa`). Only their private keys can decrypt the output.
+    /// The outputs and the log are spooled encrypted, only the files an external tool writes itself in
+    /// `{{root_output_path}}` are in plaintext until the archive is closed.
+    /// A 7z archive is kept in an encrypted spool until it is finished, which needs twice the disk space
+    pub output_recipients: Option<Vec<String>>,
     pub version: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
-pub struct Entries(HashMap<String, Vec<SearchConfig>>);
+/// Entries of a task by name
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Entries(IndexMap<String, Vec<SearchConfig>>);
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// A collect or an execute task
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct SectionConfig {
+    /// Tasks with a lower priority run first
     pub priority: u8,
     pub r#type: TypeTasks,
+    /// Drive letter to collect, `*` for every drive
     pub drive: Option<String>,
+    /// Disk image to collect instead of a drive
+    pub image: Option<String>,
+    /// Folder of the output, with the `{{root_output_path}}` and `{{drive}}` variables
     pub output_folder: Option<String>,
+    /// Default maximum size of a file collected by the task
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
     pub max_size: Option<u64>,
+    /// Maximum size of the files collected by the task
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
+    pub max_total_size: Option<u64>,
+    /// Maximum number of files collected by the task
+    pub max_files: Option<u64>,
+    /// Order of the files collected when a budget is set
+    pub selection_order: Option<SelectionOrder>,
+    /// Drive letters left out when `drive` is `*`
     pub exclude_drives: Option<Vec<String>>,
+    /// Globs of the paths left out of every entry
+    pub exclude: Option<Vec<String>>,
+    /// Number of drives collected at the same time
+    pub parallel_devices: Option<usize>,
+    /// Conditions to run the task
+    #[serde(default, deserialize_with = "deserialize_condition")]
+    pub when: Option<Condition>,
+    /// Tasks that should be done before this one starts
+    pub depends_on: Option<Vec<String>>,
+    /// Run the task alone, no other task runs at the same time
+    pub exclusive: Option<bool>,
     pub entries: Entries,
     pub disabled: Option<bool>,
 }
@@ -69,6 +153,7 @@ pub struct SectionConfig {
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeConfig {
     Glob,
+    Regex,
 }
 
 #[derive(PartialEq)]
@@ -80,7 +165,7 @@ pub enum ExecType {
 // Implement IntoIterator for `&Entries`
 impl<'a> IntoIterator for &'a Entries {
     type Item = (&'a String, &'a Vec<SearchConfig>);
-    type IntoIter = std::collections::hash_map::Iter<'a, String, Vec<SearchConfig>>;
+    type IntoIter = indexmap::map::Iter<'a, String, Vec<SearchConfig>>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.iter()
@@ -90,7 +175,7 @@ impl<'a> IntoIterator for &'a Entries {
 // Implement IntoIterator for consuming `Entries`
 impl IntoIterator for Entries {
     type Item = (String, Vec<SearchConfig>);
-    type IntoIter = std::collections::hash_map::IntoIter<String, Vec<SearchConfig>>;
+    type IntoIter = indexmap::map::IntoIter<String, Vec<SearchConfig>>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.0.into_iter()
@@ -98,7 +183,7 @@ impl IntoIterator for Entries {
 }
 
 impl Deref for Entries {
-    type Target = HashMap<String, Vec<SearchConfig>>;
+    type Target = IndexMap<String, Vec<SearchConfig>>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -129,7 +214,7 @@ impl<'de> Deserialize<'de> for Entries {
             where
                 M: MapAccess<'de>,
             {
-                let mut map = HashMap::new();
+                let mut map = IndexMap::new();
                 let mut entry_names = HashSet::new(); // Track duplicate keys
 
                 while let Some((key, configs)) = access.next_entry::<String, Vec<SearchConfig>>()? {
@@ -145,9 +230,9 @@ impl<'de> Deserialize<'de> for Entries {
                     for config in &configs {
                         // 1. Validate `root_path` if it's present
                         if let Some(root_path) = &config.root_path {
-                            if !root_path.starts_with("\\") && !root_path.starts_with('%') {
+                            if !root_path.starts_with("\\") && !root_path.starts_with('%') && !root_path.starts_with("{{") {
                                 return Err(de::Error::custom(format!(
-                                    "[ERROR] Config: root_path '{}' in entry '{}' should start with '\\\\' or '%'", 
+                                    "[ERROR] Config: root_path '{}' in entry '{}' should start with '\\\\', '%' or a profile variable like '{{{{user_profile}}}}'", 
                                     root_path, key
                                 )));
                             }
@@ -155,28 +240,45 @@ impl<'de> Deserialize<'de> for Entries {
 
                         // 2. If entry type is "collect", ensure `root_path` and `objects` are present
                         if let Some(type_config) = &config.r#type {
-                            if *type_config == TypeConfig::Glob {
-                                if config.root_path.is_none() || config.objects.is_none() {
-                                    return Err(de::Error::custom(format!(
-                                        "[ERROR] Config: Entry '{}' with type 'collect' must have `root_path` and `objects`", 
-                                        key
-                                    )));
-                                }
+                            if (*type_config == TypeConfig::Glob || *type_config == TypeConfig::Regex)
+                                && (config.root_path.is_none() || config.objects.is_none())
+                            {
+                                return Err(de::Error::custom(format!(
+                                    "[ERROR] Config: Entry '{}' with type 'collect' must have `root_path` and `objects`", 
+                                    key
+                                )));
                             }
                         }
 
                         // Additional validations for other fields, e.g., `max_size`, `encrypt`
-                        if let Some(max_size) = config.max_size {
-                            if max_size <= 0 {
-                                return Err(de::Error::custom(
-                                    "[ERROR] Config: `max_size` should be greater than zero",
-                                ));
+                        if config.max_size == Some(0) {
+                            return Err(de::Error::custom(format!(
+                                "[ERROR] Config: Entry '{}': `max_size` is 0, nothing would be collected; remove it to collect files of any size",
+                                key
+                            )));
+                        }
+
+                        // Objects of glob entries should be valid globs
+                        if !config.is_regex() {
+                            if let Err(e) = validate_objects(config) {
+                                return Err(de::Error::custom(format!(
+                                    "[ERROR] Config: Entry '{}': {}",
+                                    key, e
+                                )));
                             }
                         }
 
+                        // Time window bounds should be valid dates or relative times, and signatures known
+                        if let Err(e) = config.get_filters(&expand_with_placeholder(&config.get_expanded_root_path())) {
+                            return Err(de::Error::custom(format!(
+                                "[ERROR] Config: Entry '{}': {}",
+                                key, e
+                            )));
+                        }
+
                         // encryp shouldn't be empty
                         if let Some(password) = &config.encrypt {
-                            if password.is_empty() || *password == "".to_string() {
+                            if password.is_empty() {
                                 return Err(de::Error::custom(
                                     "[ERROR] Config: `encrypt` should be empty",
                                 ));
@@ -195,6 +297,9 @@ impl<'de> Deserialize<'de> for Entries {
     }
 }
 
+/// Names of the entry types, shared by the deserializer and the schema
+const TYPE_CONFIG_NAMES: [&str; 2] = ["glob", "regex"];
+
 impl Serialize for TypeConfig {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -202,6 +307,7 @@ impl Serialize for TypeConfig {
     {
         match *self {
             TypeConfig::Glob => serializer.serialize_str("glob"),
+            TypeConfig::Regex => serializer.serialize_str("regex"),
         }
     }
 }
@@ -217,7 +323,7 @@ impl<'de> Deserialize<'de> for TypeConfig {
             type Value = TypeConfig;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a string containing 'glob'")
+                formatter.write_str("a string containing 'glob' or 'regex'")
             }
 
             fn visit_str<E>(self, value: &str) -> Result<TypeConfig, E>
@@ -226,7 +332,8 @@ impl<'de> Deserialize<'de> for TypeConfig {
             {
                 match value {
                     "glob" => Ok(TypeConfig::Glob),
-                    _ => Err(de::Error::unknown_variant(value, &["glob"])),
+                    "regex" => Ok(TypeConfig::Regex),
+                    _ => Err(de::Error::unknown_variant(value, &TYPE_CONFIG_NAMES)),
                 }
             }
         }
@@ -241,6 +348,9 @@ pub enum TypeTasks {
     Collect,
 }
 
+/// Names of the task types, shared by the deserializer and the schema
+const TYPE_TASKS_NAMES: [&str; 2] = ["execute", "collect"];
+
 impl Serialize for TypeTasks {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -274,7 +384,7 @@ impl<'de> Deserialize<'de> for TypeTasks {
                 match value {
                     "execute" => Ok(TypeTasks::Execute),
                     "collect" => Ok(TypeTasks::Collect),
-                    _ => Err(de::Error::unknown_variant(value, &["execute", "collect"])),
+                    _ => Err(de::Error::unknown_variant(value, &TYPE_TASKS_NAMES)),
                 }
             }
         }
@@ -283,6 +393,206 @@ impl<'de> Deserialize<'de> for TypeTasks {
     }
 }
 
+/// Deserialize a size given in bytes or with a unit (`10MB`, `5 MiB`, `1G`)
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct SizeVisitor;
+
+    impl<'de> Visitor<'de> for SizeVisitor {
+        type Value = Option<u64>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a size in bytes or a string like '10MB', '5 MiB' or '1G'")
+        }
+
+        fn visit_none<E>(self) -> Result<Option<u64>, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_unit<E>(self) -> Result<Option<u64>, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Option<u64>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_any(SizeVisitor)
+        }
+
+        fn visit_u64<E>(self, value: u64) -> Result<Option<u64>, E>
+        where
+            E: de::Error,
+        {
+            Ok(Some(value))
+        }
+
+        fn visit_i64<E>(self, value: i64) -> Result<Option<u64>, E>
+        where
+            E: de::Error,
+        {
+            u64::try_from(value)
+                .map(Some)
+                .map_err(|_| de::Error::custom(format!("[ERROR] Config: invalid size `{}`", value)))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Option<u64>, E>
+        where
+            E: de::Error,
+        {
+            parse_size(value)
+                .map(Some)
+                .map_err(|e| de::Error::custom(format!("[ERROR] Config: {}", e)))
+        }
+    }
+
+    deserializer.deserialize_option(SizeVisitor)
+}
+
+/// Serialize a size in a readable form, e.g. `10 MiB`
+fn serialize_size<S>(size: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match size {
+        Some(size) => serializer.serialize_str(&format_size(*size)),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Order in which the matching files are collected when a budget is set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionOrder {
+    Newest,
+    Smallest,
+    EntryOrder,
+}
+
+/// Names of the selection orders, shared by the deserializer and the schema
+const SELECTION_ORDER_NAMES: [&str; 3] = ["newest", "smallest", "entry_order"];
+
+impl Serialize for SelectionOrder {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *self {
+            SelectionOrder::Newest => serializer.serialize_str("newest"),
+            SelectionOrder::Smallest => serializer.serialize_str("smallest"),
+            SelectionOrder::EntryOrder => serializer.serialize_str("entry_order"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SelectionOrder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SelectionOrderVisitor;
+
+        impl<'de> Visitor<'de> for SelectionOrderVisitor {
+            type Value = SelectionOrder;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string containing 'newest', 'smallest' or 'entry_order'")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<SelectionOrder, E>
+            where
+                E: de::Error,
+            {
+                match value {
+                    "newest" => Ok(SelectionOrder::Newest),
+                    "smallest" => Ok(SelectionOrder::Smallest),
+                    "entry_order" => Ok(SelectionOrder::EntryOrder),
+                    _ => Err(de::Error::unknown_variant(value, &SELECTION_ORDER_NAMES)),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(SelectionOrderVisitor)
+    }
+}
+
+/// Format of the output archive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// ZIP with Deflate
+    Zip,
+    /// tar compressed with multithreaded zstd
+    TarZst,
+    /// 7z with LZMA2
+    SevenZ,
+}
+
+/// Levels of `compression_level`, the ones of zstd
+pub const COMPRESSION_LEVELS: RangeInclusive<i32> = 1..=22;
+
+/// Smallest `output_split_size`, a volume holds at least the end of a ZIP central directory
+const MIN_SPLIT_SIZE: u64 = 64 * 1024;
+
+/// Names of the output formats, shared by the deserializer and the schema
+pub const OUTPUT_FORMAT_NAMES: [&str; 3] = ["zip", "tar.zst", "7z"];
+
+impl OutputFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Zip => "zip",
+            OutputFormat::TarZst => "tar.zst",
+            OutputFormat::SevenZ => "7z",
+        }
+    }
+}
+
+impl Serialize for OutputFormat {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct OutputFormatVisitor;
+
+        impl<'de> Visitor<'de> for OutputFormatVisitor {
+            type Value = OutputFormat;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string containing 'zip', 'tar.zst' or '7z'")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<OutputFormat, E>
+            where
+                E: de::Error,
+            {
+                match value {
+                    "zip" => Ok(OutputFormat::Zip),
+                    "tar.zst" => Ok(OutputFormat::TarZst),
+                    "7z" => Ok(OutputFormat::SevenZ),
+                    _ => Err(de::Error::unknown_variant(value, &OUTPUT_FORMAT_NAMES)),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(OutputFormatVisitor)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeExec {
     External,
@@ -290,16 +600,25 @@ pub enum TypeExec {
     System,
 }
 
+/// Names of the executor types, shared by the deserializer and the schema
+const TYPE_EXEC_NAMES: [&str; 3] = ["external", "internal", "system"];
+
+impl TypeExec {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TypeExec::External => "external",
+            TypeExec::Internal => "internal",
+            TypeExec::System => "system",
+        }
+    }
+}
+
 impl Serialize for TypeExec {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        match *self {
-            TypeExec::External => serializer.serialize_str("external"),
-            TypeExec::Internal => serializer.serialize_str("internal"),
-            TypeExec::System => serializer.serialize_str("system"),
-        }
+        serializer.serialize_str(self.as_str())
     }
 }
 
@@ -325,10 +644,7 @@ impl<'de> Deserialize<'de> for TypeExec {
                     "external" => Ok(TypeExec::External),
                     "internal" => Ok(TypeExec::Internal),
                     "system" => Ok(TypeExec::System),
-                    _ => Err(de::Error::unknown_variant(
-                        value,
-                        &["external", "internal", "system"],
-                    )),
+                    _ => Err(de::Error::unknown_variant(value, &TYPE_EXEC_NAMES)),
                 }
             }
         }
@@ -337,41 +653,264 @@ impl<'de> Deserialize<'de> for TypeExec {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+/// Files to collect, or a tool to execute
+#[derive(Deserialize, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct SearchConfig {
+    /// Folder of the objects, starting with `\`, an environment variable or a profile variable
     pub root_path: Option<String>,
+    /// Name of the tool to execute
     pub name: Option<String>,
+    /// File receiving the output of the tool
     pub output_file: Option<String>,
+    /// Arguments of the tool
     pub args: Option<Vec<String>>,
+    /// Globs, or regular expressions with `type: regex`, of the files under `root_path`
     pub objects: Option<Vec<String>>,
+    /// Password of the zip archive of the collected files
     pub encrypt: Option<String>,
     pub r#type: Option<TypeConfig>,
     pub exec_type: Option<TypeExec>,
+    /// Maximum size of a collected file
+    #[serde(default, deserialize_with = "deserialize_size", serialize_with = "serialize_size")]
+    #[schemars(schema_with = "size_schema")]
     max_size: Option<u64>,
+    /// Date or relative time (e.g. `7d`) of the oldest modification
+    pub modified_after: Option<String>,
+    /// Date or relative time of the newest modification
+    pub modified_before: Option<String>,
+    /// Date or relative time of the oldest creation
+    pub created_after: Option<String>,
+    /// Date or relative time of the newest creation
+    pub created_before: Option<String>,
+    /// Use the timestamps of the file name attribute for the time window
+    pub file_name_timestamps: Option<bool>,
+    /// Signatures the content of a file should start with
+    #[schemars(schema_with = "magic_schema")]
+    pub magic: Option<Vec<String>>,
+    /// Globs of the paths left out, relative to `root_path`
+    pub exclude: Option<Vec<String>>,
+    /// Depth of the folders searched under `root_path`
+    pub max_depth: Option<usize>,
+    /// Conditions to collect the entry
+    #[serde(default, deserialize_with = "deserialize_condition")]
+    pub when: Option<Condition>,
+}
+
+/// Schema of a string enum, from the names accepted by its deserializer
+fn names_schema(names: &[&str]) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::String.into()),
+        enum_values: Some(names.iter().map(|name| (*name).into()).collect()),
+        ..Default::default()
+    }
+    .into()
+}
+
+impl JsonSchema for TypeConfig {
+    fn schema_name() -> String {
+        "TypeConfig".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        names_schema(&TYPE_CONFIG_NAMES)
+    }
+}
+
+impl JsonSchema for TypeTasks {
+    fn schema_name() -> String {
+        "TypeTasks".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        names_schema(&TYPE_TASKS_NAMES)
+    }
+}
+
+impl JsonSchema for SelectionOrder {
+    fn schema_name() -> String {
+        "SelectionOrder".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        names_schema(&SELECTION_ORDER_NAMES)
+    }
+}
+
+impl JsonSchema for OutputFormat {
+    fn schema_name() -> String {
+        "OutputFormat".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        names_schema(&OUTPUT_FORMAT_NAMES)
+    }
+}
+
+impl JsonSchema for TypeExec {
+    fn schema_name() -> String {
+        "TypeExec".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        names_schema(&TYPE_EXEC_NAMES)
+    }
+}
+
+/// Schema of a size, in bytes or with a unit
+fn size_schema(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(vec![InstanceType::Integer, InstanceType::String, InstanceType::Null].into()),
+        string: Some(Box::new(StringValidation {
+            pattern: Some(r"^\s*[0-9]+(\.[0-9]+)?\s*[A-Za-z]*\s*$".to_string()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Schema of the magic signatures, from the names known by the filters
+fn magic_schema(_: &mut SchemaGenerator) -> Schema {
+    SchemaObject {
+        instance_type: Some(vec![InstanceType::Array, InstanceType::Null].into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(names_schema(&SIGNATURE_NAMES).into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+    .into()
 }
 
 impl Config {
     pub fn load_default() -> Result<Self, anyhow::Error> {
         // Embed the YAML content directly into the binary
         let yaml_data = include_str!("../config/.config.yml");
-        let config: Config = serde_yaml::from_str(yaml_data)?;
+        Config::from_yaml(yaml_data, Path::new("."))
+    }
 
+    /// Parse a configuration after resolving its `extends` and `include` keys,
+    /// the included files being relative to `base_dir`
+    pub fn from_yaml(yaml_data: &str, base_dir: &Path) -> Result<Self, anyhow::Error> {
+        let value = resolve_config(yaml_data, base_dir)?;
+        let config: Config = match serde_yaml::from_value(value) {
+            Ok(config) => config,
+            Err(e) => return Err(anyhow::anyhow!(e.to_string()) ),
+        };
+        config.check_dependencies()?;
+        if let Some(level) = config.compression_level.filter(|level| !COMPRESSION_LEVELS.contains(level)) {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Config: `compression_level` must be between {} and {}, not {}",
+                COMPRESSION_LEVELS.start(),
+                COMPRESSION_LEVELS.end(),
+                level
+            ));
+        }
+        if let Some(split_size) = config.output_split_size.filter(|split_size| *split_size < MIN_SPLIT_SIZE) {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Config: `output_split_size` must be at least {}, not {}",
+                format_size(MIN_SPLIT_SIZE),
+                format_size(split_size)
+            ));
+        }
+        if let Some(recipients) = &config.output_recipients {
+            parse_recipients(recipients)?;
+            if config.output_staging == Some(true) {
+                return Err(anyhow::anyhow!(
+                    "[ERROR] Config: `output_staging` writes the outputs in plaintext on the disk, it can't be used with `output_recipients`"
+                ));
+            }
+        }
         Ok(config)
     }
 
+    /// Check that the `depends_on` tasks exist and don't depend on each other in a loop
+    fn check_dependencies(&self) -> Result<()> {
+        for (name, section_config) in &self.tasks {
+            for dependency in section_config.depends_on.iter().flatten() {
+                if !self.tasks.contains_key(dependency) {
+                    return Err(anyhow::anyhow!(
+                        "[ERROR] Config: task `{}` depends on the unknown task `{}`",
+                        name,
+                        dependency
+                    ));
+                }
+            }
+        }
+
+        // Depth first walk of the dependencies, the path of the walk shows the loop
+        fn visit<'a>(config: &'a Config, name: &'a str, path: &mut Vec<&'a str>, done: &mut HashSet<&'a str>) -> Result<()> {
+            if done.contains(name) {
+                return Ok(());
+            }
+            if let Some(start) = path.iter().position(|task| *task == name) {
+                return Err(anyhow::anyhow!(
+                    "[ERROR] Config: tasks depend on each other: {} -> {}",
+                    path[start..].join(" -> "),
+                    name
+                ));
+            }
+            path.push(name);
+            for dependency in config.tasks[name].depends_on.iter().flatten() {
+                visit(config, dependency, path, done)?;
+            }
+            path.pop();
+            done.insert(name);
+            Ok(())
+        }
+        let mut done = HashSet::new();
+        for name in self.tasks.keys() {
+            visit(self, name, &mut Vec::new(), &mut done)?;
+        }
+        Ok(())
+    }
+
+    /// JSON Schema of the configuration file, generated from the types the loader deserializes
+    pub fn schema() -> Result<serde_json::Value> {
+        let mut schema = serde_json::to_value(schema_for!(Config))?;
+        // `extends` and `include` are resolved before the configuration is parsed
+        if let Some(properties) = schema["properties"].as_object_mut() {
+            properties.insert(
+                "extends".to_string(),
+                serde_json::json!({
+                    "description": "Configuration this one is based on, a file or `embedded:NAME`",
+                    "type": "string"
+                }),
+            );
+            properties.insert(
+                "include".to_string(),
+                serde_json::json!({
+                    "description": "Configurations merged in order over the `extends` one",
+                    "anyOf": [
+                        { "type": "string" },
+                        { "type": "array", "items": { "type": "string" } }
+                    ]
+                }),
+            );
+        }
+        Ok(schema)
+    }
+
+    /// Text of the embedded configuration, or of the default one
+    pub fn get_raw_data() -> Result<String, anyhow::Error> {
+        let config_data = Config::load_embedded_config().unwrap_or_default();
+        if config_data.is_empty() {
+            return Ok(include_str!("../config/.config.yml").to_string());
+        }
+        Ok(config_data)
+    }
+
     pub fn load() -> Result<Self, anyhow::Error> {
         // Load configuration: Try to load the embedded configuration first, then fallback to default
-        let config_data = Config::load_embedded_config().unwrap_or(String::new());
+        let config_data = Config::load_embedded_config().unwrap_or_default();
         if config_data.is_empty() {
             return match Config::load_default() {
                 Ok(conf) => Ok(conf),
                 Err(e) => Err(e),
             }
         }
-        return match serde_yaml::from_str(&config_data) {
-            Ok(config) => Ok(config),
-            Err(e) => Err(anyhow::anyhow!(e.to_string()) ),
-        };
+        Config::from_yaml(&config_data, Path::new("."))
     }
 
     pub fn check_config_file(filepath: &String) -> Result<Self, anyhow::Error> {
@@ -379,11 +918,9 @@ impl Config {
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
         let config_string = String::from_utf8_lossy(&buffer);
+        let base_dir = Path::new(filepath).parent().unwrap_or(Path::new("."));
 
-        return match serde_yaml::from_str(&config_string) {
-            Ok(config) => Ok(config),
-            Err(e) => Err(anyhow::anyhow!(e.to_string()) ),
-        };
+        Config::from_yaml(&config_string, base_dir)
     }
     
     // Function to load the embedded configuration at runtime
@@ -391,38 +928,40 @@ impl Config {
         let config_data = extract_resource("config.yml")?;
         let config_string = String::from_utf8(config_data)?;
 
-        return Ok(config_string);
+        Ok(config_string)
     }
 
-    /// Load the raw configuration as a plain string, choosing between embedded or default.
-    pub fn get_raw_data() -> Result<String> {
-        // Attempt to load the embedded configuration
-        if let Ok(embedded_config) = Config::load_embedded_config() {
-            if !embedded_config.is_empty() {
-                return Ok(embedded_config);
+    /// The configuration as YAML with readable sizes, without the unset fields
+    pub fn to_yaml(&self) -> Result<String> {
+        fn remove_nulls(value: &mut serde_yaml::Value) {
+            match value {
+                serde_yaml::Value::Mapping(mapping) => {
+                    mapping.retain(|_, v| !v.is_null());
+                    mapping.iter_mut().for_each(|(_, v)| remove_nulls(v));
+                }
+                serde_yaml::Value::Sequence(sequence) => sequence.iter_mut().for_each(remove_nulls),
+                _ => {}
             }
         }
-        // If embedded config is not found, fall back to loading the default config file
-        let yaml_data = include_str!("../config/.config.yml");
 
-        Ok(yaml_data.to_string())
+        let mut value = serde_yaml::to_value(self)?;
+        remove_nulls(&mut value);
+        Ok(serde_yaml::to_string(&value)?)
     }
 
+    /// Save the resolved configuration in the output folder
     pub fn save(&self, output_dir: &str) -> Result<()> {
-        let data = Config::get_raw_data()?;
-
-        // Ensure the root_output folder exists
-        let path = Path::new(output_dir);
-        if !path.exists() {
-            create_dir_all(path)?;
-        }
+        let data = self.to_yaml()?;
 
         // Define the output file path
-        let config_file_path = path.join("config.yml");
+        let config_file_path = Path::new(output_dir).join("config.yml");
 
-        // Write the YAML string to the file
-        let mut file = File::create(config_file_path)?;
+        // Write the YAML string to the file, or to the archive
+        let Some(mut file) = create_output(&config_file_path.to_string_lossy(), None)? else {
+            return Err(anyhow::anyhow!("[ERROR] `{}` already exists", config_file_path.display()));
+        };
         file.write_all(data.as_bytes())?;
+        file.finish()?;
         Ok(())
     }
 
@@ -448,6 +987,64 @@ impl Config {
         output_filename_expand
     }
 
+    /// Keep only the `tasks` if any are given, then remove the `skip_tasks`.
+    /// A selected task runs even if it is disabled in the configuration.
+    pub fn select_tasks(&mut self, tasks: &[String], skip_tasks: &[String]) -> Result<()> {
+        for name in tasks.iter().chain(skip_tasks) {
+            if !self.tasks.contains_key(name) {
+                let names: Vec<&str> = self.tasks.keys().map(String::as_str).collect();
+                return Err(anyhow::anyhow!(
+                    "[ERROR] Unknown task `{}`, expected one of {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+        if !tasks.is_empty() {
+            self.tasks.retain(|name, _| tasks.contains(name));
+            self.tasks.values_mut().for_each(|section_config| section_config.disabled = None);
+        }
+        self.tasks.retain(|name, _| !skip_tasks.contains(name));
+        Ok(())
+    }
+
+    /// Replace the `{{key}}` variables in every value of the configuration,
+    /// return the keys used nowhere
+    pub fn set_vars(&mut self, vars: &[(String, String)]) -> Result<Vec<String>> {
+        fn replace(value: &mut serde_yaml::Value, vars: &[(String, String)], used: &mut HashSet<usize>) {
+            match value {
+                serde_yaml::Value::String(text) => {
+                    for (index, (key, var_value)) in vars.iter().enumerate() {
+                        let variable = format!("{{{{{}}}}}", key);
+                        if text.contains(&variable) {
+                            *text = text.replace(&variable, var_value);
+                            used.insert(index);
+                        }
+                    }
+                }
+                serde_yaml::Value::Mapping(mapping) => {
+                    mapping.iter_mut().for_each(|(_, v)| replace(v, vars, used));
+                }
+                serde_yaml::Value::Sequence(sequence) => sequence.iter_mut().for_each(|v| replace(v, vars, used)),
+                _ => {}
+            }
+        }
+
+        if vars.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut value = serde_yaml::to_value(&*self)?;
+        let mut used = HashSet::new();
+        replace(&mut value, vars, &mut used);
+        *self = serde_yaml::from_value(value).map_err(|e| anyhow::anyhow!("[ERROR] Config: {}", e))?;
+        Ok(vars
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !used.contains(index))
+            .map(|(_, (key, _))| key.clone())
+            .collect())
+    }
+
     /// Function to return tasks sections ordered by priority
     pub fn get_tasks(&self) -> Vec<(String, SectionConfig)> {
         let mut tasks_vec: Vec<(String, SectionConfig)> = self.tasks.clone().into_iter().collect();
@@ -478,68 +1075,112 @@ impl SearchConfig {
         }
     }
 
+    /// Build the time window of the entry, `None` if it has no time bound
+    pub fn get_time_window(&self) -> Result<Option<TimeWindow>, String> {
+        let parse = |value: &Option<String>| value.as_deref().map(parse_time_bound).transpose();
+        let window = TimeWindow {
+            modified_after: parse(&self.modified_after)?,
+            modified_before: parse(&self.modified_before)?,
+            created_after: parse(&self.created_after)?,
+            created_before: parse(&self.created_before)?,
+            file_name_timestamps: self.file_name_timestamps.unwrap_or(false),
+        };
+        if window.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(window))
+    }
+
+    /// Build the filters of the entry (time window, `magic` signatures, `exclude` patterns and regexes),
+    /// the `exclude` patterns being relative to `root_path`
+    pub fn get_filters(&self, root_path: &str) -> Result<EntryFilters, String> {
+        let magic = match &self.magic {
+            Some(names) => Some(
+                names
+                    .iter()
+                    .map(|name| {
+                        Signature::from_name(name).ok_or_else(|| {
+                            format!(
+                                "unknown magic signature `{}`, expected one of {}",
+                                name,
+                                SIGNATURE_NAMES.join(", ")
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<Signature>, String>>()?,
+            ),
+            None => None,
+        };
+        let regex = match (&self.r#type, &self.objects) {
+            (Some(TypeConfig::Regex), Some(objects)) => Some(compile_regexes(objects)?),
+            _ => None,
+        };
+        let exclude = match &self.exclude {
+            Some(patterns) => vec![compile_excludes(patterns, root_path)?],
+            None => Vec::new(),
+        };
+        Ok(EntryFilters {
+            time_window: self.get_time_window()?,
+            magic,
+            exclude,
+            regex,
+        })
+    }
+
     // Method to get root_path with environment variables replaced
     pub fn get_expanded_root_path(&self) -> String {
         replace_env_vars(&self.root_path.clone().unwrap_or_default())
     }
 
-    // Method to sanitize root_path and objects based on metacharacters
-    pub fn sanitize(&mut self) -> Result<(), String> {
-        let root_path_item = &self.get_expanded_root_path();
-        let mut root_path = root_path_item.replace("\\", "/");
-        root_path = remove_trailing_slash(root_path);
-        // Check if the root_path contains a glob element (*, **, ?, or bracketed expressions)
-        if root_path.contains("*")
-            || root_path.contains("?")
-            || root_path.contains("[")
-            || root_path.contains("]")
-        {
-            let parts: Vec<&str> = root_path.split("/").collect();
-
-            // Extract the common part (before any glob or metacharacter)
-            let mut new_root_path = String::new();
-            let mut remaining_path = Vec::new();
-
-            for part in parts.iter() {
-                if part.contains("*")
-                    || part.contains("**")
-                    || part.contains("?")
-                    || part.contains("[")
-                    || part.contains("]")
-                {
-                    remaining_path.push(part.to_string());
-                } else {
-                    if !remaining_path.is_empty() {
-                        remaining_path.push(part.to_string());
-                    } else {
-                        if !new_root_path.is_empty() {
-                            new_root_path.push_str("/");
-                        }
-                        new_root_path.push_str(part);
-                    }
-                }
-            }
+    pub fn is_regex(&self) -> bool {
+        self.r#type == Some(TypeConfig::Regex)
+    }
+}
 
-            // If there's no remaining path, assume it's for the current directory
-            let remaining_path_str = if !remaining_path.is_empty() {
-                remaining_path.join("/")
-            } else {
-                "*".to_string() // A wildcard to match anything in the current directory
-            };
-
-            // Update objects by prepending the remaining path to each object pattern
-            if let Some(ref mut objects) = self.objects {
-                for object in objects.iter_mut() {
-                    *object = format!("{}/{}", remaining_path_str, object);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TASK: &str = "output_filename: out\ntasks:\n  files:\n    priority: 1\n    type: collect\n";
+
+    fn load(text: &str) -> Result<Config> {
+        Config::from_yaml(text, Path::new("."))
+    }
 
-            // Update the root_path with the new common part
-            self.root_path = Some(new_root_path);
-        } else {
-            self.root_path = Some(root_path);
+    #[test]
+    fn rejects_unknown_keys() {
+        for (text, key) in [
+            (format!("{}    entries: {{}}\nmax_sise: 1G\n", TASK), "max_sise"),
+            (format!("{}    priorty: 2\n    entries: {{}}\n", TASK), "priorty"),
+            (format!("{}    entries:\n      logs:\n        - root_path: \"\\\\Logs\"\n          object: [\"*\"]\n", TASK), "object"),
+            (format!("{}    when: {{os_bulid: \">= 1\"}}\n    entries: {{}}\n", TASK), "os_bulid"),
+            (format!("{}    when: {{registry: {{hive: SYSTEM, kye: Select}}}}\n    entries: {{}}\n", TASK), "kye"),
+        ] {
+            let error = load(&text).unwrap_err().to_string();
+            assert!(error.contains(&format!("unknown field `{}`", key)), "{}", error);
         }
+    }
 
-        Ok(())
+    #[test]
+    fn loads_the_template() {
+        load(include_str!("../config/config.yml.template")).unwrap();
+    }
+
+    #[test]
+    fn rejects_staging_with_recipients() {
+        let recipient = age::x25519::Identity::generate().to_public();
+        let text = format!("{}    entries: {{}}\noutput_recipients: [\"{}\"]\n", TASK, recipient);
+        load(&text).unwrap();
+        let error = load(&format!("{}output_staging: true\n", text)).unwrap_err().to_string();
+        assert!(error.contains("`output_staging`"), "{}", error);
+    }
+
+    #[test]
+    fn closes_every_object_of_the_schema() {
+        let schema = Config::schema().unwrap();
+        assert_eq!(schema["additionalProperties"], false);
+        for definition in ["SectionConfig", "SearchConfig", "Condition", "RegistryCondition"] {
+            assert_eq!(schema["definitions"][definition]["additionalProperties"], false, "{}", definition);
+        }
     }
 }
@@ -0,0 +1,117 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use anyhow::Result;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Extension of the files encrypted to the recipients
+pub const ENCRYPTED_EXTENSION: &str = ".age";
+
+/// Parse the public keys of `output_recipients`: age X25519 recipients (`age1...`),
+/// or SSH public keys (`ssh-ed25519`, `ssh-rsa` wrapped with RSA-OAEP)
+pub fn parse_recipients(keys: &[String]) -> Result<Vec<Box<dyn age::Recipient + Send>>> {
+    if keys.is_empty() {
+        return Err(anyhow::anyhow!("[ERROR] Config: `output_recipients` has no public key"));
+    }
+    keys.iter()
+        .map(|key| {
+            let key = key.trim();
+            if key.starts_with("age1") {
+                age::x25519::Recipient::from_str(key)
+                    .map(|recipient| Box::new(recipient) as Box<dyn age::Recipient + Send>)
+                    .map_err(|e| anyhow::anyhow!("[ERROR] Config: invalid age recipient `{}`: {}", key, e))
+            } else {
+                age::ssh::Recipient::from_str(key)
+                    .map(|recipient| Box::new(recipient) as Box<dyn age::Recipient + Send>)
+                    .map_err(|_| {
+                        anyhow::anyhow!(
+                            "[ERROR] Config: `{}` is not an age recipient or an SSH public key (ssh-ed25519, ssh-rsa)",
+                            key
+                        )
+                    })
+            }
+        })
+        .collect()
+}
+
+/// Encrypt what is written in `output` to the recipients, the stream must be finished
+pub fn encrypt_output<W: Write>(output: W, keys: &[String]) -> io::Result<age::stream::StreamWriter<W>> {
+    let recipients = parse_recipients(keys).map_err(|e| io::Error::other(e.to_string()))?;
+    let encryptor = age::Encryptor::with_recipients(recipients)
+        .ok_or_else(|| io::Error::other("[ERROR] No recipient to encrypt the output to"))?;
+    encryptor.wrap_output(output).map_err(io::Error::other)
+}
+
+/// Read the identities of an age identity file (`AGE-SECRET-KEY-...` lines), or an unencrypted SSH private key
+fn read_identities(identity_path: &str) -> Result<Vec<Box<dyn age::Identity>>> {
+    let data = fs::read_to_string(identity_path)?;
+    let age_keys: Vec<&str> = data
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("AGE-SECRET-KEY-"))
+        .collect();
+    if !age_keys.is_empty() {
+        return age_keys
+            .into_iter()
+            .map(|key| {
+                age::x25519::Identity::from_str(key)
+                    .map(|identity| Box::new(identity) as Box<dyn age::Identity>)
+                    .map_err(|e| anyhow::anyhow!("[ERROR] Invalid age identity in `{}`: {}", identity_path, e))
+            })
+            .collect();
+    }
+    match age::ssh::Identity::from_buffer(BufReader::new(data.as_bytes()), Some(identity_path.to_string()))? {
+        identity @ age::ssh::Identity::Unencrypted(_) => Ok(vec![Box::new(identity)]),
+        age::ssh::Identity::Encrypted(_) => Err(anyhow::anyhow!(
+            "[ERROR] The SSH key `{}` is protected by a passphrase, decrypt it first",
+            identity_path
+        )),
+        age::ssh::Identity::Unsupported(_) => Err(anyhow::anyhow!(
+            "[ERROR] `{}` is not an age identity or a supported SSH private key",
+            identity_path
+        )),
+    }
+}
+
+/// Decrypt files encrypted to the recipients with the identity of a responder, next to them
+/// or in `output_dir`. The `.age` extension is removed from the name of the decrypted files.
+pub fn decrypt_files(files: &[String], identity_path: &str, output_dir: Option<&String>) -> Result<Vec<String>> {
+    let identities = read_identities(identity_path)?;
+    let mut decrypted_paths = Vec::new();
+    for file in files {
+        let decryptor = match age::Decryptor::new(BufReader::new(File::open(file)?))
+            .map_err(|e| anyhow::anyhow!("[ERROR] `{}` is not encrypted to recipients: {}", file, e))?
+        {
+            age::Decryptor::Recipients(decryptor) => decryptor,
+            _ => return Err(anyhow::anyhow!("[ERROR] `{}` is encrypted with a passphrase", file)),
+        };
+        let mut reader = decryptor
+            .decrypt(identities.iter().map(|identity| identity.as_ref()))
+            .map_err(|e| anyhow::anyhow!("[ERROR] Problem to decrypt `{}`: {}", file, e))?;
+
+        let decrypted_name = file.strip_suffix(ENCRYPTED_EXTENSION).map_or_else(
+            || format!("{}.decrypted", file),
+            |name| name.to_string(),
+        );
+        let decrypted_path = match output_dir {
+            Some(output_dir) => {
+                let file_name = Path::new(&decrypted_name).file_name().unwrap_or_default();
+                Path::new(output_dir).join(file_name).to_string_lossy().to_string()
+            }
+            None => decrypted_name,
+        };
+        let mut writer = BufWriter::new(File::create(&decrypted_path)?);
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
+        decrypted_paths.push(decrypted_path);
+    }
+    Ok(decrypted_paths)
+}
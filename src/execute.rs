@@ -10,6 +10,7 @@ mod network_info;
 mod process;
 mod process_details;
 
+use crate::archive::create_output;
 use crate::config::ExecType;
 use crate::resource::extract_resource;
 
@@ -26,13 +27,13 @@ pub fn run_internal(tool_name:&str, output_filename: &str) {
 
     match tool_name {
         "ProcInfo" => {
-            process::run(&output_file_path);
+            process::run(output_file_path);
         }
         "ProcDetailsInfo" => {
-            process_details::run(&output_file_path)
+            process_details::run(output_file_path)
         }
         "PortsInfo" => {
-            network_info::run_network_info(&output_file_path);
+            network_info::run_network_info(output_file_path);
         }
         &_ => {
             dprintln!("[ERROR] > `{}` | Internal tool not found", tool_name);
@@ -79,7 +80,7 @@ pub fn run (
         name = temp_exe_path.to_string_lossy().to_string();
 
         // Get the filename
-        let tmp_display_name = temp_exe_path.file_name().and_then(|os_str| os_str.to_str()).unwrap_or(&name.as_str());
+        let tmp_display_name = temp_exe_path.file_name().and_then(|os_str| os_str.to_str()).unwrap_or(name.as_str());
         display_name = tmp_display_name.to_string();
     }
 
@@ -142,6 +143,14 @@ pub fn get_list_tools () -> Vec<&'static str> {
     ]
 }
 
+pub fn get_list_internal_tools () -> Vec<&'static str> {
+    vec![
+        "ProcInfo",
+        "ProcDetailsInfo",
+        "PortsInfo"
+    ]
+}
+
 pub fn get_bin(name: String) -> Result<Vec<u8>, anyhow::Error> {
     let exe_bytes: Vec<u8> = match name.as_str() {
         "autorunsc.exe" => include_bytes!("../tools/autorunsc.exe").to_vec(),
@@ -173,11 +182,11 @@ fn save_to_temp_file(_filename: &String, exe_bytes: &[u8], output_path: &str) ->
 }
 
 fn save_output_to_file(output: &[u8], output_filename: &str) -> io::Result<()> {
-    let output_file_path = Path::new(output_filename);
-    let mut file = File::create(output_file_path)?;
+    let Some(mut file) = create_output(output_filename, None)? else {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", output_filename)));
+    };
     file.write_all(output)?;
-    file.flush()?;
-    Ok(())
+    file.finish()
 }
 
 fn cleanup_temp_file(temp_exe_path: &str) -> io::Result<()> {
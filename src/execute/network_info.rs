@@ -6,7 +6,7 @@
 // Author(s): Areg Baghinyan
 //
 
-use std::fs::File;
+use crate::archive::create_output;
 use std::io::Write;
 use std::net::Ipv4Addr;
 use std::path::Path;
@@ -78,9 +78,13 @@ pub fn run_network_info(full_path: &Path) {
     // Get TCP connections
     let tcp_connections = get_tcp_connections();
 
-    // Try to create the file, log error if it fails
-    let mut file = match File::create(&full_path) {
-        Ok(f) => f,
+    // Try to create the file in the output, log error if it fails
+    let mut file = match create_output(&full_path.to_string_lossy(), None) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            dprintln!("[ERROR] The file `{}` already exists", full_path.display());
+            return;
+        }
         Err(e) => {
             dprintln!("[ERROR] Failed to create file at `{}`: {}", full_path.display(), e);
             return; // Exit early to avoid proceeding with errors
@@ -112,5 +116,10 @@ pub fn run_network_info(full_path: &Path) {
         }
     }
 
+    if let Err(e) = file.finish() {
+        dprintln!("[ERROR] Failed to save the file `{}`: {}", full_path.display(), e);
+        return;
+    }
+
     dprintln!("[INFO] Port information written to {:?}", full_path);
 }
@@ -6,7 +6,7 @@
 // Author(s): Areg Baghinyan
 //
 
-use std::fs::File;
+use crate::archive::create_output;
 use std::io::{self, Write};
 use windows::{
     core::*,
@@ -18,9 +18,13 @@ use windows::{
 use std::path::Path;
 
 pub fn run(full_path: &Path) {
-    // Try to create the file, log error if it fails
-    let mut file = match File::create(&full_path) {
-        Ok(f) => f,
+    // Try to create the file in the output, log error if it fails
+    let mut file = match create_output(&full_path.to_string_lossy(), None) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            dprintln!("[ERROR] The file `{}` already exists", full_path.display());
+            return;
+        }
         Err(e) => {
             dprintln!("[ERROR] Failed to create file at `{}`: {}", full_path.display(), e);
             return; // Exit early to avoid proceeding with errors
@@ -48,6 +52,11 @@ pub fn run(full_path: &Path) {
         }
     }
 
+    if let Err(e) = file.finish() {
+        dprintln!("[ERROR] Failed to save the file `{}`: {}", full_path.display(), e);
+        return;
+    }
+
     dprintln!("[INFO] Process information has been successfully written to: {}", full_path.display());
 }
 
@@ -59,13 +68,15 @@ fn get_processes() -> Result<Vec<PROCESSENTRY32>> {
         }
 
         let mut processes = Vec::new();
-        let mut process_entry = PROCESSENTRY32::default();
-        process_entry.dwSize = std::mem::size_of::<PROCESSENTRY32>() as u32;
+        let mut process_entry = PROCESSENTRY32 {
+            dwSize: std::mem::size_of::<PROCESSENTRY32>() as u32,
+            ..Default::default()
+        };
 
         if Process32First(snapshot, &mut process_entry).is_ok() {
             loop {
                 // Clone the current process entry and push it into the vector
-                processes.push(process_entry.clone());
+                processes.push(process_entry);
 
                 if Process32Next(snapshot, &mut process_entry).is_err() {
                     break;
@@ -86,9 +97,9 @@ fn extract_exe_name(raw_name: &[i8; 260]) -> String {
 
 fn write_process_info<W: Write>(writer: &mut W, process: &PROCESSENTRY32) -> io::Result<()> {
     let exe_file = extract_exe_name(&process.szExeFile);
-    write!(
+    writeln!(
         writer,
-        "PID: {}, Parent PID: {}, Executable: {}\n",
+        "PID: {}, Parent PID: {}, Executable: {}",
         process.th32ProcessID,
         process.th32ParentProcessID,
         exe_file
@@ -7,6 +7,7 @@
 //
 
 use serde::Serialize;
+use crate::archive::create_output;
 use std::fs::File;
 use std::io::{BufReader, Read, Write};
 use windows::{
@@ -332,10 +333,7 @@ fn list_all_processes() -> Result<Vec<ProcessInfo>> {
 
                 let process_handle = OpenProcess(PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, false, pe32.th32ProcessID).ok();
 
-                let modules = match list_loaded_modules(pe32.th32ProcessID) {
-                    Ok(modules) => modules,
-                    Err(_) => Vec::new(),
-                };
+                let modules = list_loaded_modules(pe32.th32ProcessID).unwrap_or_default();
 
                 let threads = if let Some(handle) = process_handle {
                     scan_threads_in_rwx(pe32.th32ProcessID, handle).unwrap_or_else(|_| Vec::new())
@@ -377,9 +375,13 @@ fn list_all_processes() -> Result<Vec<ProcessInfo>> {
 }
 
 pub fn run(full_path: &Path) {
-    // Try to create the file, log error if it fails
-    let mut file = match File::create(&full_path) {
-        Ok(f) => f,
+    // Try to create the file in the output, log error if it fails
+    let mut file = match create_output(&full_path.to_string_lossy(), None) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            dprintln!("[ERROR] The file `{}` already exists", full_path.display());
+            return;
+        }
         Err(e) => {
             dprintln!("[ERROR] Failed to create file at `{}`: {}", full_path.display(), e);
             return; // Exit early to avoid proceeding with errors
@@ -410,5 +412,10 @@ pub fn run(full_path: &Path) {
         return; // Exit early if writing to the file fails
     }
 
+    if let Err(e) = file.finish() {
+        dprintln!("[ERROR] Failed to save the file `{}`: {}", full_path.display(), e);
+        return;
+    }
+
     dprintln!("[INFO] Process information has been written to: {}", full_path.display());
 }
@@ -0,0 +1,862 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::archive::create_output;
+use crate::matcher::Matcher;
+use crate::sector_reader::{read_boot_area, Geometry};
+use crate::utils::save_stream;
+use crate::walker::{explore, EntryInfo, Volume};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT_MAGIC: u16 = 0xEF53;
+const EXTENT_MAGIC: u16 = 0xF30A;
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+const ROOT_INODE: u32 = 2;
+const MAX_EXTENT_DEPTH: u16 = 5;
+
+// Superblock feature flags
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_64BIT: u32 = 0x0080;
+
+// Inode flags
+const INODE_FLAG_INDEX: u32 = 0x0000_1000;
+const INODE_FLAG_EXTENTS: u32 = 0x0008_0000;
+const INODE_FLAG_INLINE_DATA: u32 = 0x1000_0000;
+
+// Inode modes
+const MODE_TYPE_MASK: u16 = 0xF000;
+const MODE_DIRECTORY: u16 = 0x4000;
+const MODE_REGULAR: u16 = 0x8000;
+
+// Directory entry file types
+const FT_REG_FILE: u8 = 1;
+const FT_DIR: u8 = 2;
+
+const DELETED_INODES_FILE: &str = "ext4_deleted_inodes.csv";
+
+/// Size of a block group of 4 KiB blocks, a directory read in memory can't be larger
+const MAX_DIRECTORY_SIZE: u64 = 128 * 1024 * 1024;
+
+/// Geometry of an ext2/3/4 file system, read from the superblock
+#[derive(Debug)]
+struct Ext4Volume {
+    block_size: u64,
+    inodes_count: u32,
+    inodes_per_group: u32,
+    inode_size: u64,
+    desc_size: u64,
+    group_desc_offset: u64,
+    has_file_type: bool,
+    is_64bit: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Inode {
+    mode: u16,
+    size: u64,
+    flags: u32,
+    links_count: u16,
+    block: Vec<u8>,
+    atime: u32,
+    ctime: u32,
+    mtime: u32,
+    dtime: u32,
+    crtime: Option<u32>,
+    inline_extra: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct Ext4Entry {
+    info: EntryInfo,
+    inode: u32,
+    mode: u16,
+}
+
+/// Sequential reader over data segments, `None` offsets are holes read as zeros
+struct SegmentReader<'a, T: Read + Seek> {
+    fs: &'a mut T,
+    segments: Vec<(Option<u64>, u64)>,
+    index: usize,
+    pos: u64,
+}
+
+impl<'a, T: Read + Seek> Read for SegmentReader<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.index < self.segments.len() && self.pos >= self.segments[self.index].1 {
+            self.index += 1;
+            self.pos = 0;
+        }
+        if self.index >= self.segments.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let (offset, length) = self.segments[self.index];
+        let to_read = buf.len().min((length - self.pos) as usize);
+        match offset {
+            Some(offset) => {
+                self.fs.seek(SeekFrom::Start(offset + self.pos))?;
+                self.fs.read_exact(&mut buf[..to_read])?;
+            }
+            None => buf[..to_read].fill(0),
+        }
+        self.pos += to_read as u64;
+        Ok(to_read)
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn unix_to_system_time(seconds: u32) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds as u64)
+}
+
+fn format_unix_time(seconds: u32) -> String {
+    if seconds == 0 {
+        return String::new();
+    }
+    DateTime::<Utc>::from(unix_to_system_time(seconds)).to_rfc3339()
+}
+
+/// Function to check if a partition is ext2/3/4 by looking for the superblock magic
+pub fn is_ext_partition<T: Read + Seek>(reader: &mut T) -> io::Result<bool> {
+    let mut superblock = [0u8; 1024];
+
+    reader.seek(SeekFrom::Start(SUPERBLOCK_OFFSET))?;
+    match reader.read_exact(&mut superblock) {
+        Ok(_) => Ok(read_u16(&superblock, 0x38) == EXT_MAGIC),
+        Err(_) => Ok(false),
+    }
+}
+
+impl Ext4Volume {
+    fn new(superblock: &[u8]) -> Result<Self> {
+        if read_u16(superblock, 0x38) != EXT_MAGIC {
+            return Err(anyhow::anyhow!("[ERROR] Not an ext2/3/4 superblock"));
+        }
+        let log_block_size = read_u32(superblock, 0x18);
+        if log_block_size > 6 {
+            return Err(anyhow::anyhow!("[ERROR] Invalid ext block size"));
+        }
+        let block_size = 1024u64 << log_block_size;
+        let first_data_block = read_u32(superblock, 0x14) as u64;
+        let rev_level = read_u32(superblock, 0x4C);
+        let incompat = read_u32(superblock, 0x60);
+        let is_64bit = incompat & INCOMPAT_64BIT != 0;
+        let inode_size = if rev_level == 0 { 128 } else { read_u16(superblock, 0x58) as u64 };
+        let desc_size = match read_u16(superblock, 0xFE) as u64 {
+            size if is_64bit && size >= 64 => size,
+            _ => 32,
+        };
+        let inodes_per_group = read_u32(superblock, 0x28);
+        if inodes_per_group == 0 || inode_size < 128 {
+            return Err(anyhow::anyhow!("[ERROR] Invalid ext inode geometry"));
+        }
+
+        Ok(Ext4Volume {
+            block_size,
+            inodes_count: read_u32(superblock, 0x00),
+            inodes_per_group,
+            inode_size,
+            desc_size,
+            group_desc_offset: (first_data_block + 1) * block_size,
+            has_file_type: incompat & INCOMPAT_FILETYPE != 0,
+            is_64bit,
+        })
+    }
+}
+
+struct Ext4Reader<'a, T: Read + Seek> {
+    fs: &'a mut T,
+    volume: Ext4Volume,
+    /// Rows of the deleted inode report, written when the walk ends
+    deleted_inodes: Vec<[String; 11]>,
+}
+
+impl<'a, T: Read + Seek> Ext4Reader<'a, T> {
+    fn read_at(&mut self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; length];
+        self.fs.seek(SeekFrom::Start(offset))?;
+        self.fs.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn inode_table_block(&mut self, group: u64) -> Result<u64> {
+        let offset = self.volume.group_desc_offset + group * self.volume.desc_size;
+        let desc = self.read_at(offset, self.volume.desc_size as usize)?;
+        let mut block = read_u32(&desc, 0x08) as u64;
+        if self.volume.is_64bit && desc.len() >= 0x2C {
+            block |= (read_u32(&desc, 0x28) as u64) << 32;
+        }
+        Ok(block)
+    }
+
+    fn read_inode(&mut self, inode_number: u32) -> Result<Inode> {
+        if inode_number == 0 || inode_number > self.volume.inodes_count {
+            return Err(anyhow::anyhow!("[ERROR] Invalid inode number {}", inode_number));
+        }
+        let group = ((inode_number - 1) / self.volume.inodes_per_group) as u64;
+        let index = ((inode_number - 1) % self.volume.inodes_per_group) as u64;
+        let table = self.inode_table_block(group)?;
+        let raw = self.read_at(
+            table * self.volume.block_size + index * self.volume.inode_size,
+            self.volume.inode_size as usize,
+        )?;
+
+        let extra_isize = if raw.len() > 0x82 { read_u16(&raw, 0x80) as usize } else { 0 };
+        let crtime = if extra_isize >= 0x18 && raw.len() >= 0x94 {
+            Some(read_u32(&raw, 0x90))
+        } else {
+            None
+        };
+
+        Ok(Inode {
+            mode: read_u16(&raw, 0x00),
+            size: read_u32(&raw, 0x04) as u64 | (read_u32(&raw, 0x6C) as u64) << 32,
+            flags: read_u32(&raw, 0x20),
+            links_count: read_u16(&raw, 0x1A),
+            block: raw[0x28..0x64].to_vec(),
+            atime: read_u32(&raw, 0x08),
+            ctime: read_u32(&raw, 0x0C),
+            mtime: read_u32(&raw, 0x10),
+            dtime: read_u32(&raw, 0x14),
+            crtime,
+            inline_extra: inline_data_xattr(&raw, extra_isize),
+        })
+    }
+
+    /// Walk an extent tree node and gather (logical block, physical block, length, initialized)
+    fn collect_extents(&mut self, node: &[u8], depth_left: u16, extents: &mut Vec<(u64, u64, u64, bool)>) -> Result<()> {
+        if node.len() < 12 || read_u16(node, 0) != EXTENT_MAGIC {
+            return Err(anyhow::anyhow!("[ERROR] Invalid extent header"));
+        }
+        let entries = read_u16(node, 2) as usize;
+        let depth = read_u16(node, 6);
+        if depth > depth_left {
+            return Err(anyhow::anyhow!("[ERROR] Extent tree is too deep"));
+        }
+
+        for i in 0..entries {
+            let e = 12 + i * 12;
+            if e + 12 > node.len() {
+                break;
+            }
+            if depth == 0 {
+                let logical = read_u32(node, e) as u64;
+                let raw_length = read_u16(node, e + 4) as u64;
+                // Lengths above 32768 mark uninitialized (preallocated) extents
+                let (length, initialized) = if raw_length > 32768 {
+                    (raw_length - 32768, false)
+                } else {
+                    (raw_length, true)
+                };
+                let physical = (read_u16(node, e + 6) as u64) << 32 | read_u32(node, e + 8) as u64;
+                extents.push((logical, physical, length, initialized));
+            } else {
+                let leaf = read_u32(node, e + 4) as u64 | (read_u16(node, e + 8) as u64) << 32;
+                let child = self.read_at(leaf * self.volume.block_size, self.volume.block_size as usize)?;
+                self.collect_extents(&child, depth - 1, extents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the indirect block maps used by ext2 and ext3
+    fn collect_block_map(&mut self, block: u64, level: u32, logical: &mut u64, max_blocks: u64, blocks: &mut Vec<(u64, u64)>) -> Result<()> {
+        let per_block = self.volume.block_size / 4;
+        if block == 0 {
+            *logical += per_block.pow(level + 1);
+            return Ok(());
+        }
+        let data = self.read_at(block * self.volume.block_size, self.volume.block_size as usize)?;
+        for i in 0..per_block as usize {
+            if *logical >= max_blocks {
+                break;
+            }
+            let pointer = read_u32(&data, i * 4) as u64;
+            if level == 0 {
+                if pointer != 0 {
+                    blocks.push((*logical, pointer));
+                }
+                *logical += 1;
+            } else {
+                self.collect_block_map(pointer, level - 1, logical, max_blocks, blocks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the data segments of an inode, holes and uninitialized extents are read as zeros
+    fn data_segments(&mut self, inode: &Inode) -> Result<Vec<(Option<u64>, u64)>> {
+        let block_size = self.volume.block_size;
+        let block_count = inode.size.div_ceil(block_size);
+
+        // (logical block, physical block, length) sorted by logical block
+        let mut mapping: Vec<(u64, u64, u64)> = Vec::new();
+        if inode.flags & INODE_FLAG_EXTENTS != 0 {
+            let mut extents = Vec::new();
+            self.collect_extents(&inode.block, MAX_EXTENT_DEPTH, &mut extents)?;
+            mapping.extend(
+                extents
+                    .into_iter()
+                    .filter(|(_, _, _, initialized)| *initialized)
+                    .map(|(logical, physical, length, _)| (logical, physical, length)),
+            );
+        } else {
+            let mut blocks: Vec<(u64, u64)> = Vec::new();
+            for i in 0..12u64.min(block_count) {
+                let pointer = read_u32(&inode.block, i as usize * 4) as u64;
+                if pointer != 0 {
+                    blocks.push((i, pointer));
+                }
+            }
+            let mut logical = 12u64;
+            for level in 0..3u32 {
+                if logical >= block_count {
+                    break;
+                }
+                let pointer = read_u32(&inode.block, 48 + level as usize * 4) as u64;
+                self.collect_block_map(pointer, level, &mut logical, block_count, &mut blocks)?;
+            }
+            for (logical, physical) in blocks {
+                match mapping.last_mut() {
+                    Some(last) if last.0 + last.2 == logical && last.1 + last.2 == physical => last.2 += 1,
+                    _ => mapping.push((logical, physical, 1)),
+                }
+            }
+        }
+        mapping.sort_by_key(|(logical, _, _)| *logical);
+
+        let mut segments: Vec<(Option<u64>, u64)> = Vec::new();
+        let mut position: u64 = 0;
+        for (logical, physical, length) in mapping {
+            let start = logical * block_size;
+            if start >= inode.size {
+                break;
+            }
+            if start > position {
+                segments.push((None, start - position));
+                position = start;
+            }
+            let end = (start + length * block_size).min(inode.size);
+            if end > position {
+                segments.push((Some(physical * block_size + (position - start)), end - position));
+                position = end;
+            }
+        }
+        if position < inode.size {
+            segments.push((None, inode.size - position));
+        }
+        Ok(segments)
+    }
+
+    /// Read the whole content of a (directory) inode in memory
+    fn read_content(&mut self, inode: &Inode) -> Result<Vec<u8>> {
+        if inode.flags & INODE_FLAG_INLINE_DATA != 0 {
+            return Ok(inline_content(inode));
+        }
+        if inode.size > MAX_DIRECTORY_SIZE {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Directory of {} bytes, larger than {} bytes",
+                inode.size,
+                MAX_DIRECTORY_SIZE
+            ));
+        }
+        let segments = self.data_segments(inode)?;
+        let mut data = Vec::new();
+        let reader = SegmentReader { fs: &mut *self.fs, segments, index: 0, pos: 0 };
+        reader.take(MAX_DIRECTORY_SIZE).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn make_entry(&mut self, inode_number: u32, name: String, file_type: u8, deleted: bool) -> Option<Ext4Entry> {
+        let inode = match self.read_inode(inode_number) {
+            Ok(inode) => inode,
+            Err(e) => {
+                dprintln!("[WARN] Unable to read inode {} of `{}`: {}", inode_number, name, e);
+                return None;
+            }
+        };
+        let mode = if inode.mode != 0 {
+            inode.mode
+        } else if self.volume.has_file_type && file_type == FT_DIR {
+            MODE_DIRECTORY
+        } else if self.volume.has_file_type && file_type == FT_REG_FILE {
+            MODE_REGULAR
+        } else {
+            0
+        };
+        Some(Ext4Entry {
+            info: EntryInfo {
+                name,
+                is_directory: mode & MODE_TYPE_MASK == MODE_DIRECTORY,
+                deleted,
+                id: inode_number as u64,
+                size: inode.size,
+                modified: Some(unix_to_system_time(inode.mtime)),
+                created: inode.crtime.map(unix_to_system_time),
+                file_name_modified: None,
+                file_name_created: None,
+            },
+            inode: inode_number,
+            mode,
+        })
+    }
+
+    fn parse_directory(&mut self, data: &[u8], indexed: bool) -> Vec<Ext4Entry> {
+        let mut entries = Vec::new();
+        let chunk_size = if data.len() as u64 >= self.volume.block_size {
+            self.volume.block_size as usize
+        } else {
+            data.len().max(1)
+        };
+
+        for block in data.chunks(chunk_size) {
+            let mut pos = 0usize;
+            while pos + 8 <= block.len() {
+                let inode_number = read_u32(block, pos);
+                let rec_len = read_u16(block, pos + 4) as usize;
+                let name_len = block[pos + 6] as usize;
+                let file_type = block[pos + 7];
+                if rec_len < 8 || pos + rec_len > block.len() || 8 + name_len > rec_len {
+                    break;
+                }
+                let name = String::from_utf8_lossy(&block[pos + 8..pos + 8 + name_len]).to_string();
+                let is_dot = name == "." || name == "..";
+
+                if inode_number != 0 && name_len > 0 && !is_dot {
+                    if let Some(entry) = self.make_entry(inode_number, name.clone(), file_type, false) {
+                        entries.push(entry);
+                    }
+                }
+
+                // Deleted entries are merged into the record length of the previous entry.
+                // The htree root is hidden behind "..", and index nodes are empty records
+                // covering the whole block, so their slack is never scanned.
+                let skip_slack = (is_dot && indexed) || (inode_number == 0 && name_len == 0);
+                if !skip_slack {
+                    let slack_end = pos + rec_len;
+                    let mut slack = pos + ((8 + name_len + 3) & !3);
+                    while slack + 8 <= slack_end {
+                        match self.parse_deleted_entry(&block[slack..slack_end]) {
+                            Some((entry, length)) => {
+                                entries.push(entry);
+                                slack += length;
+                            }
+                            None => slack += 4,
+                        }
+                    }
+                }
+
+                pos += rec_len;
+            }
+        }
+
+        entries
+    }
+
+    fn parse_deleted_entry(&mut self, data: &[u8]) -> Option<(Ext4Entry, usize)> {
+        let inode_number = read_u32(data, 0);
+        let name_len = data[6] as usize;
+        let file_type = data[7];
+        if inode_number == 0
+            || inode_number > self.volume.inodes_count
+            || name_len == 0
+            || 8 + name_len > data.len()
+            || (self.volume.has_file_type && file_type > 7)
+        {
+            return None;
+        }
+        let name_bytes = &data[8..8 + name_len];
+        if name_bytes.iter().any(|&b| b == 0 || b == b'/') {
+            return None;
+        }
+        let name = String::from_utf8(name_bytes.to_vec()).ok()?;
+        if name == "." || name == ".." {
+            return None;
+        }
+        let entry = self.make_entry(inode_number, name, file_type, true)?;
+        Some((entry, (8 + name_len + 3) & !3))
+    }
+
+    /// Record the metadata of a deleted inode for the CSV report
+    fn record_deleted_inode(&mut self, entry: &Ext4Entry, inode: &Inode, file_name: &str, status: &str) {
+        self.deleted_inodes.push([
+            file_name.to_string(),
+            entry.inode.to_string(),
+            format!("{:o}", inode.mode),
+            inode.size.to_string(),
+            inode.links_count.to_string(),
+            format_unix_time(inode.atime),
+            format_unix_time(inode.ctime),
+            format_unix_time(inode.mtime),
+            inode.crtime.map(format_unix_time).unwrap_or_default(),
+            format_unix_time(inode.dtime),
+            status.to_string(),
+        ]);
+    }
+
+    /// Write the deleted inode report in the output, if deleted inodes were found
+    fn save_deleted_inodes(&self, destination_folder: &str) -> Result<()> {
+        if self.deleted_inodes.is_empty() {
+            return Ok(());
+        }
+        let report_path = format!("{}/{}", destination_folder, DELETED_INODES_FILE);
+        let Some(report_file) = create_output(&report_path, None)? else {
+            return Err(anyhow::anyhow!("[ERROR] `{}` already exists", report_path));
+        };
+        let mut writer = csv::Writer::from_writer(report_file);
+        writer.write_record([
+            "path", "inode", "mode", "size", "links", "atime", "ctime", "mtime", "crtime", "dtime", "status",
+        ])?;
+        for record in &self.deleted_inodes {
+            writer.write_record(record)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|e| anyhow::anyhow!("[ERROR] Problem to save `{}`: {}", report_path, e))?
+            .finish()?;
+        Ok(())
+    }
+}
+
+/// Extract the `system.data` extended attribute holding the tail of inline data
+fn inline_data_xattr(raw: &[u8], extra_isize: usize) -> Vec<u8> {
+    let start = 128 + extra_isize;
+    if start + 4 > raw.len() || read_u32(raw, start) != XATTR_MAGIC {
+        return Vec::new();
+    }
+    let entries_start = start + 4;
+    let mut pos = entries_start;
+    while pos + 16 <= raw.len() {
+        let name_len = raw[pos] as usize;
+        let name_index = raw[pos + 1];
+        if name_len == 0 && name_index == 0 {
+            break;
+        }
+        let value_offset = read_u16(raw, pos + 2) as usize;
+        let value_size = read_u32(raw, pos + 8) as usize;
+        if pos + 16 + name_len > raw.len() {
+            break;
+        }
+        // Name index 7 is the "system." namespace
+        if name_index == 7 && &raw[pos + 16..pos + 16 + name_len] == b"data" {
+            let value_start = entries_start + value_offset;
+            if value_start + value_size <= raw.len() {
+                return raw[value_start..value_start + value_size].to_vec();
+            }
+            break;
+        }
+        pos += (16 + name_len + 3) & !3;
+    }
+    Vec::new()
+}
+
+/// Content of an inode stored inline (i_block followed by the `system.data` attribute)
+fn inline_content(inode: &Inode) -> Vec<u8> {
+    let mut data: Vec<u8> = inode.block.clone();
+    data.extend_from_slice(&inode.inline_extra);
+    data.truncate(inode.size as usize);
+    data
+}
+
+impl<'a, T: Read + Seek> Volume for Ext4Reader<'a, T> {
+    type Entry = Ext4Entry;
+
+    fn read_directory(&mut self, directory: Option<&Ext4Entry>) -> Result<Vec<Ext4Entry>> {
+        let inode_number = directory.map_or(ROOT_INODE, |entry| entry.inode);
+        let inode = self.read_inode(inode_number)?;
+        if inode.mode & MODE_TYPE_MASK != MODE_DIRECTORY {
+            return Ok(Vec::new());
+        }
+
+        if inode.flags & INODE_FLAG_INLINE_DATA != 0 {
+            // Inline directories start with the parent inode number instead of "." and ".."
+            let mut entries = self.parse_directory(&inode.block[4..], false);
+            if !inode.inline_extra.is_empty() {
+                entries.extend(self.parse_directory(&inode.inline_extra, false));
+            }
+            return Ok(entries);
+        }
+
+        let data = self.read_content(&inode)?;
+        Ok(self.parse_directory(&data, inode.flags & INODE_FLAG_INDEX != 0))
+    }
+
+    fn info(entry: &Ext4Entry) -> &EntryInfo {
+        &entry.info
+    }
+
+    fn read_header(&mut self, entry: &Ext4Entry, _stream: &str, length: usize) -> Result<Vec<u8>> {
+        let inode = self.read_inode(entry.inode)?;
+        let mut header = Vec::with_capacity(length);
+        if inode.flags & INODE_FLAG_INLINE_DATA != 0 {
+            Cursor::new(inline_content(&inode)).take(length as u64).read_to_end(&mut header)?;
+            return Ok(header);
+        }
+        let segments = self.data_segments(&inode)?;
+        let reader = SegmentReader { fs: &mut *self.fs, segments, index: 0, pos: 0 };
+        reader.take(length as u64).read_to_end(&mut header)?;
+        Ok(header)
+    }
+
+    fn save(
+        &mut self,
+        entry: &Ext4Entry,
+        _stream: &str,
+        file_name: &str,
+        destination_folder: &str,
+        encrypt: Option<&String>,
+    ) -> Result<bool> {
+        if entry.mode & MODE_TYPE_MASK != MODE_REGULAR {
+            dprintln!("[WARN] Skip `{}` because it is not a regular file", file_name);
+            return Ok(false);
+        }
+        let inode = self.read_inode(entry.inode)?;
+
+        if entry.info.deleted {
+            // A live inode behind a deleted name has been reused by another file
+            if inode.dtime == 0 && inode.links_count > 0 {
+                self.record_deleted_inode(entry, &inode, file_name, "reused");
+                return Ok(false);
+            }
+            let segments = self.data_segments(&inode).unwrap_or_default();
+            if segments.iter().all(|(offset, _)| offset.is_none()) {
+                self.record_deleted_inode(entry, &inode, file_name, "metadata_only");
+                return Ok(false);
+            }
+            self.record_deleted_inode(entry, &inode, file_name, "recovered");
+        }
+
+        let modified = Some(unix_to_system_time(inode.mtime));
+        if inode.flags & INODE_FLAG_INLINE_DATA != 0 {
+            let mut reader = Cursor::new(inline_content(&inode));
+            return save_stream(&mut reader, file_name, destination_folder, encrypt, modified);
+        }
+        let segments = self.data_segments(&inode)?;
+        let mut reader = SegmentReader { fs: &mut *self.fs, segments, index: 0, pos: 0 };
+        save_stream(&mut reader, file_name, destination_folder, encrypt, modified)
+    }
+}
+
+/// Entry point for parsing an ext2/3/4 partition and applying glob matching
+pub fn explorer(volume_path: &str, offset: u64, matcher: &Matcher, destination_folder: &str) -> Result<()> {
+    let boot_area = read_boot_area(volume_path, offset)?;
+    let superblock = &boot_area[SUPERBLOCK_OFFSET as usize..SUPERBLOCK_OFFSET as usize + 1024];
+    let volume = Ext4Volume::new(superblock)?;
+
+    // ext has no sector size of its own, blocks are read as runs of 512 bytes sectors
+    let geometry = Geometry::new(offset, 512, volume.block_size as usize / 512);
+    let mut fs = geometry.open(volume_path)?;
+    dprintln!(
+        "[INFO] ext volume with {} inodes and blocks of {} bytes",
+        volume.inodes_count,
+        volume.block_size
+    );
+
+    let mut reader = Ext4Reader {
+        fs: &mut fs,
+        volume,
+        deleted_inodes: Vec::new(),
+    };
+    let result = explore(&mut reader, matcher, destination_folder);
+    if let Err(e) = reader.save_deleted_inodes(destination_folder) {
+        dprintln!("{}", e);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 1024;
+    const INODE_TABLE_BLOCK: usize = 4;
+    const INODE_SIZE: usize = 128;
+
+    fn superblock() -> Vec<u8> {
+        let mut superblock = vec![0u8; 1024];
+        superblock[0x00..0x04].copy_from_slice(&16u32.to_le_bytes());
+        superblock[0x14..0x18].copy_from_slice(&1u32.to_le_bytes());
+        superblock[0x28..0x2C].copy_from_slice(&16u32.to_le_bytes());
+        superblock[0x38..0x3A].copy_from_slice(&EXT_MAGIC.to_le_bytes());
+        superblock[0x4C..0x50].copy_from_slice(&1u32.to_le_bytes());
+        superblock[0x58..0x5A].copy_from_slice(&(INODE_SIZE as u16).to_le_bytes());
+        superblock[0x60..0x64].copy_from_slice(&INCOMPAT_FILETYPE.to_le_bytes());
+        superblock
+    }
+
+    /// Image of 8 blocks of 1 KiB: superblock in block 1, group descriptor in block 2, inode table in block 4
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; 8 * BLOCK_SIZE];
+        image[BLOCK_SIZE..2 * BLOCK_SIZE].copy_from_slice(&superblock());
+        image[2 * BLOCK_SIZE + 0x08..2 * BLOCK_SIZE + 0x0C].copy_from_slice(&(INODE_TABLE_BLOCK as u32).to_le_bytes());
+        image
+    }
+
+    fn set_inode(image: &mut [u8], inode_number: u32, mode: u16, size: u32, dtime: u32, links: u16) -> usize {
+        let start = INODE_TABLE_BLOCK * BLOCK_SIZE + (inode_number as usize - 1) * INODE_SIZE;
+        let raw = &mut image[start..start + INODE_SIZE];
+        raw[0x00..0x02].copy_from_slice(&mode.to_le_bytes());
+        raw[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+        raw[0x10..0x14].copy_from_slice(&1_600_000_000u32.to_le_bytes());
+        raw[0x14..0x18].copy_from_slice(&dtime.to_le_bytes());
+        raw[0x1A..0x1C].copy_from_slice(&links.to_le_bytes());
+        start
+    }
+
+    fn dir_record(inode_number: u32, rec_len: u16, name: &str, file_type: u8) -> Vec<u8> {
+        let mut record = vec![0u8; rec_len as usize];
+        record[0..4].copy_from_slice(&inode_number.to_le_bytes());
+        record[4..6].copy_from_slice(&rec_len.to_le_bytes());
+        record[6] = name.len() as u8;
+        record[7] = file_type;
+        record[8..8 + name.len()].copy_from_slice(name.as_bytes());
+        record
+    }
+
+    fn with_reader<F: FnOnce(&mut Ext4Reader<Cursor<Vec<u8>>>)>(image: Vec<u8>, test: F) {
+        let volume = Ext4Volume::new(&superblock()).unwrap();
+        let mut fs = Cursor::new(image);
+        let mut reader = Ext4Reader { fs: &mut fs, volume, deleted_inodes: Vec::new() };
+        test(&mut reader);
+    }
+
+    #[test]
+    fn parses_live_and_deleted_directory_entries() {
+        let mut image = image();
+        set_inode(&mut image, 2, MODE_DIRECTORY | 0o755, 1024, 0, 3);
+        set_inode(&mut image, 12, MODE_REGULAR | 0o644, 5000, 0, 1);
+        set_inode(&mut image, 13, MODE_REGULAR | 0o644, 42, 1_600_000_100, 0);
+        set_inode(&mut image, 14, MODE_DIRECTORY | 0o755, 1024, 0, 2);
+
+        // The deleted "old.txt" is in the slack of "evidence.log", whose record covers it
+        let mut block = Vec::new();
+        block.extend(dir_record(2, 12, ".", FT_DIR));
+        block.extend(dir_record(2, 12, "..", FT_DIR));
+        let mut evidence = dir_record(12, 36, "evidence.log", FT_REG_FILE);
+        evidence[20..36].copy_from_slice(&dir_record(13, 16, "old.txt", FT_REG_FILE));
+        block.extend(evidence);
+        block.extend(dir_record(14, (BLOCK_SIZE - block.len()) as u16, "subdir", FT_DIR));
+
+        with_reader(image, |reader| {
+            let entries = reader.parse_directory(&block, false);
+            let names: Vec<(&str, bool, bool)> = entries
+                .iter()
+                .map(|entry| (entry.info.name.as_str(), entry.info.deleted, entry.info.is_directory))
+                .collect();
+            assert_eq!(names, [("evidence.log", false, false), ("old.txt", true, false), ("subdir", false, true)]);
+            assert_eq!(entries[0].info.size, 5000);
+            assert_eq!(entries[0].inode, 12);
+        });
+    }
+
+    #[test]
+    fn stops_at_an_invalid_record_length() {
+        let mut block = dir_record(12, 16, "file", FT_REG_FILE);
+        let mut bad = dir_record(13, 16, "bad", FT_REG_FILE);
+        bad[4..6].copy_from_slice(&4u16.to_le_bytes());
+        block.extend(bad);
+        let mut image = image();
+        set_inode(&mut image, 12, MODE_REGULAR | 0o644, 1, 0, 1);
+        with_reader(image, |reader| {
+            let entries = reader.parse_directory(&block, false);
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].info.name, "file");
+        });
+    }
+
+    #[test]
+    fn maps_extents_and_holes_to_segments() {
+        let mut image = image();
+        let start = set_inode(&mut image, 12, MODE_REGULAR | 0o644, 5 * 1024 + 100, 0, 1);
+        image[start + 0x20..start + 0x24].copy_from_slice(&INODE_FLAG_EXTENTS.to_le_bytes());
+        let mut block = vec![0u8; 60];
+        block[0..2].copy_from_slice(&EXTENT_MAGIC.to_le_bytes());
+        block[2..4].copy_from_slice(&3u16.to_le_bytes());
+        block[4..6].copy_from_slice(&4u16.to_le_bytes());
+        // (logical block, length, physical block), the last extent is uninitialized
+        for (i, (logical, length, physical)) in [(0u32, 2u16, 6u32), (4, 1, 7), (5, 32768 + 1, 3)].into_iter().enumerate() {
+            let e = 12 + i * 12;
+            block[e..e + 4].copy_from_slice(&logical.to_le_bytes());
+            block[e + 4..e + 6].copy_from_slice(&length.to_le_bytes());
+            block[e + 8..e + 12].copy_from_slice(&physical.to_le_bytes());
+        }
+        image[start + 0x28..start + 0x64].copy_from_slice(&block);
+
+        with_reader(image, |reader| {
+            let inode = reader.read_inode(12).unwrap();
+            assert_eq!(
+                reader.data_segments(&inode).unwrap(),
+                [(Some(6 * 1024), 2048), (None, 2048), (Some(7 * 1024), 1024), (None, 100)]
+            );
+        });
+    }
+
+    #[test]
+    fn maps_direct_blocks_to_segments() {
+        let mut image = image();
+        let start = set_inode(&mut image, 12, MODE_REGULAR | 0o644, 2048, 0, 1);
+        image[start + 0x28..start + 0x2C].copy_from_slice(&6u32.to_le_bytes());
+        image[start + 0x2C..start + 0x30].copy_from_slice(&7u32.to_le_bytes());
+        with_reader(image, |reader| {
+            let inode = reader.read_inode(12).unwrap();
+            assert_eq!(reader.data_segments(&inode).unwrap(), [(Some(6 * 1024), 2048)]);
+        });
+    }
+
+    #[test]
+    fn refuses_directories_larger_than_the_limit() {
+        let mut image = image();
+        set_inode(&mut image, 2, MODE_DIRECTORY, MAX_DIRECTORY_SIZE as u32 + 1, 0, 2);
+        with_reader(image, |reader| {
+            let inode = reader.read_inode(2).unwrap();
+            assert!(reader.read_content(&inode).is_err());
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_superblocks() {
+        assert!(Ext4Volume::new(&superblock()).is_ok());
+        let mut bad_magic = superblock();
+        bad_magic[0x38] = 0;
+        assert!(Ext4Volume::new(&bad_magic).is_err());
+        let mut bad_block_size = superblock();
+        bad_block_size[0x18] = 7;
+        assert!(Ext4Volume::new(&bad_block_size).is_err());
+        let mut no_inodes = superblock();
+        no_inodes[0x28..0x2C].fill(0);
+        assert!(Ext4Volume::new(&no_inodes).is_err());
+    }
+
+    #[test]
+    fn reads_the_inline_data_attribute() {
+        let mut raw = vec![0u8; 256];
+        let extra_isize = 32;
+        let start = 128 + extra_isize;
+        raw[start..start + 4].copy_from_slice(&XATTR_MAGIC.to_le_bytes());
+        let entry = start + 4;
+        raw[entry] = 4;
+        raw[entry + 1] = 7;
+        raw[entry + 2..entry + 4].copy_from_slice(&40u16.to_le_bytes());
+        raw[entry + 8..entry + 12].copy_from_slice(&5u32.to_le_bytes());
+        raw[entry + 16..entry + 20].copy_from_slice(b"data");
+        raw[entry + 40..entry + 45].copy_from_slice(b"tail!");
+        assert_eq!(inline_data_xattr(&raw, extra_isize), b"tail!");
+        assert!(inline_data_xattr(&raw, 0).is_empty());
+    }
+}
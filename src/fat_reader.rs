@@ -0,0 +1,819 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::matcher::Matcher;
+use crate::sector_reader::{read_boot_area, Geometry};
+use crate::utils::save_stream;
+use crate::walker::{explore, EntryInfo, Volume};
+use anyhow::Result;
+use chrono::{Local, NaiveDate, TimeZone};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+const EXFAT_SIGNATURE: &[u8] = b"EXFAT   ";
+const DIR_ENTRY_SIZE: usize = 32;
+
+// FAT directory entry attributes
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+// FAT directory entry markers
+const FAT_ENTRY_END: u8 = 0x00;
+const FAT_ENTRY_DELETED: u8 = 0xE5;
+
+// exFAT directory entry types (the high bit is the "in use" flag)
+const EXFAT_TYPE_FILE: u8 = 0x05;
+const EXFAT_TYPE_STREAM: u8 = 0x40;
+const EXFAT_TYPE_FILE_NAME: u8 = 0x41;
+const EXFAT_IN_USE: u8 = 0x80;
+const EXFAT_ATTR_DIRECTORY: u16 = 0x10;
+const EXFAT_FLAG_NO_FAT_CHAIN: u8 = 0x02;
+
+// exFAT sectors of 512 to 4096 bytes, clusters of at most 32 MiB
+const EXFAT_BYTES_PER_SECTOR_SHIFTS: std::ops::RangeInclusive<u8> = 9..=12;
+const EXFAT_MAX_CLUSTER_SHIFT: u8 = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+    ExFat,
+}
+
+/// Geometry of a FAT12/16/32 or exFAT volume, read from the boot sector
+#[derive(Debug)]
+struct FatVolume {
+    fat_type: FatType,
+    bytes_per_sector: u64,
+    cluster_size: u64,
+    volume_size: u64,
+    fat_offset: u64,
+    fat_length: u64,
+    data_offset: u64,
+    root_dir_offset: u64,
+    root_dir_size: u64,
+    root_cluster: u32,
+    cluster_count: u32,
+}
+
+#[derive(Debug, Clone)]
+struct FatEntry {
+    info: EntryInfo,
+    first_cluster: u32,
+    contiguous: bool,
+}
+
+/// Sequential reader over a list of (offset, length) byte runs of the volume
+struct RunReader<'a, T: Read + Seek> {
+    fs: &'a mut T,
+    runs: Vec<(u64, u64)>,
+    run_index: usize,
+    run_pos: u64,
+}
+
+impl<'a, T: Read + Seek> Read for RunReader<'a, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.run_index < self.runs.len() && self.run_pos >= self.runs[self.run_index].1 {
+            self.run_index += 1;
+            self.run_pos = 0;
+        }
+        if self.run_index >= self.runs.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let (offset, length) = self.runs[self.run_index];
+        let to_read = buf.len().min((length - self.run_pos) as usize);
+        self.fs.seek(SeekFrom::Start(offset + self.run_pos))?;
+        self.fs.read_exact(&mut buf[..to_read])?;
+        self.run_pos += to_read as u64;
+        Ok(to_read)
+    }
+}
+
+/// Identify the FAT flavour of a boot sector, if any
+pub fn detect_fat_type(boot_sector: &[u8]) -> Option<FatType> {
+    if boot_sector.len() < 512 {
+        return None;
+    }
+    if &boot_sector[3..11] == EXFAT_SIGNATURE {
+        return Some(FatType::ExFat);
+    }
+    if boot_sector[510] != 0x55 || boot_sector[511] != 0xAA {
+        return None;
+    }
+
+    let bytes_per_sector = read_u16(boot_sector, 11) as u64;
+    let sectors_per_cluster = boot_sector[13] as u64;
+    let reserved_sectors = read_u16(boot_sector, 14) as u64;
+    let num_fats = boot_sector[16] as u64;
+    let root_entries = read_u16(boot_sector, 17) as u64;
+
+    if !(512..=4096).contains(&bytes_per_sector)
+        || !bytes_per_sector.is_power_of_two()
+        || sectors_per_cluster == 0
+        || !sectors_per_cluster.is_power_of_two()
+        || reserved_sectors == 0
+        || num_fats == 0
+    {
+        return None;
+    }
+
+    let fat_size = match read_u16(boot_sector, 22) {
+        0 => read_u32(boot_sector, 36) as u64,
+        n => n as u64,
+    };
+    let total_sectors = match read_u16(boot_sector, 19) {
+        0 => read_u32(boot_sector, 32) as u64,
+        n => n as u64,
+    };
+    let root_dir_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+    let meta_sectors = reserved_sectors + num_fats * fat_size + root_dir_sectors;
+    if fat_size == 0 || total_sectors <= meta_sectors {
+        return None;
+    }
+
+    // Cluster count thresholds as defined by the Microsoft FAT specification
+    let cluster_count = (total_sectors - meta_sectors) / sectors_per_cluster;
+    if cluster_count < 4085 {
+        Some(FatType::Fat12)
+    } else if cluster_count < 65525 {
+        Some(FatType::Fat16)
+    } else {
+        Some(FatType::Fat32)
+    }
+}
+
+/// Function to check if a partition is FAT12/16/32 or exFAT
+pub fn is_fat_partition<T: Read + Seek>(reader: &mut T) -> io::Result<bool> {
+    let mut boot_sector = [0u8; 512];
+
+    reader.seek(SeekFrom::Start(0))?;
+    match reader.read_exact(&mut boot_sector) {
+        Ok(_) => Ok(detect_fat_type(&boot_sector).is_some()),
+        Err(_) => Ok(false),
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// Convert a DOS date and time (local time) to SystemTime
+fn dos_datetime_to_system_time(date: u16, time: u16) -> Option<SystemTime> {
+    let year = 1980 + (date >> 9) as i32;
+    let month = ((date >> 5) & 0x0F) as u32;
+    let day = (date & 0x1F) as u32;
+    let hour = (time >> 11) as u32;
+    let minute = ((time >> 5) & 0x3F) as u32;
+    let second = ((time & 0x1F) * 2) as u32;
+
+    let naive = NaiveDate::from_ymd_opt(year, month, day)?.and_hms_opt(hour, minute, second)?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+}
+
+impl FatVolume {
+    fn new(boot_sector: &[u8]) -> Result<Self> {
+        let fat_type = detect_fat_type(boot_sector)
+            .ok_or_else(|| anyhow::anyhow!("[ERROR] Not a FAT or exFAT boot sector"))?;
+
+        if fat_type == FatType::ExFat {
+            let (bytes_per_sector_shift, sectors_per_cluster_shift) = (boot_sector[108], boot_sector[109]);
+            if !EXFAT_BYTES_PER_SECTOR_SHIFTS.contains(&bytes_per_sector_shift)
+                || bytes_per_sector_shift as u32 + sectors_per_cluster_shift as u32 > EXFAT_MAX_CLUSTER_SHIFT as u32
+            {
+                return Err(anyhow::anyhow!(
+                    "[ERROR] Invalid exFAT boot sector: sectors of 2^{} bytes, clusters of 2^{} sectors",
+                    bytes_per_sector_shift,
+                    sectors_per_cluster_shift
+                ));
+            }
+            let bytes_per_sector = 1u64 << bytes_per_sector_shift;
+            let volume = FatVolume {
+                fat_type,
+                bytes_per_sector,
+                cluster_size: bytes_per_sector << sectors_per_cluster_shift,
+                volume_size: read_u64(boot_sector, 72).saturating_mul(bytes_per_sector),
+                fat_offset: read_u32(boot_sector, 80) as u64 * bytes_per_sector,
+                fat_length: read_u32(boot_sector, 84) as u64 * bytes_per_sector,
+                data_offset: read_u32(boot_sector, 88) as u64 * bytes_per_sector,
+                root_dir_offset: 0,
+                root_dir_size: 0,
+                root_cluster: read_u32(boot_sector, 96),
+                cluster_count: read_u32(boot_sector, 92),
+            };
+            return volume.check_fat();
+        }
+
+        let bytes_per_sector = read_u16(boot_sector, 11) as u64;
+        let sectors_per_cluster = boot_sector[13] as u64;
+        let reserved_sectors = read_u16(boot_sector, 14) as u64;
+        let num_fats = boot_sector[16] as u64;
+        let root_entries = read_u16(boot_sector, 17) as u64;
+        let fat_size = match read_u16(boot_sector, 22) {
+            0 => read_u32(boot_sector, 36) as u64,
+            n => n as u64,
+        };
+        let total_sectors = match read_u16(boot_sector, 19) {
+            0 => read_u32(boot_sector, 32) as u64,
+            n => n as u64,
+        };
+        let root_dir_sectors = (root_entries * 32).div_ceil(bytes_per_sector);
+        let root_dir_offset = (reserved_sectors + num_fats * fat_size) * bytes_per_sector;
+        let data_offset = root_dir_offset + root_dir_sectors * bytes_per_sector;
+        let data_sectors = total_sectors - (data_offset / bytes_per_sector);
+
+        FatVolume {
+            fat_type,
+            bytes_per_sector,
+            cluster_size: bytes_per_sector * sectors_per_cluster,
+            volume_size: total_sectors * bytes_per_sector,
+            fat_offset: reserved_sectors * bytes_per_sector,
+            fat_length: fat_size * bytes_per_sector,
+            data_offset,
+            root_dir_offset,
+            root_dir_size: root_dir_sectors * bytes_per_sector,
+            root_cluster: if fat_type == FatType::Fat32 { read_u32(boot_sector, 44) } else { 0 },
+            cluster_count: (data_sectors / sectors_per_cluster) as u32,
+        }
+        .check_fat()
+    }
+
+    /// The FAT is read in memory, it must be in the volume
+    fn check_fat(self) -> Result<Self> {
+        if self.fat_length == 0 || self.fat_offset.saturating_add(self.fat_length) > self.volume_size {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Invalid {:?} boot sector: FAT of {} bytes at offset {} in a volume of {} bytes",
+                self.fat_type,
+                self.fat_length,
+                self.fat_offset,
+                self.volume_size
+            ));
+        }
+        Ok(self)
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count.saturating_add(2)
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    /// Return the next cluster of a chain, or None at the end of the chain
+    fn next_cluster(&self, fat: &[u8], cluster: u32) -> Option<u32> {
+        let c = cluster as usize;
+        let next = match self.fat_type {
+            FatType::Fat12 => {
+                let offset = c + c / 2;
+                if offset + 1 >= fat.len() {
+                    return None;
+                }
+                let value = read_u16(fat, offset);
+                let value = if c & 1 == 1 { value >> 4 } else { value & 0x0FFF };
+                if value >= 0x0FF7 { return None; }
+                value as u32
+            }
+            FatType::Fat16 => {
+                if c * 2 + 1 >= fat.len() {
+                    return None;
+                }
+                let value = read_u16(fat, c * 2);
+                if value >= 0xFFF7 { return None; }
+                value as u32
+            }
+            FatType::Fat32 => {
+                if c * 4 + 3 >= fat.len() {
+                    return None;
+                }
+                let value = read_u32(fat, c * 4) & 0x0FFF_FFFF;
+                if value >= 0x0FFF_FFF7 { return None; }
+                value
+            }
+            FatType::ExFat => {
+                if c * 4 + 3 >= fat.len() {
+                    return None;
+                }
+                let value = read_u32(fat, c * 4);
+                if value >= 0xFFFF_FFF7 { return None; }
+                value
+            }
+        };
+        if self.is_valid_cluster(next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Build the byte runs of a cluster chain limited to `size` bytes
+    fn cluster_runs(&self, fat: &[u8], first_cluster: u32, size: u64, contiguous: bool) -> Vec<(u64, u64)> {
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        if !self.is_valid_cluster(first_cluster) || size == 0 {
+            return runs;
+        }
+
+        if contiguous {
+            let available = (self.cluster_count as u64 + 2 - first_cluster as u64) * self.cluster_size;
+            runs.push((self.cluster_offset(first_cluster), size.min(available)));
+            return runs;
+        }
+
+        let mut remaining = size;
+        let mut cluster = Some(first_cluster);
+        let mut steps: u32 = 0;
+        while let Some(c) = cluster {
+            if remaining == 0 || steps > self.cluster_count {
+                break;
+            }
+            let length = remaining.min(self.cluster_size);
+            let offset = self.cluster_offset(c);
+            match runs.last_mut() {
+                Some(last) if last.0 + last.1 == offset => last.1 += length,
+                _ => runs.push((offset, length)),
+            }
+            remaining -= length;
+            steps += 1;
+            cluster = self.next_cluster(fat, c);
+        }
+        runs
+    }
+
+    /// Byte runs holding the content of a directory entry
+    fn entry_runs(&self, fat: &[u8], entry: &FatEntry) -> Vec<(u64, u64)> {
+        let info = &entry.info;
+        if info.deleted {
+            // The FAT chain of a deleted entry is zeroed, assume the data is contiguous
+            let size = if info.is_directory && info.size == 0 { self.cluster_size } else { info.size };
+            return self.cluster_runs(fat, entry.first_cluster, size, true);
+        }
+        if info.is_directory && self.fat_type != FatType::ExFat {
+            return self.cluster_runs(fat, entry.first_cluster, u64::MAX, false);
+        }
+        self.cluster_runs(fat, entry.first_cluster, info.size, entry.contiguous)
+    }
+
+    fn root_runs(&self, fat: &[u8]) -> Vec<(u64, u64)> {
+        match self.fat_type {
+            FatType::Fat12 | FatType::Fat16 => vec![(self.root_dir_offset, self.root_dir_size)],
+            _ => self.cluster_runs(fat, self.root_cluster, u64::MAX, false),
+        }
+    }
+
+    fn parse_directory(&self, data: &[u8]) -> Vec<FatEntry> {
+        match self.fat_type {
+            FatType::ExFat => parse_exfat_directory(data),
+            _ => parse_fat_directory(data, self.fat_type == FatType::Fat32),
+        }
+    }
+}
+
+fn parse_fat_directory(data: &[u8], is_fat32: bool) -> Vec<FatEntry> {
+    let mut entries = Vec::new();
+    let mut long_name_parts: Vec<Vec<u16>> = Vec::new();
+
+    for raw in data.chunks_exact(DIR_ENTRY_SIZE) {
+        let marker = raw[0];
+        if marker == FAT_ENTRY_END {
+            break;
+        }
+        let attributes = raw[11];
+        if attributes & 0x3F == ATTR_LONG_NAME {
+            let mut part: Vec<u16> = Vec::with_capacity(13);
+            for range in [1..11, 14..26, 28..32] {
+                part.extend(raw[range].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])));
+            }
+            long_name_parts.push(part);
+            continue;
+        }
+        let deleted = marker == FAT_ENTRY_DELETED;
+        if attributes & ATTR_VOLUME_ID != 0 {
+            long_name_parts.clear();
+            continue;
+        }
+
+        // Long name entries are stored in reverse order before the short entry
+        let long_name: Vec<u16> = long_name_parts
+            .drain(..)
+            .rev()
+            .flatten()
+            .take_while(|&c| c != 0x0000 && c != 0xFFFF)
+            .collect();
+
+        let name = if !long_name.is_empty() {
+            String::from_utf16_lossy(&long_name)
+        } else {
+            let mut base = raw[0..8].to_vec();
+            if deleted {
+                base[0] = b'_';
+            } else if base[0] == 0x05 {
+                base[0] = FAT_ENTRY_DELETED;
+            }
+            let mut base = String::from_utf8_lossy(&base).trim_end().to_string();
+            let mut extension = String::from_utf8_lossy(&raw[8..11]).trim_end().to_string();
+            if raw[12] & 0x08 != 0 {
+                base = base.to_lowercase();
+            }
+            if raw[12] & 0x10 != 0 {
+                extension = extension.to_lowercase();
+            }
+            if extension.is_empty() {
+                base
+            } else {
+                format!("{}.{}", base, extension)
+            }
+        };
+
+        if name == "." || name == ".." || name.is_empty() {
+            continue;
+        }
+
+        let high_cluster = if is_fat32 { read_u16(raw, 20) as u32 } else { 0 };
+        let first_cluster = (high_cluster << 16) | read_u16(raw, 26) as u32;
+        entries.push(FatEntry {
+            info: EntryInfo {
+                name,
+                is_directory: attributes & ATTR_DIRECTORY != 0,
+                deleted,
+                id: first_cluster as u64,
+                size: read_u32(raw, 28) as u64,
+                modified: dos_datetime_to_system_time(read_u16(raw, 24), read_u16(raw, 22)),
+                created: dos_datetime_to_system_time(read_u16(raw, 16), read_u16(raw, 14)),
+                file_name_modified: None,
+                file_name_created: None,
+            },
+            first_cluster,
+            contiguous: false,
+        });
+    }
+
+    entries
+}
+
+fn parse_exfat_directory(data: &[u8]) -> Vec<FatEntry> {
+    let mut entries = Vec::new();
+    let raw_entries: Vec<&[u8]> = data.chunks_exact(DIR_ENTRY_SIZE).collect();
+    let mut i = 0;
+
+    while i < raw_entries.len() {
+        let raw = raw_entries[i];
+        let entry_type = raw[0];
+        if entry_type == 0x00 {
+            break;
+        }
+        if entry_type & 0x7F != EXFAT_TYPE_FILE {
+            i += 1;
+            continue;
+        }
+
+        let in_use = entry_type & EXFAT_IN_USE != 0;
+        let secondary_count = raw[1] as usize;
+        if secondary_count < 2 || i + secondary_count >= raw_entries.len() {
+            i += 1;
+            continue;
+        }
+
+        let stream = raw_entries[i + 1];
+        if stream[0] & 0x7F != EXFAT_TYPE_STREAM || (stream[0] & EXFAT_IN_USE != 0) != in_use {
+            i += 1;
+            continue;
+        }
+
+        let name_length = stream[3] as usize;
+        let mut name_units: Vec<u16> = Vec::with_capacity(name_length);
+        for name_entry in &raw_entries[i + 2..=i + secondary_count] {
+            if name_entry[0] & 0x7F != EXFAT_TYPE_FILE_NAME {
+                break;
+            }
+            name_units.extend(name_entry[2..32].chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])));
+        }
+        name_units.truncate(name_length);
+
+        let created = read_u32(raw, 8);
+        let timestamp = read_u32(raw, 12);
+        let first_cluster = read_u32(stream, 20);
+        entries.push(FatEntry {
+            info: EntryInfo {
+                name: String::from_utf16_lossy(&name_units),
+                is_directory: read_u16(raw, 4) & EXFAT_ATTR_DIRECTORY != 0,
+                deleted: !in_use,
+                id: first_cluster as u64,
+                size: read_u64(stream, 24),
+                modified: dos_datetime_to_system_time((timestamp >> 16) as u16, timestamp as u16),
+                created: dos_datetime_to_system_time((created >> 16) as u16, created as u16),
+                file_name_modified: None,
+                file_name_created: None,
+            },
+            first_cluster,
+            contiguous: stream[1] & EXFAT_FLAG_NO_FAT_CHAIN != 0,
+        });
+
+        i += secondary_count + 1;
+    }
+
+    entries
+}
+
+struct FatReader<'a, T: Read + Seek> {
+    fs: &'a mut T,
+    volume: FatVolume,
+    fat: Vec<u8>,
+}
+
+impl<'a, T: Read + Seek> Volume for FatReader<'a, T> {
+    type Entry = FatEntry;
+
+    fn read_directory(&mut self, directory: Option<&FatEntry>) -> Result<Vec<FatEntry>> {
+        let runs = match directory {
+            Some(entry) => self.volume.entry_runs(&self.fat, entry),
+            None => self.volume.root_runs(&self.fat),
+        };
+        let mut data = Vec::new();
+        let mut reader = RunReader { fs: &mut *self.fs, runs, run_index: 0, run_pos: 0 };
+        reader.read_to_end(&mut data)?;
+        Ok(self.volume.parse_directory(&data))
+    }
+
+    fn info(entry: &FatEntry) -> &EntryInfo {
+        &entry.info
+    }
+
+    fn read_header(&mut self, entry: &FatEntry, _stream: &str, length: usize) -> Result<Vec<u8>> {
+        let runs = self.volume.entry_runs(&self.fat, entry);
+        let reader = RunReader { fs: &mut *self.fs, runs, run_index: 0, run_pos: 0 };
+        let mut header = Vec::with_capacity(length);
+        reader.take(length as u64).read_to_end(&mut header)?;
+        Ok(header)
+    }
+
+    fn save(
+        &mut self,
+        entry: &FatEntry,
+        _stream: &str,
+        file_name: &str,
+        destination_folder: &str,
+        encrypt: Option<&String>,
+    ) -> Result<bool> {
+        let runs = self.volume.entry_runs(&self.fat, entry);
+        let mut reader = RunReader { fs: &mut *self.fs, runs, run_index: 0, run_pos: 0 };
+        save_stream(&mut reader, file_name, destination_folder, encrypt, entry.info.modified)
+    }
+}
+
+/// Sector and cluster geometry of a FAT or exFAT volume
+fn fat_geometry(volume: &FatVolume, offset: u64) -> Geometry {
+    let sectors_per_cluster = volume.cluster_size / volume.bytes_per_sector;
+    Geometry::new(offset, volume.bytes_per_sector as usize, sectors_per_cluster as usize)
+}
+
+/// Entry point for parsing a FAT or exFAT partition and applying glob matching
+pub fn explorer(volume_path: &str, offset: u64, matcher: &Matcher, destination_folder: &str) -> Result<()> {
+    let boot_area = read_boot_area(volume_path, offset)?;
+    let boot_sector = &boot_area[..512];
+    let volume = FatVolume::new(boot_sector)?;
+    let geometry = fat_geometry(&volume, offset);
+    let mut fs = geometry.open(volume_path)?;
+    dprintln!(
+        "[INFO] {:?} volume with {} clusters of {} bytes",
+        volume.fat_type,
+        volume.cluster_count,
+        volume.cluster_size
+    );
+
+    let mut fat = vec![0u8; volume.fat_length as usize];
+    fs.seek(SeekFrom::Start(volume.fat_offset))?;
+    fs.read_exact(&mut fat)?;
+
+    let mut reader = FatReader { fs: &mut fs, volume, fat };
+    explore(&mut reader, matcher, destination_folder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-01-15 12:30:10 in DOS format
+    const DOS_DATE: u16 = (40 << 9) | (1 << 5) | 15;
+    const DOS_TIME: u16 = (12 << 11) | (30 << 5) | 5;
+
+    fn short_entry(name: &[u8; 11], attributes: u8, cluster: u32, size: u32) -> [u8; 32] {
+        let mut raw = [0u8; 32];
+        raw[0..11].copy_from_slice(name);
+        raw[11] = attributes;
+        raw[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        raw[22..24].copy_from_slice(&DOS_TIME.to_le_bytes());
+        raw[24..26].copy_from_slice(&DOS_DATE.to_le_bytes());
+        raw[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+        raw[28..32].copy_from_slice(&size.to_le_bytes());
+        raw
+    }
+
+    /// Long name entry holding 13 characters, padded with 0x0000 then 0xFFFF
+    fn long_name_entry(ordinal: u8, part: &str) -> [u8; 32] {
+        let mut units: Vec<u16> = part.encode_utf16().collect();
+        if units.len() < 13 {
+            units.push(0);
+        }
+        units.resize(13, 0xFFFF);
+        let mut raw = [0u8; 32];
+        raw[0] = ordinal;
+        raw[11] = ATTR_LONG_NAME;
+        let positions = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (position, unit) in positions.zip(units) {
+            raw[position..position + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        raw
+    }
+
+    fn exfat_entry_set(name: &str, in_use: bool, attributes: u16, cluster: u32, size: u64, contiguous: bool) -> Vec<u8> {
+        let in_use_flag = if in_use { EXFAT_IN_USE } else { 0 };
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let name_entries = units.len().div_ceil(15);
+
+        let mut file = [0u8; 32];
+        file[0] = EXFAT_TYPE_FILE | in_use_flag;
+        file[1] = 1 + name_entries as u8;
+        file[4..6].copy_from_slice(&attributes.to_le_bytes());
+        let timestamp = ((DOS_DATE as u32) << 16) | DOS_TIME as u32;
+        file[8..12].copy_from_slice(&timestamp.to_le_bytes());
+        file[12..16].copy_from_slice(&timestamp.to_le_bytes());
+
+        let mut stream = [0u8; 32];
+        stream[0] = EXFAT_TYPE_STREAM | in_use_flag;
+        stream[1] = if contiguous { EXFAT_FLAG_NO_FAT_CHAIN | 0x01 } else { 0x01 };
+        stream[3] = units.len() as u8;
+        stream[20..24].copy_from_slice(&cluster.to_le_bytes());
+        stream[24..32].copy_from_slice(&size.to_le_bytes());
+
+        let mut set = [file, stream].concat();
+        for chunk in units.chunks(15) {
+            let mut name_entry = [0u8; 32];
+            name_entry[0] = EXFAT_TYPE_FILE_NAME | in_use_flag;
+            for (i, unit) in chunk.iter().enumerate() {
+                name_entry[2 + i * 2..4 + i * 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            set.extend_from_slice(&name_entry);
+        }
+        set
+    }
+
+    fn fat32_boot_sector() -> Vec<u8> {
+        let mut boot_sector = vec![0u8; 512];
+        boot_sector[11..13].copy_from_slice(&512u16.to_le_bytes());
+        boot_sector[13] = 1;
+        boot_sector[14..16].copy_from_slice(&32u16.to_le_bytes());
+        boot_sector[16] = 2;
+        boot_sector[32..36].copy_from_slice(&70_000u32.to_le_bytes());
+        boot_sector[36..40].copy_from_slice(&600u32.to_le_bytes());
+        boot_sector[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot_sector[510] = 0x55;
+        boot_sector[511] = 0xAA;
+        boot_sector
+    }
+
+    fn exfat_boot_sector(bytes_per_sector_shift: u8, sectors_per_cluster_shift: u8) -> Vec<u8> {
+        let mut boot_sector = vec![0u8; 512];
+        boot_sector[3..11].copy_from_slice(EXFAT_SIGNATURE);
+        boot_sector[72..80].copy_from_slice(&100_000u64.to_le_bytes());
+        boot_sector[80..84].copy_from_slice(&128u32.to_le_bytes());
+        boot_sector[84..88].copy_from_slice(&64u32.to_le_bytes());
+        boot_sector[88..92].copy_from_slice(&256u32.to_le_bytes());
+        boot_sector[92..96].copy_from_slice(&10_000u32.to_le_bytes());
+        boot_sector[96..100].copy_from_slice(&5u32.to_le_bytes());
+        boot_sector[108] = bytes_per_sector_shift;
+        boot_sector[109] = sectors_per_cluster_shift;
+        boot_sector
+    }
+
+    #[test]
+    fn parses_fat_long_and_short_names() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&short_entry(b".          ", ATTR_DIRECTORY, 5, 0));
+        data.extend_from_slice(&short_entry(b"VOLUME     ", ATTR_VOLUME_ID, 0, 0));
+        data.extend_from_slice(&long_name_entry(0x42, "on.txt"));
+        data.extend_from_slice(&long_name_entry(0x01, "Security_Repo"));
+        data.extend_from_slice(&short_entry(b"SECURI~1TXT", 0x20, 0x0001_0007, 1234));
+        data.extend_from_slice(&short_entry(b"DOCS       ", ATTR_DIRECTORY, 9, 0));
+        let mut deleted = short_entry(b"XDELETEDLOG", 0x20, 12, 99);
+        deleted[0] = FAT_ENTRY_DELETED;
+        data.extend_from_slice(&deleted);
+        let mut lowercase = short_entry(b"NOTES   TXT", 0x20, 13, 1);
+        lowercase[12] = 0x08;
+        data.extend_from_slice(&lowercase);
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend_from_slice(&short_entry(b"AFTEREND   ", 0x20, 14, 1));
+
+        let entries = parse_fat_directory(&data, true);
+        let names: Vec<&str> = entries.iter().map(|entry| entry.info.name.as_str()).collect();
+        assert_eq!(names, ["Security_Repoon.txt", "DOCS", "_DELETED.LOG", "notes.TXT"]);
+
+        let file = &entries[0];
+        assert_eq!(file.first_cluster, 0x0001_0007);
+        assert_eq!(file.info.size, 1234);
+        assert!(!file.info.is_directory && !file.info.deleted);
+        assert!(file.info.modified.is_some());
+        assert!(entries[1].info.is_directory);
+        assert!(entries[2].info.deleted);
+    }
+
+    #[test]
+    fn ignores_the_high_cluster_word_before_fat32() {
+        let data = short_entry(b"FILE    BIN", 0x20, 0x0003_0004, 10);
+        assert_eq!(parse_fat_directory(&data, false)[0].first_cluster, 4);
+        assert_eq!(parse_fat_directory(&data, true)[0].first_cluster, 0x0003_0004);
+    }
+
+    #[test]
+    fn parses_exfat_entry_sets() {
+        let mut data = Vec::new();
+        // Allocation bitmap entry, skipped
+        data.extend_from_slice(&[0x81; 32]);
+        data.extend(exfat_entry_set("a_long_file_name_over_15_units.evtx", true, 0x20, 20, 70_000, true));
+        data.extend(exfat_entry_set("Logs", true, EXFAT_ATTR_DIRECTORY, 30, 4096, false));
+        data.extend(exfat_entry_set("gone.tmp", false, 0x20, 40, 12, true));
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend(exfat_entry_set("after_end", true, 0x20, 50, 1, true));
+
+        let entries = parse_exfat_directory(&data);
+        let names: Vec<&str> = entries.iter().map(|entry| entry.info.name.as_str()).collect();
+        assert_eq!(names, ["a_long_file_name_over_15_units.evtx", "Logs", "gone.tmp"]);
+
+        assert_eq!(entries[0].first_cluster, 20);
+        assert_eq!(entries[0].info.size, 70_000);
+        assert!(entries[0].contiguous);
+        assert!(entries[0].info.modified.is_some());
+        assert!(entries[1].info.is_directory && !entries[1].contiguous);
+        assert!(entries[2].info.deleted);
+    }
+
+    #[test]
+    fn skips_incomplete_exfat_entry_sets() {
+        let mut data = exfat_entry_set("truncated.txt", true, 0x20, 20, 1, true);
+        data.truncate(64);
+        assert!(parse_exfat_directory(&data).is_empty());
+    }
+
+    #[test]
+    fn detects_the_fat_type_from_the_cluster_count() {
+        let mut boot_sector = fat32_boot_sector();
+        assert_eq!(detect_fat_type(&boot_sector), Some(FatType::Fat32));
+        boot_sector[32..36].copy_from_slice(&4_000u32.to_le_bytes());
+        boot_sector[36..40].copy_from_slice(&12u32.to_le_bytes());
+        assert_eq!(detect_fat_type(&boot_sector), Some(FatType::Fat12));
+        boot_sector[510] = 0;
+        assert_eq!(detect_fat_type(&boot_sector), None);
+    }
+
+    #[test]
+    fn reads_the_fat32_volume_geometry() {
+        let volume = FatVolume::new(&fat32_boot_sector()).unwrap();
+        assert_eq!(volume.cluster_size, 512);
+        assert_eq!(volume.fat_offset, 32 * 512);
+        assert_eq!(volume.fat_length, 600 * 512);
+        assert_eq!(volume.data_offset, (32 + 2 * 600) * 512);
+        assert_eq!(volume.root_cluster, 2);
+    }
+
+    #[test]
+    fn reads_the_exfat_volume_geometry() {
+        let volume = FatVolume::new(&exfat_boot_sector(12, 13)).unwrap();
+        assert_eq!(volume.cluster_size, 32 * 1024 * 1024);
+        assert_eq!(volume.fat_offset, 128 * 4096);
+        assert_eq!(volume.fat_length, 64 * 4096);
+        assert_eq!(volume.cluster_count, 10_000);
+        assert_eq!(volume.root_cluster, 5);
+    }
+
+    #[test]
+    fn rejects_invalid_exfat_shifts() {
+        for (bytes_per_sector_shift, sectors_per_cluster_shift) in [(8, 0), (13, 0), (12, 14), (9, 200), (255, 255), (12, 250), (9, 255)] {
+            let boot_sector = exfat_boot_sector(bytes_per_sector_shift, sectors_per_cluster_shift);
+            assert!(FatVolume::new(&boot_sector).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_a_fat_larger_than_the_volume() {
+        let mut boot_sector = exfat_boot_sector(9, 3);
+        boot_sector[84..88].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(FatVolume::new(&boot_sector).is_err());
+    }
+}
@@ -0,0 +1,276 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::utils::replace_env_vars;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use std::time::SystemTime;
+
+/// Time window of a collect entry, built from `modified_after`, `modified_before`,
+/// `created_after` and `created_before`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeWindow {
+    pub modified_after: Option<SystemTime>,
+    pub modified_before: Option<SystemTime>,
+    pub created_after: Option<SystemTime>,
+    pub created_before: Option<SystemTime>,
+    /// Also accept the file when the $FILE_NAME timestamps (NTFS only) are in the window
+    pub file_name_timestamps: bool,
+}
+
+impl TimeWindow {
+    pub fn is_empty(&self) -> bool {
+        self.modified_after.is_none()
+            && self.modified_before.is_none()
+            && self.created_after.is_none()
+            && self.created_before.is_none()
+    }
+
+    /// Check a pair of modification and creation times. A missing timestamp never matches a bound.
+    pub fn matches(&self, modified: Option<SystemTime>, created: Option<SystemTime>) -> bool {
+        in_bounds(modified, self.modified_after, self.modified_before)
+            && in_bounds(created, self.created_after, self.created_before)
+    }
+}
+
+fn in_bounds(time: Option<SystemTime>, after: Option<SystemTime>, before: Option<SystemTime>) -> bool {
+    if after.is_none() && before.is_none() {
+        return true;
+    }
+    match time {
+        Some(t) => after.is_none_or(|a| t >= a) && before.is_none_or(|b| t <= b),
+        None => false,
+    }
+}
+
+/// Parse a time bound, either absolute (`2025-01-31`, `2025-01-31 08:00:00`, RFC 3339)
+/// in local time unless a timezone is given, or relative to now (`14d`, `last 14d`, `12h`, `30m`, `2w`)
+pub fn parse_time_bound(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+
+    let relative = value.strip_prefix("last").map(str::trim_start).unwrap_or(value);
+    if let Some(unit) = relative.chars().last() {
+        if let Ok(amount) = relative[..relative.len() - unit.len_utf8()].trim().parse::<i64>() {
+            let duration = match unit {
+                'm' => Duration::try_minutes(amount),
+                'h' => Duration::try_hours(amount),
+                'd' => Duration::try_days(amount),
+                'w' => Duration::try_weeks(amount),
+                _ => None,
+            };
+            if let Some(duration) = duration {
+                return Ok((Utc::now() - duration).into());
+            }
+        }
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc).into());
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            return local_to_system_time(naive, value);
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return local_to_system_time(date.and_hms_opt(0, 0, 0).unwrap(), value);
+    }
+
+    Err(format!(
+        "invalid time `{}`, expected a date like `2025-01-31`, `2025-01-31 08:00:00` or a relative time like `14d`",
+        value
+    ))
+}
+
+fn local_to_system_time(naive: NaiveDateTime, value: &str) -> Result<SystemTime, String> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(SystemTime::from)
+        .ok_or_else(|| format!("invalid local time `{}`", value))
+}
+
+/// Number of bytes read at the start of a file to check its content signature
+pub const MAGIC_HEADER_SIZE: usize = 512;
+
+/// Named content signatures of the `magic` filter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signature {
+    /// Windows executables and DLLs (MZ header)
+    Pe,
+    Elf,
+    /// ZIP archives, including OOXML documents, JAR and APK files
+    Zip,
+    /// OLE2 compound files (legacy Office documents, MSI)
+    Ole2,
+    /// Windows shortcuts
+    Lnk,
+    /// Scripts starting with `#!`
+    Shebang,
+}
+
+pub const SIGNATURE_NAMES: [&str; 6] = ["pe", "elf", "zip", "ole2", "lnk", "shebang"];
+
+impl Signature {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "pe" | "mz" => Some(Signature::Pe),
+            "elf" => Some(Signature::Elf),
+            "zip" | "ooxml" => Some(Signature::Zip),
+            "ole2" | "ole" => Some(Signature::Ole2),
+            "lnk" => Some(Signature::Lnk),
+            "shebang" | "script" => Some(Signature::Shebang),
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, header: &[u8]) -> bool {
+        match self {
+            Signature::Pe => header.starts_with(b"MZ"),
+            Signature::Elf => header.starts_with(b"\x7FELF"),
+            Signature::Zip => header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06"),
+            Signature::Ole2 => header.starts_with(&[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1]),
+            Signature::Lnk => header.starts_with(&[0x4C, 0x00, 0x00, 0x00, 0x01, 0x14, 0x02, 0x00]),
+            Signature::Shebang => header.starts_with(b"#!") || header.starts_with(b"\xEF\xBB\xBF#!"),
+        }
+    }
+}
+
+/// Filters of a collect entry, checked before a matching file is copied
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilters {
+    pub time_window: Option<TimeWindow>,
+    pub magic: Option<Vec<Signature>>,
+    /// Paths that are neither collected nor walked, from the entry and from its task
+    pub exclude: Vec<GlobSet>,
+    /// Regular expressions of a `regex` entry, matched against the full path (e.g. `\Windows\Temp\x.log`)
+    pub regex: Option<RegexSet>,
+}
+
+impl EntryFilters {
+    /// Check the start of a file against the `magic` signatures, any of them is enough
+    pub fn matches_magic(&self, header: &[u8]) -> bool {
+        match &self.magic {
+            Some(signatures) => signatures.iter().any(|s| s.matches(header)),
+            None => true,
+        }
+    }
+
+    pub fn matches_regex(&self, path: &str) -> bool {
+        match &self.regex {
+            Some(regex) => regex.is_match(&path.replace('/', "\\")),
+            None => true,
+        }
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|set| set.is_match(path))
+    }
+}
+
+/// Compile `exclude` patterns on the volume paths, relative to `base`
+/// (the `root_path` of an entry, or the volume root for the task level `exclude`).
+/// A pattern ending with `\**` also excludes the folder itself, so it is never walked.
+pub fn compile_excludes(patterns: &[String], base: &str) -> Result<GlobSet, String> {
+    let base = base.replace('\\', "/").trim_end_matches('/').to_string();
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let expanded = replace_env_vars(pattern).replace('\\', "/");
+        let full = format!("{}/{}", base, expanded.trim_matches('/'));
+        let mut variants = vec![full.clone()];
+        if let Some(folder) = full.strip_suffix("/**") {
+            variants.push(folder.to_string());
+        }
+        for variant in variants {
+            let glob = GlobBuilder::new(&variant)
+                .case_insensitive(true)
+                .literal_separator(true)
+                .backslash_escape(false)
+                .build()
+                .map_err(|e| format!("invalid exclude pattern `{}`: {}", pattern, e))?;
+            builder.add(glob);
+        }
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Compile the objects of a `regex` entry once, case insensitive like NTFS paths
+pub fn compile_regexes(patterns: &[String]) -> Result<RegexSet, String> {
+    // Compile them one by one first to point to the invalid one
+    for pattern in patterns {
+        if let Err(e) = Regex::new(pattern) {
+            return Err(format!("invalid regex `{}`: {}", pattern, e));
+        }
+    }
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("invalid regex: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds_ago(time: SystemTime) -> i64 {
+        SystemTime::now().duration_since(time).unwrap().as_secs() as i64
+    }
+
+    #[test]
+    fn parses_relative_time_bounds() {
+        for (value, seconds) in [
+            ("14d", 14 * 86400),
+            ("last 14d", 14 * 86400),
+            ("last14d", 14 * 86400),
+            (" 12h ", 12 * 3600),
+            ("30m", 30 * 60),
+            ("2w", 14 * 86400),
+        ] {
+            let elapsed = seconds_ago(parse_time_bound(value).unwrap());
+            assert!((seconds..seconds + 5).contains(&elapsed), "{}: {}", value, elapsed);
+        }
+    }
+
+    #[test]
+    fn parses_absolute_time_bounds() {
+        let utc = |s: &str| SystemTime::from(DateTime::parse_from_rfc3339(s).unwrap());
+        assert_eq!(parse_time_bound("2025-01-31T08:00:00Z").unwrap(), utc("2025-01-31T08:00:00Z"));
+        assert_eq!(parse_time_bound("2025-01-31T10:00:00+02:00").unwrap(), utc("2025-01-31T08:00:00Z"));
+
+        let local = |s: &str| {
+            let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+            SystemTime::from(Local.from_local_datetime(&naive).earliest().unwrap())
+        };
+        assert_eq!(parse_time_bound("2025-01-31").unwrap(), local("2025-01-31 00:00:00"));
+        assert_eq!(parse_time_bound("2025-01-31 08:30:15").unwrap(), local("2025-01-31 08:30:15"));
+        assert_eq!(parse_time_bound("2025-01-31T08:30:15").unwrap(), local("2025-01-31 08:30:15"));
+        assert_eq!(parse_time_bound("2025-01-31 08:30").unwrap(), local("2025-01-31 08:30:00"));
+    }
+
+    #[test]
+    fn rejects_invalid_time_bounds() {
+        for value in ["", "yesterday", "14", "14y", "last", "2025-13-01", "2025-01-31 25:00"] {
+            assert!(parse_time_bound(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn checks_the_time_window() {
+        let bound = |s| parse_time_bound(s).unwrap();
+        let window = TimeWindow {
+            modified_after: Some(bound("2025-01-01T00:00:00Z")),
+            modified_before: Some(bound("2025-02-01T00:00:00Z")),
+            ..Default::default()
+        };
+        assert!(window.matches(Some(bound("2025-01-15T00:00:00Z")), None));
+        assert!(!window.matches(Some(bound("2025-02-15T00:00:00Z")), None));
+        assert!(!window.matches(None, None));
+        assert!(TimeWindow::default().matches(None, None));
+    }
+}
@@ -0,0 +1,416 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::config::{Config, Entries};
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extension of the KAPE target files
+const TARGET_EXTENSION: &str = "tkape";
+
+/// Fields of a KAPE target converted, or without meaning for aralez
+const KNOWN_FIELDS: [&str; 8] = [
+    "Name", "Category", "Comment", "AlwaysAddToQueue", "Path", "FileMask", "Recursive", "IsDirectory",
+];
+
+/// Prefix of a `FileMask` given as a regular expression
+const REGEX_PREFIX: &str = "regex:";
+
+/// Name of the collect task of the converted configuration
+const TASK_NAME: &str = "kape";
+
+/// A configuration converted from KAPE targets, with what could not be expressed
+pub struct Conversion {
+    pub config: String,
+    pub entries: usize,
+    pub warnings: Vec<String>,
+}
+
+struct Converter {
+    /// Target files of the folder by lowercase file name
+    files: HashMap<String, PathBuf>,
+    /// Target files being resolved, to detect the compound targets including themselves
+    stack: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Converter {
+    fn warn(&mut self, message: String) {
+        self.warnings.push(message);
+    }
+
+    /// Convert a target file, the compound targets are resolved recursively
+    fn convert_file(&mut self, file_name: &str) -> Vec<Value> {
+        let key = file_name.to_lowercase();
+        let Some(path) = self.files.get(&key).cloned() else {
+            self.warn(format!("target file `{}` not found", file_name));
+            return Vec::new();
+        };
+        if self.stack.contains(&key) {
+            self.warn(format!(
+                "target file `{}` includes itself through {}, ignored",
+                file_name,
+                self.stack.join(" -> ")
+            ));
+            return Vec::new();
+        }
+
+        let document: Value = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| serde_yaml::from_str(&data).map_err(|e| e.to_string()))
+        {
+            Ok(document) => document,
+            Err(e) => {
+                self.warn(format!("target file `{}` can't be read: {}", path.display(), e));
+                return Vec::new();
+            }
+        };
+        let Some(Value::Sequence(targets)) = document.get("Targets") else {
+            self.warn(format!("target file `{}` has no `Targets`", file_name));
+            return Vec::new();
+        };
+
+        self.stack.push(key);
+        let mut search_configs = Vec::new();
+        for target in targets {
+            let Value::Mapping(target) = target else {
+                continue;
+            };
+            let target_path = field_str(target, "Path").unwrap_or_default();
+            if target_path.to_lowercase().ends_with(&format!(".{}", TARGET_EXTENSION)) {
+                let included = target_path.rsplit(['\\', '/']).next().unwrap_or(&target_path).to_string();
+                search_configs.extend(self.convert_file(&included));
+            } else if let Some(search_config) = self.convert_target(file_name, target) {
+                search_configs.push(search_config);
+            }
+        }
+        self.stack.pop();
+        search_configs
+    }
+
+    /// Convert a target collecting files to an entry of aralez, `None` if it can't be expressed
+    fn convert_target(&mut self, file_name: &str, target: &Mapping) -> Option<Value> {
+        let name = field_str(target, "Name").unwrap_or_else(|| "unnamed".to_string());
+        for field in target.keys().filter_map(Value::as_str) {
+            if !KNOWN_FIELDS.contains(&field) {
+                self.warn(format!(
+                    "`{}` of target `{}` in `{}` is not supported, ignored",
+                    field, name, file_name
+                ));
+            }
+        }
+
+        let Some(path) = field_str(target, "Path") else {
+            self.warn(format!("target `{}` in `{}` has no `Path`, ignored", name, file_name));
+            return None;
+        };
+        let recursive = field_bool(target, "Recursive");
+        let mut root_path = convert_path(&path);
+        let mask = match field_str(target, "FileMask") {
+            Some(mask) => mask,
+            // Old targets give the file in `Path` when it is not a directory
+            None if target.get("IsDirectory").is_some() && !field_bool(target, "IsDirectory") => {
+                let (parent, file) = root_path.rsplit_once('\\').unwrap_or(("", root_path.as_str()));
+                let file = file.to_string();
+                root_path = if parent.is_empty() { "\\".to_string() } else { parent.to_string() };
+                file
+            }
+            None => "*".to_string(),
+        };
+
+        let mut search_config = Mapping::new();
+        search_config.insert("root_path".into(), root_path.clone().into());
+        match mask.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => {
+                // KAPE matches the regex on the file name, aralez on the whole path
+                let start = regex.strip_prefix('^').map_or_else(|| format!("\\\\[^\\\\]*{}", regex), |r| format!("\\\\{}", r));
+                let pattern = if start.ends_with('$') { start } else { format!("{}[^\\\\]*$", start) };
+                self.warn(format!(
+                    "regex `{}` of target `{}` in `{}` converted to `{}` on the whole path",
+                    regex, name, file_name, pattern
+                ));
+                search_config.insert("type".into(), "regex".into());
+                search_config.insert("objects".into(), Value::Sequence(vec![pattern.into()]));
+                if !recursive {
+                    search_config.insert("max_depth".into(), 0.into());
+                }
+            }
+            None => {
+                let object = match (recursive, mask.as_str()) {
+                    (true, "*") | (true, "*.*") => "**".to_string(),
+                    (true, mask) => format!("**\\{}", mask),
+                    (false, mask) => mask.to_string(),
+                };
+                search_config.insert("objects".into(), Value::Sequence(vec![object.into()]));
+            }
+        }
+
+        // Check the entry like the loader does
+        let search_config = Value::Mapping(search_config);
+        let mut entry = Mapping::new();
+        entry.insert(name.clone().into(), Value::Sequence(vec![search_config.clone()]));
+        if let Err(e) = serde_yaml::from_value::<Entries>(Value::Mapping(entry)) {
+            self.warn(format!(
+                "target `{}` in `{}` can't be converted: {}",
+                name,
+                file_name,
+                e.to_string().replace("[ERROR] Config: ", "")
+            ));
+            return None;
+        }
+        Some(search_config)
+    }
+}
+
+fn field_str(target: &Mapping, field: &str) -> Option<String> {
+    match target.get(field)? {
+        Value::String(value) => Some(value.trim().to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn field_bool(target: &Mapping, field: &str) -> bool {
+    match target.get(field) {
+        Some(Value::Bool(value)) => *value,
+        Some(Value::String(value)) => value.eq_ignore_ascii_case("true"),
+        _ => false,
+    }
+}
+
+/// Convert a KAPE path (`C:\Users\%user%\AppData`) to a `root_path` of the volume (`\Users\*\AppData`)
+fn convert_path(path: &str) -> String {
+    let path = match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => &path[2..],
+        _ => path,
+    };
+    let mut converted = path.replace('/', "\\");
+    for variable in ["%user%", "%users%"] {
+        while let Some(start) = converted.to_lowercase().find(variable) {
+            converted.replace_range(start..start + variable.len(), "*");
+        }
+    }
+    format!("\\{}", converted.trim_matches('\\'))
+}
+
+/// Entry name from the name of a target file (`EventLogs-RDP` gives `eventlogs_rdp`)
+fn entry_name(file_name: &str) -> String {
+    let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+    let name: String = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    name.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_")
+}
+
+/// List the target files of a folder and its subfolders
+fn list_target_files(dir: &Path, files: &mut HashMap<String, PathBuf>, warnings: &mut Vec<String>) -> Result<()> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            list_target_files(&path, files, warnings)?;
+            continue;
+        }
+        let is_target = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case(TARGET_EXTENSION));
+        if !is_target {
+            continue;
+        }
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+        if let Some(existing) = files.get(&file_name) {
+            warnings.push(format!(
+                "`{}` has the same name as `{}`, ignored",
+                path.display(),
+                existing.display()
+            ));
+            continue;
+        }
+        files.insert(file_name, path);
+    }
+    Ok(())
+}
+
+/// Convert KAPE targets of a folder to an aralez configuration with one collect task,
+/// each target being an entry. Every target of the folder is converted without `targets`.
+pub fn convert_kape(dir: &Path, targets: &[String]) -> Result<Conversion> {
+    let mut converter = Converter {
+        files: HashMap::new(),
+        stack: Vec::new(),
+        warnings: Vec::new(),
+    };
+    list_target_files(dir, &mut converter.files, &mut converter.warnings)?;
+    if converter.files.is_empty() {
+        return Err(anyhow::anyhow!("[ERROR] No `.{}` file found in `{}`", TARGET_EXTENSION, dir.display()));
+    }
+
+    let selected: Vec<String> = if targets.is_empty() {
+        let mut names: Vec<String> = converter
+            .files
+            .values()
+            .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    } else {
+        targets
+            .iter()
+            .map(|target| {
+                if target.to_lowercase().ends_with(&format!(".{}", TARGET_EXTENSION)) {
+                    target.clone()
+                } else {
+                    format!("{}.{}", target, TARGET_EXTENSION)
+                }
+            })
+            .collect()
+    };
+
+    let mut entries = Mapping::new();
+    for file_name in &selected {
+        let mut search_configs = Vec::new();
+        for search_config in converter.convert_file(file_name) {
+            if !search_configs.contains(&search_config) {
+                search_configs.push(search_config);
+            }
+        }
+        if search_configs.is_empty() {
+            converter.warn(format!("target file `{}` has nothing to collect, ignored", file_name));
+            continue;
+        }
+        let mut name = entry_name(file_name);
+        let mut suffix = 2;
+        while entries.contains_key(name.as_str()) {
+            name = format!("{}_{}", entry_name(file_name), suffix);
+            suffix += 1;
+        }
+        entries.insert(name.into(), Value::Sequence(search_configs));
+    }
+    if entries.is_empty() {
+        return Err(anyhow::anyhow!("[ERROR] No KAPE target could be converted"));
+    }
+
+    let mut task = Mapping::new();
+    task.insert("priority".into(), 1.into());
+    task.insert("type".into(), "collect".into());
+    task.insert("output_folder".into(), "{{root_output_path}}\\{{drive}}".into());
+    let entry_count = entries.len();
+    task.insert("entries".into(), Value::Mapping(entries));
+    let mut tasks = Mapping::new();
+    tasks.insert(TASK_NAME.into(), Value::Mapping(task));
+    let mut config = Mapping::new();
+    config.insert("tasks".into(), Value::Mapping(tasks));
+    config.insert("output_filename".into(), "Aralez_{{hostname}}_{{datetime}}".into());
+
+    // Parse the result like the loader, so only a valid configuration is written
+    let config: Config = serde_yaml::from_value(Value::Mapping(config))?;
+    Ok(Conversion {
+        config: format!(
+            "# Converted from the KAPE targets of `{}`\n{}",
+            dir.display(),
+            config.to_yaml()?
+        ),
+        entries: entry_count,
+        warnings: converter.warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(target: &str) -> (Option<Value>, Vec<String>) {
+        let mut converter = Converter {
+            files: HashMap::new(),
+            stack: Vec::new(),
+            warnings: Vec::new(),
+        };
+        let target: Mapping = serde_yaml::from_str(target).unwrap();
+        let search_config = converter.convert_target("Test.tkape", &target);
+        (search_config, converter.warnings)
+    }
+
+    fn search_config(yaml: &str) -> Value {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn converts_file_masks_to_globs() {
+        for (target, expected) in [
+            (
+                "{Name: Logs, Path: 'C:\\Windows\\System32\\winevt\\Logs', FileMask: '*.evtx'}",
+                "{root_path: '\\Windows\\System32\\winevt\\Logs', objects: ['*.evtx']}",
+            ),
+            (
+                "{Name: Logs, Path: 'C:\\Windows\\System32\\winevt\\Logs\\', FileMask: '*.evtx', Recursive: true}",
+                "{root_path: '\\Windows\\System32\\winevt\\Logs', objects: ['**\\*.evtx']}",
+            ),
+            (
+                "{Name: Tasks, Path: 'C:\\Windows\\System32\\Tasks', Recursive: true}",
+                "{root_path: '\\Windows\\System32\\Tasks', objects: ['**']}",
+            ),
+            (
+                "{Name: Tasks, Path: 'C:\\Windows\\System32\\Tasks', FileMask: '*.*', Recursive: 'true'}",
+                "{root_path: '\\Windows\\System32\\Tasks', objects: ['**']}",
+            ),
+            (
+                "{Name: History, Path: 'C:\\Users\\%user%\\AppData\\Local\\Google\\Chrome\\User Data\\*\\', FileMask: History}",
+                "{root_path: '\\Users\\*\\AppData\\Local\\Google\\Chrome\\User Data\\*', objects: ['History']}",
+            ),
+            (
+                "{Name: SRUM, Path: 'C:\\Windows\\System32\\SRU\\SRUDB.dat', IsDirectory: false}",
+                "{root_path: '\\Windows\\System32\\SRU', objects: ['SRUDB.dat']}",
+            ),
+        ] {
+            let (converted, warnings) = convert(target);
+            assert_eq!(converted, Some(search_config(expected)), "{}", target);
+            assert!(warnings.is_empty(), "{:?}", warnings);
+        }
+    }
+
+    #[test]
+    fn converts_regex_masks_on_the_whole_path() {
+        let (converted, warnings) = convert("{Name: Prefetch, Path: 'C:\\Windows\\Prefetch', FileMask: 'regex:^.+\\.pf$'}");
+        assert_eq!(
+            converted,
+            Some(search_config(
+                "{root_path: '\\Windows\\Prefetch', type: regex, objects: ['\\\\.+\\.pf$'], max_depth: 0}"
+            ))
+        );
+        assert_eq!(warnings.len(), 1);
+
+        let (converted, _) = convert("{Name: Logs, Path: 'C:\\Logs', FileMask: 'regex:log', Recursive: true}");
+        assert_eq!(
+            converted,
+            Some(search_config("{root_path: '\\Logs', type: regex, objects: ['\\\\[^\\\\]*log[^\\\\]*$']}"))
+        );
+    }
+
+    #[test]
+    fn warns_about_unsupported_targets() {
+        let (converted, warnings) = convert("{Name: NoPath, FileMask: '*'}");
+        assert_eq!(converted, None);
+        assert_eq!(warnings, ["target `NoPath` in `Test.tkape` has no `Path`, ignored"]);
+
+        let (converted, warnings) = convert("{Name: Size, Path: 'C:\\Logs', MinSize: 10}");
+        assert!(converted.is_some());
+        assert_eq!(warnings, ["`MinSize` of target `Size` in `Test.tkape` is not supported, ignored"]);
+    }
+
+    #[test]
+    fn converts_paths_and_names() {
+        assert_eq!(convert_path("C:\\Users\\%user%\\NTUSER.DAT"), "\\Users\\*\\NTUSER.DAT");
+        assert_eq!(convert_path("%users%/AppData/"), "\\*\\AppData");
+        assert_eq!(convert_path("C:"), "\\");
+        assert_eq!(entry_name("EventLogs-RDP.tkape"), "eventlogs_rdp");
+        assert_eq!(entry_name("$MFT.tkape"), "mft");
+    }
+}
@@ -0,0 +1,675 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::compose::resolve_config;
+use crate::config::{Config, TypeExec, TypeTasks};
+use crate::execute::{get_list_internal_tools, get_list_tools};
+use crate::matcher::{full_pattern, split_stream};
+use crate::resource::list_resources;
+use crate::profiles::expand_with_placeholder;
+use regex::Regex;
+use serde_yaml::Value;
+use std::fmt;
+use std::path::Path;
+
+/// Folder names of the usual artifact locations, to catch misspelled `root_path`
+const KNOWN_FOLDERS: [&str; 59] = [
+    "$Extend", "$Recycle.Bin", "AppCompat", "AppData", "Chrome", "config", "Default", "Desktop", "Documents",
+    "Downloader", "Downloads", "drivers", "Edge", "etc", "Firefox", "Firewall", "Google", "inetpub",
+    "INetCache", "Local", "LocalLow", "LogFiles", "Logs", "Microsoft", "Mozilla", "Network", "PowerShell",
+    "Prefetch", "Profiles", "ProgramData", "Program Files", "Program Files (x86)", "Programs", "PSReadline",
+    "Recent", "ReportArchive", "ReportQueue", "Repository", "Roaming", "Sysmon", "spool", "Start Menu",
+    "Startup", "System Volume Information", "System32", "SysWOW64", "Tasks", "Temp", "User Data", "Users", "wbem",
+    "wdi", "WER", "winevt", "Windows", "WMI", "Exchange Server", "inetsrv", "Sysnative",
+];
+
+/// Extensions of a `root_path` that looks like a file
+const FILE_EXTENSIONS: [&str; 15] = [
+    "dat", "db", "sqlite", "log", "evtx", "etl", "exe", "dll", "txt", "json", "xml", "pf", "lnk", "hve", "job",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem of the configuration, with its position in the YAML file when it is known
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{}: {}: {}", line, column, severity, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Lines and columns of the keys of a block style YAML file, by their path (e.g. `tasks.artifacts.entries.mft.0`)
+struct Locator {
+    keys: Vec<(String, usize, usize)>,
+    lines: Vec<String>,
+}
+
+impl Locator {
+    fn new(text: &str) -> Self {
+        let key_regex = Regex::new(r#"^(?:"([^"]*)"|'([^']*)'|([^\s:"'#\[\]{},-][^:#]*?))\s*:(?:\s|$)"#).unwrap();
+        // Indentation, path segment and if the segment is a sequence item
+        let mut stack: Vec<(usize, String, bool)> = Vec::new();
+        let mut keys = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+                continue;
+            }
+            let mut indent = line.len() - trimmed.len();
+            let mut rest = trimmed;
+
+            while let Some(after) = rest.strip_prefix('-').filter(|a| a.is_empty() || a.starts_with(' ')) {
+                let mut index = 0;
+                while let Some((last_indent, segment, is_item)) = stack.last() {
+                    if *last_indent > indent {
+                        stack.pop();
+                    } else if *last_indent == indent && *is_item {
+                        index = segment.parse::<usize>().unwrap_or(0) + 1;
+                        stack.pop();
+                    } else {
+                        break;
+                    }
+                }
+                stack.push((indent, index.to_string(), true));
+                let content = after.trim_start();
+                indent += 1 + after.len() - content.len();
+                rest = content;
+            }
+
+            if let Some(captures) = key_regex.captures(rest) {
+                let key = captures
+                    .get(1)
+                    .or_else(|| captures.get(2))
+                    .or_else(|| captures.get(3))
+                    .map_or("", |m| m.as_str())
+                    .trim()
+                    .to_string();
+                while stack.last().is_some_and(|(last_indent, _, _)| *last_indent >= indent) {
+                    stack.pop();
+                }
+                stack.push((indent, key, false));
+                let path: Vec<&str> = stack.iter().map(|(_, segment, _)| segment.as_str()).collect();
+                keys.push((path.join("."), number + 1, indent + 1));
+            }
+        }
+
+        Locator {
+            keys,
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+
+    /// Position of a key, or of its closest parent found
+    fn find(&self, path: &[&str]) -> Option<(usize, usize)> {
+        for length in (1..=path.len()).rev() {
+            let wanted = path[..length].join(".");
+            if let Some((_, line, column)) = self.keys.iter().find(|(p, _, _)| *p == wanted) {
+                return Some((*line, *column));
+            }
+        }
+        None
+    }
+
+    /// Position of a key, `None` if it is not in the file (e.g. a key of an included file)
+    fn find_exact(&self, path: &[&str]) -> Option<(usize, usize)> {
+        let wanted = path.join(".");
+        self.keys.iter().find(|(p, _, _)| *p == wanted).map(|(_, line, column)| (*line, *column))
+    }
+
+    /// Position of a quoted value of a key (e.g. an object of `objects: ["*.evtx"]`), the key otherwise
+    fn find_value(&self, path: &[&str], value: &str) -> Option<(usize, usize)> {
+        let (line, column) = self.find(path)?;
+        let next_key_line = self
+            .keys
+            .iter()
+            .map(|(_, l, _)| *l)
+            .filter(|l| *l > line)
+            .min()
+            .unwrap_or(self.lines.len() + 1);
+        for number in line..next_key_line {
+            let text = &self.lines[number - 1];
+            for written in [format!("\"{}\"", value.replace('\\', "\\\\")), format!("'{}'", value)] {
+                if let Some(position) = text.find(&written) {
+                    return Some((number, position + 1));
+                }
+            }
+        }
+        Some((line, column))
+    }
+}
+
+struct Linter {
+    locator: Locator,
+    /// JSON Schema of the configuration, for the known keys
+    schema: serde_json::Value,
+    findings: Vec<Finding>,
+}
+
+impl Linter {
+    fn report(&mut self, severity: Severity, location: Option<(usize, usize)>, message: String) {
+        self.findings.push(Finding { severity, location, message });
+    }
+
+    /// Keys of the top level (without definition) or of a definition of the schema
+    fn schema_keys(&self, definition: Option<&str>) -> Vec<String> {
+        let schema = match definition {
+            Some(definition) => &self.schema["definitions"][definition],
+            None => &self.schema,
+        };
+        schema["properties"]
+            .as_object()
+            .map(|properties| properties.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Report the keys of a mapping that are not part of the configuration
+    fn check_keys(&mut self, value: &Value, definition: Option<&str>, path: &[&str], kind: &str) {
+        let Value::Mapping(mapping) = value else {
+            return;
+        };
+        let known = self.schema_keys(definition);
+        if known.is_empty() {
+            return;
+        }
+        for key in mapping.keys() {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
+            if !known.iter().any(|k| k == key) {
+                let mut key_path = path.to_vec();
+                key_path.push(key);
+                let location = self.locator.find_exact(&key_path);
+                let suggestion = known
+                    .iter()
+                    .find(|k| levenshtein(&k.to_lowercase(), &key.to_lowercase()) <= 2)
+                    .map(|k| format!(", did you mean `{}`?", k))
+                    .unwrap_or_default();
+                self.report(
+                    Severity::Error,
+                    location,
+                    format!("unknown {} key `{}`{}", kind, key, suggestion),
+                );
+            }
+        }
+    }
+
+    fn check_unknown_keys(&mut self, root: &Value) {
+        self.check_keys(root, None, &[], "top level");
+        let Some(Value::Mapping(tasks)) = root.get("tasks") else {
+            return;
+        };
+        for (task_name, task) in tasks {
+            let Some(task_name) = task_name.as_str() else {
+                continue;
+            };
+            self.check_keys(task, Some("SectionConfig"), &["tasks", task_name], "task");
+            if let Some(when) = task.get("when") {
+                self.check_when_keys(when, &["tasks", task_name, "when"]);
+            }
+            let Some(Value::Mapping(entries)) = task.get("entries") else {
+                continue;
+            };
+            for (entry_name, items) in entries {
+                let (Some(entry_name), Value::Sequence(items)) = (entry_name.as_str(), items) else {
+                    continue;
+                };
+                for (index, item) in items.iter().enumerate() {
+                    let index = index.to_string();
+                    let path = ["tasks", task_name, "entries", entry_name, index.as_str()];
+                    self.check_keys(item, Some("SearchConfig"), &path, "entry");
+                    if let Some(when) = item.get("when") {
+                        let mut when_path = path.to_vec();
+                        when_path.push("when");
+                        self.check_when_keys(when, &when_path);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_when_keys(&mut self, when: &Value, path: &[&str]) {
+        self.check_keys(when, Some("Condition"), path, "`when`");
+        if let Some(registry) = when.get("registry") {
+            let mut registry_path = path.to_vec();
+            registry_path.push("registry");
+            self.check_keys(registry, Some("RegistryCondition"), &registry_path, "`registry`");
+        }
+    }
+
+    fn check_collect_task(&mut self, task_name: &str, config: &Config) {
+        let task = &config.tasks[task_name];
+        let literal_files = literal_files(config);
+        // Full pattern, stream, entry name and object of the rules already seen
+        let mut rules: Vec<(String, String, String, String)> = Vec::new();
+
+        for (entry_name, search_configs) in &task.entries {
+            for (index, search_config) in search_configs.iter().enumerate() {
+                let index = index.to_string();
+                let path = ["tasks", task_name, "entries", entry_name.as_str(), index.as_str()];
+                let root_path = search_config.root_path.clone().unwrap_or_default();
+                let mut root_path_path = path.to_vec();
+                root_path_path.push("root_path");
+                let root_location = self.locator.find(&root_path_path);
+
+                self.check_root_path(entry_name, &root_path, &literal_files, root_location);
+
+                if config.output_recipients.is_some() && search_config.encrypt.is_some() {
+                    let mut encrypt_path = path.to_vec();
+                    encrypt_path.push("encrypt");
+                    self.report(
+                        Severity::Warning,
+                        self.locator.find(&encrypt_path),
+                        format!(
+                            "`encrypt` of entry `{}` writes a password in the configuration, the archive is already encrypted to `output_recipients`",
+                            entry_name
+                        ),
+                    );
+                }
+
+                if search_config.is_regex() {
+                    continue;
+                }
+                let expanded_root = expand_with_placeholder(&search_config.get_expanded_root_path());
+                let mut objects_path = path.to_vec();
+                objects_path.push("objects");
+                for object in search_config.objects.iter().flatten() {
+                    let location = self.locator.find_value(&objects_path, object);
+                    let (pattern, stream) = split_stream(object);
+                    if let Some(message) = never_matches(pattern) {
+                        self.report(Severity::Warning, location, format!("object `{}` of entry `{}` {}", object, entry_name, message));
+                    }
+
+                    let full = full_pattern(&expanded_root, pattern);
+                    let stream = stream.to_lowercase();
+                    if let Some((_, _, other_entry, _)) = rules.iter().find(|(p, s, _, _)| *p == full && *s == stream) {
+                        self.report(
+                            Severity::Warning,
+                            location,
+                            format!("object `{}` of entry `{}` duplicates entry `{}`", object, entry_name, other_entry),
+                        );
+                        continue;
+                    }
+                    for (other_full, _, other_entry, other_object) in &rules {
+                        if covers(other_full, &full) || covers(&full, other_full) {
+                            self.report(
+                                Severity::Warning,
+                                location,
+                                format!(
+                                    "object `{}` of entry `{}` overlaps object `{}` of entry `{}`, the files are collected once with the settings of the first entry",
+                                    object, entry_name, other_object, other_entry
+                                ),
+                            );
+                        }
+                    }
+                    rules.push((full, stream, entry_name.clone(), object.clone()));
+                }
+            }
+        }
+    }
+
+    fn check_root_path(
+        &mut self,
+        entry_name: &str,
+        root_path: &str,
+        literal_files: &[(String, String, String)],
+        location: Option<(usize, usize)>,
+    ) {
+        let components: Vec<&str> = root_path.split('\\').filter(|c| !c.is_empty()).collect();
+
+        for component in &components {
+            if component.contains(['*', '?', '[', '{', '%']) {
+                continue;
+            }
+            if KNOWN_FOLDERS.iter().any(|known| known.eq_ignore_ascii_case(component)) {
+                continue;
+            }
+            if let Some(known) = misspelled_folder(component) {
+                self.report(
+                    Severity::Warning,
+                    location,
+                    format!(
+                        "root_path `{}` of entry `{}`: `{}` looks like a misspelling of `{}`",
+                        root_path, entry_name, component, known
+                    ),
+                );
+            }
+        }
+
+        let Some(last) = components.last() else {
+            return;
+        };
+        if let Some(parent) = components.len().checked_sub(2).map(|i| components[i]) {
+            if let Some((_, _, other_entry)) = literal_files
+                .iter()
+                .find(|(folder, file, _)| folder.eq_ignore_ascii_case(parent) && file.eq_ignore_ascii_case(last))
+            {
+                self.report(
+                    Severity::Warning,
+                    location,
+                    format!(
+                        "root_path `{}` of entry `{}` ends with `{}`, which entry `{}` collects as a file; objects are searched inside root_path",
+                        root_path, entry_name, last, other_entry
+                    ),
+                );
+                return;
+            }
+        }
+        if let Some((_, extension)) = last.rsplit_once('.') {
+            if FILE_EXTENSIONS.contains(&extension.to_lowercase().as_str()) {
+                self.report(
+                    Severity::Warning,
+                    location,
+                    format!(
+                        "root_path `{}` of entry `{}` ends with `{}`, which looks like a file; objects are searched inside root_path",
+                        root_path, entry_name, last
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_execute_task(&mut self, task_name: &str, config: &Config) {
+        let task = &config.tasks[task_name];
+        let internal_tools = get_list_internal_tools();
+        let embedded_tools: Vec<String> = get_list_tools()
+            .iter()
+            .map(|tool| tool.to_string())
+            .chain(list_resources(10).unwrap_or_default())
+            .collect();
+
+        for (entry_name, executors) in &task.entries {
+            for (index, executor) in executors.iter().enumerate() {
+                let index = index.to_string();
+                let path = ["tasks", task_name, "entries", entry_name.as_str(), index.as_str()];
+                let mut name_path = path.to_vec();
+                name_path.push("name");
+                let location = self.locator.find(&name_path);
+                let Some(name) = &executor.name else {
+                    self.report(Severity::Error, location, format!("entry `{}` of task `{}` has no `name`", entry_name, task_name));
+                    continue;
+                };
+                match executor.exec_type {
+                    Some(TypeExec::Internal) if !internal_tools.iter().any(|tool| tool == name) => self.report(
+                        Severity::Error,
+                        location,
+                        format!("unknown internal tool `{}`, expected one of {}", name, internal_tools.join(", ")),
+                    ),
+                    Some(TypeExec::External) if !embedded_tools.iter().any(|tool| tool.eq_ignore_ascii_case(name)) => self.report(
+                        Severity::Error,
+                        location,
+                        format!("external tool `{}` is not embedded, add it with `--add_tool`", name),
+                    ),
+                    None => self.report(
+                        Severity::Error,
+                        location,
+                        format!("entry `{}` of task `{}` has no `exec_type`", entry_name, task_name),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Literal file names collected by the entries, with the last folder of their root_path and the entry name
+fn literal_files(config: &Config) -> Vec<(String, String, String)> {
+    let mut files = Vec::new();
+    for task in config.tasks.values() {
+        for (entry_name, search_configs) in &task.entries {
+            for search_config in search_configs.iter().filter(|s| !s.is_regex()) {
+                let root_path = search_config.root_path.clone().unwrap_or_default();
+                let Some(folder) = root_path.split('\\').rfind(|c| !c.is_empty()) else {
+                    continue;
+                };
+                for object in search_config.objects.iter().flatten() {
+                    if !object.contains(['*', '?', '[', '{', '\\', '/', ':']) {
+                        files.push((folder.to_string(), object.clone(), entry_name.clone()));
+                    }
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Explain why an object can never match a file, `None` if it can
+fn never_matches(object: &str) -> Option<String> {
+    if object.trim().is_empty() {
+        return Some("is empty and never matches".to_string());
+    }
+    let has_wildcard = object.contains(['*', '?', '[', '{']);
+    if object.starts_with('.') && object.len() > 1 && !has_wildcard && !object.contains(['\\', '/']) {
+        return Some(format!(
+            "matches only a file named `{}`, did you mean `*{}`?",
+            object, object
+        ));
+    }
+    if object.as_bytes().get(1) == Some(&b':') {
+        return Some("has a drive letter, objects are relative to root_path".to_string());
+    }
+    if object.ends_with(['\\', '/']) {
+        return Some("ends with a separator and never matches a file".to_string());
+    }
+    None
+}
+
+/// Check if a full pattern ending with `/**` collects the files of another pattern
+fn covers(pattern: &str, other: &str) -> bool {
+    let Some(folder) = pattern.strip_suffix("/**") else {
+        return false;
+    };
+    pattern != other
+        && !folder.contains(['*', '?', '[', '{'])
+        && other.starts_with(&format!("{}/", folder))
+}
+
+/// Known folder an unknown folder name is a misspelling of, `None` when it is close to none
+/// or to several known names, or when it is the start of a known name (e.g. `System` and `System32`)
+fn misspelled_folder(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    if KNOWN_FOLDERS.iter().any(|known| known.to_lowercase().starts_with(&name)) {
+        return None;
+    }
+    let mut close = KNOWN_FOLDERS.iter().filter(|known| {
+        let known = known.to_lowercase();
+        let distance = levenshtein(&name, &known);
+        (known.len() >= 5 && distance <= 1) || (known.len() >= 8 && distance <= 2)
+    });
+    match (close.next(), close.next()) {
+        (Some(known), None) => Some(known),
+        _ => None,
+    }
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Lint a configuration, the included files being relative to `base_dir`
+pub fn lint_config(text: &str, base_dir: &Path) -> Vec<Finding> {
+    let mut linter = Linter {
+        locator: Locator::new(text),
+        schema: Config::schema().unwrap_or_default(),
+        findings: Vec::new(),
+    };
+
+    let root: Value = match serde_yaml::from_str(text) {
+        Ok(root) => root,
+        Err(e) => {
+            let location = e.location().map(|l| (l.line(), l.column()));
+            linter.report(Severity::Error, location, e.to_string());
+            return linter.findings;
+        }
+    };
+    // The keys of the included files are checked too, they have no position in this file
+    match resolve_config(text, base_dir) {
+        Ok(composed) => linter.check_unknown_keys(&composed),
+        Err(e) => {
+            linter.report(Severity::Error, None, e.to_string().replace("[ERROR] Config: ", ""));
+            return linter.findings;
+        }
+    }
+    // The loader stops at the first unknown key, they are all reported above
+    if !linter.findings.is_empty() {
+        return linter.findings;
+    }
+
+    let config = match Config::from_yaml(text, base_dir) {
+        Ok(config) => config,
+        Err(e) => {
+            // Without composition, parsing the text again gives the position of the error
+            let composed = root.get("include").is_some() || root.get("extends").is_some();
+            let location = match serde_yaml::from_str::<Config>(text) {
+                Err(e) if !composed => e.location().map(|l| (l.line(), l.column())),
+                _ => None,
+            };
+            let message = e.to_string().replace("[ERROR] Config: ", "");
+            linter.report(Severity::Error, location, message);
+            return linter.findings;
+        }
+    };
+
+    for (task_name, task) in config.tasks.iter().filter(|(_, task)| task.disabled != Some(true)) {
+        match task.r#type {
+            TypeTasks::Collect => linter.check_collect_task(task_name, &config),
+            TypeTasks::Execute => linter.check_execute_task(task_name, &config),
+        }
+    }
+
+    linter.findings
+}
+
+/// Print the findings of the linter, return the exit code: 0 without findings, 1 with errors, 2 with warnings only
+pub fn print_findings(source: &str, findings: &[Finding]) -> i32 {
+    for finding in findings {
+        println!("{}:{}", source, finding);
+    }
+    let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let warnings = findings.len() - errors;
+    if findings.is_empty() {
+        println!("The configuration file is valid");
+        return 0;
+    }
+    println!("{} errors, {} warnings", errors, warnings);
+    if errors > 0 {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn lint(text: &str) -> Vec<String> {
+        lint_config(text, Path::new(".")).iter().map(Finding::to_string).collect()
+    }
+
+    fn collect_config(root_path: &str, objects: &str) -> String {
+        format!(
+            "output_filename: out\ntasks:\n  files:\n    priority: 1\n    type: collect\n    entries:\n      entry:\n        - root_path: \"{}\"\n          objects: {}\n",
+            root_path.replace('\\', "\\\\"),
+            objects
+        )
+    }
+
+    #[test]
+    fn finds_misspelled_folders() {
+        for (name, known) in [
+            ("Repositry", "Repository"),
+            ("Prefecth", "Prefetch"),
+            ("windos", "Windows"),
+            ("Microsft", "Microsoft"),
+            ("Profils", "Profiles"),
+        ] {
+            assert_eq!(misspelled_folder(name), Some(known), "{}", name);
+        }
+    }
+
+    #[test]
+    fn ignores_folders_which_are_not_misspelled() {
+        // Starts of known names, names close to several known ones, short and unrelated names
+        for name in ["System", "Program", "Download", "Downloadz", "Logs2", "Temp1", "Reports", "Kaspersky", "Repsystemitory"] {
+            assert_eq!(misspelled_folder(name), None, "{}", name);
+        }
+        assert!(lint(&collect_config("\\Windows\\System", "[\"*.ini\"]")).is_empty());
+        assert!(lint(&collect_config("\\Program Files\\Vendor\\Logs", "[\"*.log\"]")).is_empty());
+    }
+
+    #[test]
+    fn reports_the_position_of_a_misspelled_root_path() {
+        assert_eq!(
+            lint(&collect_config("\\Windows\\Prefecth", "[\"*.pf\"]")),
+            ["8:11: warning: root_path `\\Windows\\Prefecth` of entry `entry`: `Prefecth` looks like a misspelling of `Prefetch`"]
+        );
+    }
+
+    #[test]
+    fn reports_objects_which_never_match() {
+        assert_eq!(
+            lint(&collect_config("\\Windows\\Tasks", "[\".job\", \"*.xml\"]")),
+            ["9:21: warning: object `.job` of entry `entry` matches only a file named `.job`, did you mean `*.job`?"]
+        );
+    }
+
+    #[test]
+    fn reports_passwords_of_an_encrypted_archive() {
+        let recipient = age::x25519::Identity::generate().to_public();
+        let text = collect_config("\\Windows\\Tasks", "[\"*.job\"]").replacen("objects:", "encrypt: \"infected\"\n          objects:", 1);
+        assert_eq!(lint(&text), Vec::<String>::new());
+        assert_eq!(
+            lint(&format!("{}output_recipients: [\"{}\"]\n", text, recipient)),
+            ["9:11: warning: `encrypt` of entry `entry` writes a password in the configuration, the archive is already encrypted to `output_recipients`"]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_keys_of_included_files() {
+        let dir = std::env::temp_dir().join(format!("aralez-lint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("extra.yml"),
+            "tasks:\n  files:\n    priority: 1\n    type: collect\n    entries:\n      entry:\n        - root_path: \"\\\\Temp\"\n          object: [\"*\"]\n",
+        )
+        .unwrap();
+        let text = format!("include: [extra.yml]\n{}", collect_config("\\Windows\\Tasks", "[\"*.job\"]").replacen("entry:", "main:", 1));
+        let findings: Vec<String> = lint_config(&text, &dir).iter().map(Finding::to_string).collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(findings, ["error: unknown entry key `object`, did you mean `objects`?"]);
+    }
+}
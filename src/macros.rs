@@ -18,29 +18,16 @@
 macro_rules! dprintln {
     ($($arg:tt)*) => {
         {
-            use std::fs::OpenOptions;
-            use std::io::Write;
             use chrono::Local;
-            use crate::get_config;
-
-            // Retrieve dynamic log filename from the global config
-            let tmp_logfile = get_config().get_output_filename();
-            let mut file = OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&tmp_logfile)
-                .expect(&format!("[ERROR] Unable to open or create {}", tmp_logfile).as_str());
 
             // Get current timestamp
             let now = Local::now();
             let timestamp = now.format("%Y-%m-%d %H:%M:%S").to_string();
+            let line = format!("[{}] {}", timestamp, format!($($arg)*));
 
-            // Write log to file
-            writeln!(file, "[{}] {}", timestamp, format!($($arg)*)).expect(&format!("Unable to write to {}", tmp_logfile).as_str());
-
-            // Print to console only if debug mode is enabled
-            if cfg!(debug_assertions) || std::env::var("DEBUG_MODE").is_ok() {
-                println!("[{}] {}", timestamp, format!($($arg)*));
+            // Volumes collected in parallel keep their lines until the volume is done
+            if !$crate::utils::buffer_log_line(&line) {
+                $crate::utils::write_log_lines(&[line]);
             }
         }
     };
@@ -8,36 +8,50 @@
 #[macro_use]
 mod macros;
 
+mod archive;
+mod budget;
+mod conditions;
+mod compose;
 mod config;
+mod encryption;
 mod execute;
+mod ext4_reader;
+mod fat_reader;
+mod kape;
+mod filters;
+mod lint;
+mod matcher;
 mod ntfs_reader;
+mod partition_table;
+mod plan;
+mod profiles;
+mod scheduler;
 mod sector_reader;
 mod utils;
 mod resource;
+mod walker;
 
+use archive::{close_archive, create_output, open_archive};
+use budget::{save_skipped_files, set_global_budget, Budget};
+use encryption::decrypt_files;
 use execute::get_list_tools;
+use scheduler::{run_tasks, DEFAULT_MAX_PARALLEL_TASKS};
 use resource::{add_resource, list_resources, remove_resource};
 use anyhow::Result;
 use clap::Parser;
-use clap::{Arg, Command};
-use config::{get_config, set_config, Config, ExecType};
+use clap::{Arg, ArgMatches, Command};
+use config::{set_config, Config, ExecType, OutputFormat, SectionConfig, OUTPUT_FORMAT_NAMES};
 use execute::{get_bin, run, run_internal};
 use indicatif::{ProgressBar, ProgressStyle};
-use ntfs_reader::{process_all_drives, process_drive_artifacts};
+use kape::convert_kape;
+use lint::{lint_config, print_findings};
+use plan::{add_planned_tool, is_dry_run, save_plan, set_dry_run, set_plan_task};
+use ntfs_reader::{check_drive_condition, process_all_drives, process_drive_artifacts, process_image_artifacts};
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use std::env;
-use std::fs::{self, File};
-use std::io::{self, Write};
-use std::io::Seek;
+use std::fs;
 use std::path::Path;
-use utils::{ensure_directory_exists, remove_dir_all};
-use zip::{write::FileOptions, CompressionMethod, ZipWriter};
-use std::time::UNIX_EPOCH;
-use chrono::DateTime;
-use chrono::Utc;
-use zip::DateTime as ZipDateTime;
-use chrono::Datelike;
-use chrono::Timelike;
+use utils::{ensure_directory_exists, finish_archive_log, remove_dir_all, start_archive_log};
 
 #[derive(Parser)]
 struct Cli {
@@ -73,16 +87,90 @@ const TARGET_ARCH: &str = "x86_64";
 #[cfg(target_pointer_width = "32")]
 const TARGET_ARCH: &str = "x86";
 
-// Helper function to pretty-print the configuration
-fn show_config() -> Result<()> {
-    let data = Config::get_raw_data()?;
-    println!("{}", data);
+// Helper function to load the configuration of `--config` or the embedded one,
+// then apply the `--tasks`, `--skip_tasks` and `--var` options
+fn load_config(matches: &ArgMatches) -> Result<Config> {
+    let mut config = match matches.get_one::<String>("config") {
+        Some(config_path) => Config::check_config_file(config_path)?,
+        None => Config::load()?,
+    };
+    let tasks: Vec<String> = matches.get_many::<String>("tasks").unwrap_or_default().cloned().collect();
+    let skip_tasks: Vec<String> = matches.get_many::<String>("skip_tasks").unwrap_or_default().cloned().collect();
+    config.select_tasks(&tasks, &skip_tasks)?;
+
+    let mut vars = Vec::new();
+    for var in matches.get_many::<String>("var").unwrap_or_default() {
+        match var.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => vars.push((key.trim().to_string(), value.to_string())),
+            _ => return Err(anyhow::anyhow!("[ERROR] Variable `{}` must be given as KEY=VALUE", var)),
+        }
+    }
+    for key in config.set_vars(&vars)? {
+        println!("[WARN] Variable `{}` is not used by the configuration", key);
+    }
+    Ok(config)
+}
+
+// Helper function to pretty-print the resolved configuration (`extends` and `include` merged), with readable sizes
+fn show_config(matches: &ArgMatches) -> Result<()> {
+    let mut config = load_config(matches)?;
+    // Show the output format even when it is the default one
+    config.output_format.get_or_insert(OutputFormat::Zip);
+    println!(
+        "# output_format: {} (`compression_level` from 1 to 22 sets the zstd level of tar.zst)",
+        OUTPUT_FORMAT_NAMES.join(", ")
+    );
+    println!("{}", config.to_yaml()?);
+    Ok(())
+}
+
+// Helper function to print the JSON Schema of the configuration, for the YAML editors
+fn dump_schema() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&Config::schema()?)?);
     Ok(())
 }
 
-// Helper function to check the configuration
-fn check_config() -> Result<Config, anyhow::Error> {
-    Config::load()
+// Helper function to convert KAPE targets to a configuration, written in a file or printed
+fn convert_kape_targets(dir: &str, targets: &[String], output: Option<&String>) -> Result<()> {
+    let conversion = convert_kape(Path::new(dir), targets)?;
+    for warning in &conversion.warnings {
+        eprintln!("[WARN] {}", warning);
+    }
+    match output {
+        Some(output_path) => {
+            fs::write(output_path, &conversion.config)?;
+            println!(
+                "[INFO] {} entries converted to `{}` with {} warnings",
+                conversion.entries,
+                output_path,
+                conversion.warnings.len()
+            );
+        }
+        None => print!("{}", conversion.config),
+    }
+    Ok(())
+}
+
+// Helper function to decrypt the volumes and the index of an encrypted output
+fn decrypt_output(files: &[String], identity_path: &str, output_dir: Option<&String>) -> Result<()> {
+    if let Some(output_dir) = output_dir {
+        fs::create_dir_all(output_dir)?;
+    }
+    for (file, decrypted_path) in files.iter().zip(decrypt_files(files, identity_path, output_dir)?) {
+        println!("[INFO] `{}` decrypted to `{}`", file, decrypted_path);
+    }
+    Ok(())
+}
+
+// Helper function to lint a configuration file, or the embedded configuration without file, return the exit code
+fn check_config(config_path: &str) -> Result<i32, anyhow::Error> {
+    if config_path.is_empty() {
+        let config_data = Config::get_raw_data()?;
+        return Ok(print_findings("config.yml", &lint_config(&config_data, Path::new("."))));
+    }
+    let config_data = fs::read_to_string(config_path)?;
+    let base_dir = Path::new(config_path).parent().unwrap_or(Path::new("."));
+    Ok(print_findings(config_path, &lint_config(&config_data, base_dir)))
 }
 
 /// Helper function to check if the drive exists
@@ -115,17 +203,66 @@ fn main() -> Result<(), anyhow::Error> {
                 .help("Show the configuration file and exit")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("dump_schema")
+                .long("dump_schema")
+                .help("Print the JSON Schema of the configuration file and exit")
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("check_config")
                 .long("check_config")
-                .help("Check the configuration file")
-                .action(clap::ArgAction::SetTrue),
+                .help("Check a configuration file, the `--config` or embedded one by default; exit with 1 on errors and 2 on warnings")
+                .value_name("CONFIG_FILE")
+                .value_hint(clap::ValueHint::FilePath)
+                .num_args(0..=1)
+                .default_missing_value("")
+                .required(false),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Load the configuration from a file instead of the embedded one")
+                .value_name("CONFIG_FILE")
+                .value_hint(clap::ValueHint::FilePath)
+                .required(false),
+        )
+        .arg(
+            Arg::new("tasks")
+                .long("tasks")
+                .help("Run only these tasks, even the disabled ones")
+                .value_name("TASK")
+                .value_delimiter(',')
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("skip_tasks")
+                .long("skip_tasks")
+                .help("Don't run these tasks")
+                .value_name("TASK")
+                .value_delimiter(',')
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("Write the output in this folder instead of the current one")
+                .value_name("OUTPUT_DIR")
+                .value_hint(clap::ValueHint::DirPath)
+                .required(false),
+        )
+        .arg(
+            Arg::new("var")
+                .long("var")
+                .help("Replace the `{{KEY}}` variables of the configuration by VALUE")
+                .value_name("KEY=VALUE")
+                .action(clap::ArgAction::Append),
         )
         .arg(
             Arg::new("change_config")
                 .long("change_config")
                 .help("Change the embedded configuration file")
-                .value_names(&["CONFIG_FILE", "OUTPUT_FILE"])
+                .value_names(["CONFIG_FILE", "OUTPUT_FILE"])
                 .value_hint(clap::ValueHint::FilePath)
                 .num_args(2)
                 .required(false),
@@ -134,7 +271,7 @@ fn main() -> Result<(), anyhow::Error> {
             Arg::new("add_tool")
                 .long("add_tool")
                 .help("Add a new executable tool to the resources")
-                .value_names(&["EXECUTABLE_TOOL_PATH", "OUTPUT_FILE"])
+                .value_names(["EXECUTABLE_TOOL_PATH", "OUTPUT_FILE"])
                 .value_hint(clap::ValueHint::FilePath)
                 .num_args(2)
                 .required(false),
@@ -143,32 +280,118 @@ fn main() -> Result<(), anyhow::Error> {
             Arg::new("remove_tool")
                 .long("remove_tool")
                 .help("Remove an executable tool to the resources")
-                .value_names(&["EXECUTABLE_TOOL_NAME", "OUTPUT_FILE"])
+                .value_names(["EXECUTABLE_TOOL_NAME", "OUTPUT_FILE"])
                 .value_hint(clap::ValueHint::Other)
                 .num_args(2)
                 .required(false),
         )
+        .arg(
+            Arg::new("dry_run")
+                .long("dry_run")
+                .help("List the files and the tools of the collection without copying or running them, only the plan file is written")
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("list_tools")
                 .long("list_tools")
                 .help("List all external tools")
                 .action(clap::ArgAction::SetTrue),
         )
+        .subcommand(
+            Command::new("convert")
+                .about("Convert the collection definitions of other tools to a configuration file")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("kape")
+                        .about("Convert KAPE targets, the compound targets being resolved")
+                        .arg(
+                            Arg::new("dir")
+                                .help("Folder of the `.tkape` files")
+                                .value_name("DIR")
+                                .value_hint(clap::ValueHint::DirPath)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::new("targets")
+                                .help("Targets to convert, every target of the folder by default")
+                                .value_name("TARGET")
+                                .num_args(0..),
+                        )
+                        .arg(
+                            Arg::new("output")
+                                .long("output")
+                                .short('o')
+                                .help("Write the configuration in a file instead of printing it")
+                                .value_name("OUTPUT_FILE")
+                                .value_hint(clap::ValueHint::FilePath),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("decrypt")
+                .about("Decrypt an output encrypted to `output_recipients` with the private key of a recipient")
+                .arg(
+                    Arg::new("files")
+                        .help("Encrypted volumes and index of the output")
+                        .value_name("FILE")
+                        .value_hint(clap::ValueHint::FilePath)
+                        .num_args(1..)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("identity")
+                        .long("identity")
+                        .short('i')
+                        .help("age identity file or unencrypted SSH private key")
+                        .value_name("IDENTITY_FILE")
+                        .value_hint(clap::ValueHint::FilePath)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Write the decrypted files in a folder instead of next to the encrypted ones")
+                        .value_name("OUTPUT_DIR")
+                        .value_hint(clap::ValueHint::DirPath),
+                ),
+        )
         .help_template(HELP_TEMPLATE)
         .get_matches();
 
+    // Handle converting the definitions of other tools
+    if let Some(("convert", convert)) = matches.subcommand() {
+        if let Some(("kape", kape)) = convert.subcommand() {
+            let dir = kape.get_one::<String>("dir").unwrap();
+            let targets: Vec<String> = kape.get_many::<String>("targets").unwrap_or_default().cloned().collect();
+            return convert_kape_targets(dir, &targets, kape.get_one::<String>("output"));
+        }
+    }
+
+    // Handle decrypting an output encrypted to public keys
+    if let Some(("decrypt", decrypt)) = matches.subcommand() {
+        let files: Vec<String> = decrypt.get_many::<String>("files").unwrap_or_default().cloned().collect();
+        let identity_path = decrypt.get_one::<String>("identity").unwrap();
+        return decrypt_output(&files, identity_path, decrypt.get_one::<String>("output"));
+    }
+
     // Handle changing the embedded configuration
     if let Some(values) = matches.get_many::<String>("change_config") {
         let args: Vec<_> = values.collect();
         let config_path = args[0];
         let output_path = args[1];
-        match Config::check_config_file(&config_path) {
-            Ok(_) => {
+        match Config::check_config_file(config_path) {
+            Ok(config) => {
                 if !output_path.is_empty() {
-                    match add_resource(config_path, "config.yml", output_path) {
+                    // Embed the resolved configuration, so it doesn't depend on its included files
+                    let resolved_path = env::temp_dir().join("aralez_config.yml");
+                    fs::write(&resolved_path, config.to_yaml()?)?;
+                    let resolved_path = resolved_path.to_string_lossy().to_string();
+                    match add_resource(&resolved_path, "config.yml", output_path) {
                         Ok(_) => println!("[INFO] The config `{}` was successfully added to `{}`.",config_path, output_path),
                         Err(_) => println!("[ERROR] Problem to add the config {} in the resource.", config_path),
                     }
+                    let _ = fs::remove_file(&resolved_path);
                 } else {
                     return Err(anyhow::anyhow!(
                         "[ERROR] Output file name is required when changing configuration"
@@ -187,7 +410,7 @@ fn main() -> Result<(), anyhow::Error> {
         let tool_path = args[0];
         let output_path = args[1];
         if !output_path.is_empty() {
-            if let Some(resource_name) = tool_path.split('\\').last() {
+            if let Some(resource_name) = tool_path.split('\\').next_back() {
                 if resource_name != "config.yml" {
                     match add_resource(tool_path, resource_name, output_path) {
                         Ok(_) => println!("[INFO] The tool `{}` was successfully added to `{}`.",tool_path, output_path),
@@ -234,43 +457,70 @@ fn main() -> Result<(), anyhow::Error> {
         for tool in ext_list {
             println!("(static) {}",tool);
         }
-        match list_resources(10) {
-            Ok(list_resources_array) => {
-                for resource_element in list_resources_array {
-                    if resource_element != "CONFIG.YML" {
-                        println!("(dynamic) {}",resource_element);
-                    }
+        if let Ok(list_resources_array) = list_resources(10) {
+            for resource_element in list_resources_array {
+                if resource_element != "CONFIG.YML" {
+                    println!("(dynamic) {}",resource_element);
                 }
-            },
-            Err(_) => ()
+            }
         }
         return Ok(());
     }
 
     // Handle show_config flag
     if matches.get_flag("show_config") {
-        return show_config();
+        return show_config(&matches);
+    }
+
+    // Handle dump_schema flag
+    if matches.get_flag("dump_schema") {
+        return dump_schema();
     }
 
     // Handle check_config flag
-    if matches.get_flag("check_config") {
-        return match check_config() {
-            Ok(_) => {
-                println!("The configuration file is valid");
-                Ok(())
-            },
-            Err(e) => Err(e),
+    if let Some(config_path) = matches.get_one::<String>("check_config") {
+        let config_path = match matches.get_one::<String>("config") {
+            Some(path) if config_path.is_empty() => path,
+            _ => config_path,
         };
+        let exit_code = check_config(config_path)?;
+        std::process::exit(exit_code);
     }
 
-    let config = Config::load()?;
+    let config = load_config(&matches)?;
+
+    // The output goes in `--output`, an external drive or a share, or in the current folder
+    let output_filename = config.get_output_filename();
+    let root_output = &match matches.get_one::<String>("output") {
+        Some(output_dir) => Path::new(output_dir).join(&output_filename).to_string_lossy().to_string(),
+        None => output_filename,
+    };
 
     set_config(Config {
-        output_filename: format!("{}.log", config.get_output_filename()), // Placeholder (overridden)
+        output_filename: format!("{}.log", root_output), // Placeholder (overridden)
         tasks: config.tasks.clone(),
         max_size: config.max_size,
+        max_total_size: config.max_total_size,
+        max_files: config.max_files,
+        selection_order: config.selection_order,
+        max_parallel_tasks: config.max_parallel_tasks,
+        output_staging: config.output_staging,
+        output_format: config.output_format,
+        compression_level: config.compression_level,
+        output_split_size: config.output_split_size,
+        output_recipients: config.output_recipients.clone(),
         version: config.version.clone()
     });
+    set_global_budget(Budget::new("global", config.max_total_size, config.max_files));
+
+    if matches.get_flag("dry_run") {
+        set_dry_run();
+    }
+
+    // Created even in dry run, for the plan file
+    if let Some(output_dir) = matches.get_one::<String>("output") {
+        fs::create_dir_all(output_dir)?;
+    }
 
     // Check if the --debug flag was provided
     if matches.get_flag("debug") {
@@ -289,9 +539,18 @@ fn main() -> Result<(), anyhow::Error> {
     println!("Developed by: {}", env!("CARGO_PKG_AUTHORS"));
     println!();
 
-    let root_output = &config.get_output_filename();
-
-    config.save(root_output)?;
+    let src_log_file = format!("{}.log", root_output);
+    let log_file_name = Path::new(&src_log_file).file_name().unwrap_or_default().to_string_lossy().to_string();
+    if !is_dry_run() {
+        open_archive(root_output, &config)?;
+        config.save(root_output)?;
+        // The log of an encrypted archive is spooled in it, never in plaintext on the disk
+        if config.output_recipients.is_some() {
+            if let Some(log) = create_output(&format!("{}/{}", root_output, log_file_name), None)? {
+                start_archive_log(log)?;
+            }
+        }
+    }
 
     dprintln!("Aralez version: {} ({})", env!("CARGO_PKG_VERSION"), TARGET_ARCH);
     dprintln!("Configuration version: {} ", &config.version.clone().unwrap_or("unknown".to_string()));
@@ -311,158 +570,40 @@ fn main() -> Result<(), anyhow::Error> {
     let c_drive = "C".to_string();
     let default_drive = matches.get_one::<String>("default_drive").unwrap_or(&c_drive);
 
-    let sorted_tasks = config.get_tasks();
-    for (section_name, mut section_config) in sorted_tasks {
-        if let Some(disabled_task) = section_config.disabled {
-            if disabled_task {
-                continue;
-            }
-        }
-        dprintln!("[INFO] == Starting task `{}` ==", section_name);
-        spinner.set_message(format!("Processing: `{}` task", section_name));
-        match section_config.r#type {
-            config::TypeTasks::Collect => {
-                let drive: String = section_config
-                    .drive
-                    .clone()
-                    .unwrap_or_else(|| default_drive.to_string());
-                spinner.set_message(format!("Processing: `{}` drive", drive));
-
-                if drive == "*" {
-                    let output_collect_folder = match section_config.output_folder.clone(){
-                        Some(o) => o.replace("{{root_output_path}}", root_output),
-                        None => root_output.to_string(),
-                    };
-                    process_all_drives(&mut section_config, &output_collect_folder)?;
-                } else {
-                    // Check if the drive exists
-                    if !is_drive_accessible(&drive) {
-                        dprintln!("[ERROR] Drive `{}` is not accessible or does not exist", drive);
-                    } else {
-                        let output_collect_folder = match section_config.output_folder.clone(){
-                            Some(o) => o.replace("{{root_output_path}}", root_output)
-                                                .replace("{{drive}}", &drive),
-                            None => format!("{}\\{}", root_output, drive),
-                        };
-                        ensure_directory_exists(&output_collect_folder)?;
-                        process_drive_artifacts(&drive, &mut section_config,
-                            &output_collect_folder)?;
-                    }
-                }
-            }
-            config::TypeTasks::Execute => {
-                let _ = &section_config
-                    .entries
-                    .par_iter()
-                    .for_each(|(_, executors)| {
-                        executors.par_iter().for_each(|executor_iter| {
-                            let executor = executor_iter.clone();
-                            match executor.exec_type {
-                                Some(exec_type) => {
-                                    let output_path = root_output; 
-                                    ensure_directory_exists(&output_path)
-                                        .expect("Failed to create or access output directory");
-
-                                    // Sanitize args
-                                    let args_refs: Vec<String> = match executor.args {
-                                        Some(ref args_array) => {
-                                            args_array
-                                                .iter()
-                                                .map(|arg| {
-                                                    let mut updated_arg = arg.to_string();
-                                                    if  arg.contains("{{root_output_path}}") {
-                                                        updated_arg = arg.replace("{{root_output_path}}", output_path);
-                                                        if let Some(pos) = updated_arg.rfind('\\') {
-                                                            let directory_path = &updated_arg[..pos];
-                                                            ensure_directory_exists(directory_path)
-                                                                .expect("Failed to create or access output directory");
-                                                        }
-                                                    } 
-                                                    updated_arg
-                                                })
-                                                .collect()
-                                        }
-                                        None => Vec::new(),
-                                    };
-                                    let args: Vec<&str> = args_refs.iter().map(String::as_str).collect();
-
-                                    let executor_name =
-                                    executor.name.clone().expect(MSG_ERROR_CONFIG);
-                                    spinner.set_message(format!(
-                                        "Processing: `{}` tool",
-                                        executor_name
-                                    ));
-
-                                    // Sanitize output_file
-                                    let updated_output_file = match executor.output_file {
-                                        Some(output_file) => {
-                                            let updated_output_file: String = output_file.to_string();
-                                            if let Some(pos) = updated_output_file.rfind('\\') {
-                                                let directory_path = &updated_output_file[..pos];
-                                                ensure_directory_exists(directory_path)
-                                                    .expect("Failed to create or access output directory");
-                                            }
-                                            updated_output_file
-                                        },
-                                        None => executor_name.clone().replace(".exe", ".txt").to_string(),
-                                    };
-                                    let output_file = updated_output_file.as_str();
-                                    let output_exec_folder = match section_config.output_folder.clone(){
-                                        Some(o) => o.replace("{{root_output_path}}", root_output),
-                                        None => format!("{}\\{}", root_output, "tools"),
-                                    };
-                                    ensure_directory_exists(&output_exec_folder)
-                                        .expect("Failed to create or access output directory");
-                                    let output_fullpath = format!("{}\\{}",output_exec_folder,output_file);
-
-                                    match exec_type {
-                                        config::TypeExec::External => {
-                                            match get_bin(executor_name) {
-                                                Ok(bin) => {
-                                                    run (
-                                                        executor
-                                                            .name
-                                                            .clone()
-                                                            .expect(MSG_ERROR_CONFIG),
-                                                        &args,
-                                                        config::ExecType::External,
-                                                        Some(&bin),
-                                                        Some(&output_path),
-                                                        &output_fullpath
-                                                    );
-                                                }
-                                                Err(e) => dprintln!("{}", e),
-                                            }
-                                        }
-                                        config::TypeExec::Internal => {
-                                            run_internal(&executor_name, &output_fullpath);
-                                        }
-                                        config::TypeExec::System => {
-                                            run (
-                                                executor_name,
-                                                &args,
-                                                ExecType::System,
-                                                None,
-                                                None,
-                                                &output_fullpath
-                                            );
-                                        }
-                                    }
-                                }
-                                None => dprintln!("{}", MSG_ERROR_CONFIG),
-                            }
-                        });
-                    });
-            }
-        }
-    }
+    // Disabled tasks are left out, the tasks depending on them don't wait for them
+    let tasks: Vec<(String, SectionConfig)> = config
+        .get_tasks()
+        .into_iter()
+        .filter(|(_, section_config)| section_config.disabled != Some(true))
+        .collect();
+    // The plan of a dry run is made one task at a time
+    let max_parallel_tasks = if is_dry_run() {
+        1
+    } else {
+        config.max_parallel_tasks.unwrap_or(DEFAULT_MAX_PARALLEL_TASKS)
+    };
+    run_tasks(tasks, max_parallel_tasks, |section_name, section_config| {
+        run_task(section_name, section_config, root_output, default_drive, &spinner)
+    })?;
 
     dprintln!("[INFO] All tasks completed");
 
-    let src_log_file = format!("{}.log", root_output);
-    // Move the logfile into the root folder
-    if Path::new(&src_log_file).exists() {
-        let dest_log_file = format!("{}/{}", root_output, src_log_file);
+    if is_dry_run() {
+        spinner.finish_with_message("Dry run completed");
+        return save_plan(root_output);
+    }
+
+    if let Err(e) = save_skipped_files(root_output) {
+        dprintln!("[ERROR] Problem to save the skipped files: {}", e);
+    }
+
+    // Add the log of an encrypted archive, otherwise move the logfile into the root folder,
+    // it is added to the archive with the staged files
+    if config.output_recipients.is_some() {
+        finish_archive_log()?;
+    } else if Path::new(&src_log_file).exists() {
+        fs::create_dir_all(root_output)?;
+        let dest_log_file = format!("{}/{}", root_output, log_file_name);
         fs::rename(src_log_file, dest_log_file)?;
     } else {
         println!("[WARN] Log file not found");
@@ -470,7 +611,7 @@ fn main() -> Result<(), anyhow::Error> {
 
     spinner.set_message("Running: compression");
 
-    zip_dir(root_output)?;
+    close_archive()?;
 
     remove_dir_all(root_output)?;
 
@@ -479,66 +620,177 @@ fn main() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn zip_dir(dir_name: &str) -> io::Result<()> {
-    let root_path = Path::new(dir_name);
-    fs::create_dir_all(&root_path)?;
-
-    let zip_file_name = format!("{}.zip", dir_name);
-    let zip_file = File::create(&zip_file_name)?;
-
-    let mut zip = ZipWriter::new(zip_file);
-    let options = FileOptions::default()
-        .compression_method(CompressionMethod::Deflated)
-        .large_file(true);
-
-    add_directory_to_zip(&mut zip, root_path, "", &options)?;
-
-    zip.finish()?;
-    Ok(())
-}
-
-fn add_directory_to_zip<W: Write + Seek>(
-    zip: &mut ZipWriter<W>,
-    root_path: &Path,
-    parent_dir: &str,
-    options: &FileOptions<()>,
-) -> io::Result<()> {
-    for entry in fs::read_dir(root_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        let name = format!("{}{}", parent_dir, entry.file_name().to_string_lossy());
-
-        if path.exists() {
-            if path.is_dir() {
-                zip.add_directory(&format!("{}/", name), *options)?;
-                add_directory_to_zip(zip, &path, &format!("{}/", name), options)?;
+/// Run a collect or an execute task
+fn run_task(
+    section_name: &str,
+    section_config: &SectionConfig,
+    root_output: &str,
+    default_drive: &str,
+    spinner: &ProgressBar,
+) -> Result<()> {
+    dprintln!("[INFO] == Starting task `{}` ==", section_name);
+    set_plan_task(section_name);
+    spinner.set_message(format!("Processing: `{}` task", section_name));
+    match section_config.r#type {
+        config::TypeTasks::Collect => {
+            let drive: String = section_config
+                .drive
+                .clone()
+                .unwrap_or_else(|| default_drive.to_string());
+            spinner.set_message(format!("Processing: `{}` drive", drive));
+
+            if let Some(image) = section_config.image.clone() {
+                // Raw disk or partition image (NTFS, FAT, exFAT or ext2/3/4)
+                let image_name = Path::new(&image)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "image".to_string());
+                spinner.set_message(format!("Processing: `{}` image", image_name));
+                let output_collect_folder = match section_config.output_folder.clone(){
+                    Some(o) => o.replace("{{root_output_path}}", root_output)
+                                        .replace("{{drive}}", &image_name),
+                    None => format!("{}\\{}", root_output, image_name),
+                };
+                ensure_directory_exists(&output_collect_folder)?;
+                process_image_artifacts(&image, section_config, &output_collect_folder)?;
+            } else if drive == "*" {
+                let output_collect_folder = match section_config.output_folder.clone(){
+                    Some(o) => o.replace("{{root_output_path}}", root_output),
+                    None => root_output.to_string(),
+                };
+                process_all_drives(section_config, &output_collect_folder)?;
             } else {
-                let mut file = File::open(&path)?;
-
-                // Retrieve last modified time and convert to DateTime components
-                let metadata = file.metadata()?;
-                let modified_time = metadata.modified()?.duration_since(UNIX_EPOCH).unwrap_or_default();
-                let datetime = DateTime::<Utc>::from(UNIX_EPOCH + modified_time);
-                let naive_datetime = datetime.naive_utc();
-    
-                // Convert to ZipDateTime using date and time components
-                let zip_datetime = ZipDateTime::from_date_and_time(
-                    naive_datetime.year() as u16,
-                    naive_datetime.month() as u8,
-                    naive_datetime.day() as u8,
-                    naive_datetime.hour() as u8,
-                    naive_datetime.minute() as u8,
-                    naive_datetime.second() as u8,
-                ).unwrap_or_else(|_| ZipDateTime::default_for_write());
-    
-                // Set options with the zip DateTime
-                let file_options = options.clone().last_modified_time(zip_datetime);
-    
-                zip.start_file(name, file_options)?;
-                io::copy(&mut file, zip)?;
+                // Check if the drive exists
+                if !is_drive_accessible(&drive) {
+                    dprintln!("[ERROR] Drive `{}` is not accessible or does not exist", drive);
+                } else {
+                    let output_collect_folder = match section_config.output_folder.clone(){
+                        Some(o) => o.replace("{{root_output_path}}", root_output)
+                                            .replace("{{drive}}", &drive),
+                        None => format!("{}\\{}", root_output, drive),
+                    };
+                    ensure_directory_exists(&output_collect_folder)?;
+                    process_drive_artifacts(&drive, section_config,
+                        &output_collect_folder)?;
+                }
             }
         }
-    }
+        config::TypeTasks::Execute => {
+            // The conditions of a collect task are checked on each of its volumes
+            if let Some(condition) = &section_config.when {
+                if let Err(reason) = check_drive_condition(default_drive, condition) {
+                    dprintln!("[INFO] Skip task `{}` because {}", section_name, reason);
+                    return Ok(());
+                }
+            }
+            let _ = &section_config
+                .entries
+                .par_iter()
+                .for_each(|(_, executors)| {
+                    executors.par_iter().for_each(|executor_iter| {
+                        let executor = executor_iter.clone();
+                        match executor.exec_type {
+                            Some(exec_type) => {
+                                let output_path = root_output; 
+                                ensure_directory_exists(output_path)
+                                    .expect("Failed to create or access output directory");
+
+                                // Sanitize args
+                                let args_refs: Vec<String> = match executor.args {
+                                    Some(ref args_array) => {
+                                        args_array
+                                            .iter()
+                                            .map(|arg| {
+                                                let mut updated_arg = arg.to_string();
+                                                if  arg.contains("{{root_output_path}}") {
+                                                    updated_arg = arg.replace("{{root_output_path}}", output_path);
+                                                    if let Some(pos) = updated_arg.rfind('\\') {
+                                                        let directory_path = &updated_arg[..pos];
+                                                        ensure_directory_exists(directory_path)
+                                                            .expect("Failed to create or access output directory");
+                                                    }
+                                                } 
+                                                updated_arg
+                                            })
+                                            .collect()
+                                    }
+                                    None => Vec::new(),
+                                };
+                                let args: Vec<&str> = args_refs.iter().map(String::as_str).collect();
+
+                                let executor_name =
+                                executor.name.clone().expect(MSG_ERROR_CONFIG);
+                                spinner.set_message(format!(
+                                    "Processing: `{}` tool",
+                                    executor_name
+                                ));
+
+                                // Sanitize output_file
+                                let updated_output_file = match executor.output_file {
+                                    Some(output_file) => {
+                                        let updated_output_file: String = output_file.to_string();
+                                        if let Some(pos) = updated_output_file.rfind('\\') {
+                                            let directory_path = &updated_output_file[..pos];
+                                            ensure_directory_exists(directory_path)
+                                                .expect("Failed to create or access output directory");
+                                        }
+                                        updated_output_file
+                                    },
+                                    None => executor_name.clone().replace(".exe", ".txt").to_string(),
+                                };
+                                let output_file = updated_output_file.as_str();
+                                let output_exec_folder = match section_config.output_folder.clone(){
+                                    Some(o) => o.replace("{{root_output_path}}", root_output),
+                                    None => format!("{}\\{}", root_output, "tools"),
+                                };
+                                ensure_directory_exists(&output_exec_folder)
+                                    .expect("Failed to create or access output directory");
+                                let output_fullpath = format!("{}\\{}",output_exec_folder,output_file);
+
+                                if is_dry_run() {
+                                    add_planned_tool(exec_type.as_str(), &executor_name, &args_refs, &output_fullpath);
+                                    return;
+                                }
 
+                                match exec_type {
+                                    config::TypeExec::External => {
+                                        match get_bin(executor_name) {
+                                            Ok(bin) => {
+                                                run (
+                                                    executor
+                                                        .name
+                                                        .clone()
+                                                        .expect(MSG_ERROR_CONFIG),
+                                                    &args,
+                                                    config::ExecType::External,
+                                                    Some(&bin),
+                                                    Some(output_path),
+                                                    &output_fullpath
+                                                );
+                                            }
+                                            Err(e) => dprintln!("{}", e),
+                                        }
+                                    }
+                                    config::TypeExec::Internal => {
+                                        run_internal(&executor_name, &output_fullpath);
+                                    }
+                                    config::TypeExec::System => {
+                                        run (
+                                            executor_name,
+                                            &args,
+                                            ExecType::System,
+                                            None,
+                                            None,
+                                            &output_fullpath
+                                        );
+                                    }
+                                }
+                            }
+                            None => dprintln!("{}", MSG_ERROR_CONFIG),
+                        }
+                    });
+                });
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,542 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::budget::{get_global_budget, Budget};
+use crate::conditions::VolumeFacts;
+use crate::config::{get_config, SearchConfig, SectionConfig, SelectionOrder};
+use crate::filters::{compile_excludes, EntryFilters};
+use crate::profiles::{expand_profile_vars, expand_with_placeholder, uses_profile_vars};
+use crate::walker::Volume;
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use std::sync::Arc;
+
+/// Settings of the collect entry a rule comes from
+#[derive(Debug, Clone)]
+pub struct EntrySettings {
+    pub encrypt: Option<String>,
+    pub max_size: Option<u64>,
+    pub filters: EntryFilters,
+}
+
+/// One component of a rule, used to prune the folders that can't hold a match
+#[derive(Debug, Clone)]
+enum Component {
+    Literal(String),
+    Glob(GlobMatcher),
+    /// `**`, or a component that can't be compiled alone (e.g. `{a/b,c}`)
+    AnyDepth,
+}
+
+impl Component {
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Component::Literal(literal) => name.to_lowercase() == *literal,
+            Component::Glob(glob) => glob.is_match(name),
+            Component::AnyDepth => true,
+        }
+    }
+}
+
+/// A `root_path` and object pair of a collect entry
+#[derive(Debug, Clone)]
+pub struct Rule {
+    components: Vec<Component>,
+    /// Alternate data stream to collect (NTFS only), empty for the main content
+    pub stream: String,
+    root_depth: usize,
+    max_depth: Option<usize>,
+    settings: usize,
+}
+
+impl Rule {
+    /// Position of the collect entry of the rule in the task
+    pub fn entry_index(&self) -> usize {
+        self.settings
+    }
+}
+
+/// Progress of a rule in the walked folder
+#[derive(Debug, Clone, Copy)]
+pub struct RuleState {
+    rule: usize,
+    /// Next component to match, `None` after a `**`
+    next: Option<usize>,
+}
+
+/// All the rules of a collect task, with a compiled `GlobSet` for the full paths
+/// and the budget shared by the volumes of the task
+#[derive(Debug)]
+pub struct Matcher {
+    set: GlobSet,
+    rules: Vec<Rule>,
+    settings: Vec<EntrySettings>,
+    budget: Arc<Budget>,
+    pub selection_order: SelectionOrder,
+    /// Task of the rules, kept to check the conditions and expand the profile variables of each volume
+    section_config: SectionConfig,
+}
+
+fn build_glob(pattern: &str) -> Result<Glob, globset::Error> {
+    GlobBuilder::new(pattern)
+        .case_insensitive(true)
+        .literal_separator(true)
+        .backslash_escape(false)
+        .build()
+}
+
+/// Normalize a `root_path` or an object to a lowercased path with `/` separators
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/").trim_matches('/').to_lowercase()
+}
+
+/// Split an object into its pattern and its alternate data stream (`$Extend\$UsnJrnl:$J`)
+pub fn split_stream(object: &str) -> (&str, &str) {
+    let name_start = object.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match object[name_start..].find(':') {
+        Some(i) => (&object[..name_start + i], &object[name_start + i + 1..]),
+        None => (object, ""),
+    }
+}
+
+/// Full glob of an object of an entry, from the volume root
+pub fn full_pattern(root_path: &str, object: &str) -> String {
+    let root = normalize(root_path);
+    let object = normalize(object);
+    if root.is_empty() {
+        format!("/{}", object)
+    } else {
+        format!("/{}/{}", root, object)
+    }
+}
+
+/// Check the objects of an entry, return a message for the first invalid glob
+pub fn validate_objects(search_config: &SearchConfig) -> Result<(), String> {
+    let root_path = expand_with_placeholder(&search_config.get_expanded_root_path());
+    for object in search_config.objects.iter().flatten() {
+        let (pattern, _) = split_stream(object);
+        build_glob(&full_pattern(&root_path, pattern))
+            .map_err(|e| format!("invalid glob `{}`: {}", object, e))?;
+    }
+    Ok(())
+}
+
+impl Matcher {
+    /// Compile the rules of every entry of a collect task. The entries with a `when` clause or
+    /// with profile variables (`{{user_profile}}`, `{{appdata}}`, `{{localappdata}}`) need a volume, see `for_volume`.
+    pub fn new(section_config: &SectionConfig) -> Self {
+        let budget = Budget::new("task", section_config.max_total_size, section_config.max_files);
+        Matcher::build(section_config, &[], Arc::new(budget), &mut |_, search_config| {
+            search_config.when.is_none()
+        })
+    }
+
+    fn uses_profiles(&self) -> bool {
+        self.section_config
+            .entries
+            .values()
+            .flatten()
+            .any(|search_config| uses_profile_vars(&search_config.get_expanded_root_path()))
+    }
+
+    /// Check if the task has conditions or profile variables to resolve on each volume
+    pub fn needs_volume(&self) -> bool {
+        self.section_config.when.is_some()
+            || self.section_config.entries.values().flatten().any(|search_config| search_config.when.is_some())
+            || self.uses_profiles()
+    }
+
+    /// Compile the rules again for a volume, with its profiles and without the entries whose condition is false.
+    /// `None` if the condition of the task is false. The budget stays shared with the task.
+    pub fn for_volume<V: Volume>(&self, facts: &mut VolumeFacts<V>) -> Option<Self> {
+        if let Some(condition) = &self.section_config.when {
+            if let Err(reason) = condition.check(facts) {
+                dprintln!("[INFO] Skip the task on this volume because {}", reason);
+                return None;
+            }
+        }
+        let profiles = if self.uses_profiles() { facts.profiles() } else { Vec::new() };
+        Some(Matcher::build(
+            &self.section_config,
+            &profiles,
+            self.budget.clone(),
+            &mut |entry_name, search_config| match &search_config.when {
+                Some(condition) => match condition.check(facts) {
+                    Ok(()) => true,
+                    Err(reason) => {
+                        dprintln!("[INFO] Skip entry `{}` because {}", entry_name, reason);
+                        false
+                    }
+                },
+                None => true,
+            },
+        ))
+    }
+
+    fn build(
+        section_config: &SectionConfig,
+        profiles: &[String],
+        budget: Arc<Budget>,
+        is_enabled: &mut dyn FnMut(&str, &SearchConfig) -> bool,
+    ) -> Self {
+        let task_exclude = section_config
+            .exclude
+            .as_ref()
+            .and_then(|patterns| match compile_excludes(patterns, "") {
+                Ok(set) => Some(set),
+                Err(e) => {
+                    dprintln!("[ERROR] Config: {}", e);
+                    None
+                }
+            });
+
+        let mut builder = GlobSetBuilder::new();
+        let mut rules = Vec::new();
+        let mut settings = Vec::new();
+
+        for (entry_name, search_configs) in &section_config.entries {
+            for search_config in search_configs {
+                if !is_enabled(entry_name, search_config) {
+                    continue;
+                }
+                for root_path in expand_profile_vars(&search_config.get_expanded_root_path(), profiles) {
+                    let mut filters = match search_config.get_filters(&root_path) {
+                        Ok(filters) => filters,
+                        Err(e) => {
+                            dprintln!("[ERROR] Config: Entry '{}': {}", entry_name, e);
+                            continue;
+                        }
+                    };
+                    filters.exclude.extend(task_exclude.clone());
+                    settings.push(EntrySettings {
+                        encrypt: search_config.encrypt.clone(),
+                        max_size: search_config.get_max_size(section_config.max_size),
+                        filters,
+                    });
+
+                    let root_depth = normalize(&root_path).split('/').filter(|s| !s.is_empty()).count();
+                    // Regex entries walk everything under their root_path and match with the regexes
+                    let objects = if search_config.is_regex() {
+                        vec!["**".to_string()]
+                    } else {
+                        search_config.objects.clone().unwrap_or_default()
+                    };
+
+                    for object in objects {
+                        let (object_pattern, stream) = split_stream(&object);
+                        let pattern = full_pattern(&root_path, object_pattern);
+                        let glob = match build_glob(&pattern) {
+                            Ok(glob) => glob,
+                            Err(e) => {
+                                dprintln!("[ERROR] Config: Entry '{}': invalid glob `{}`: {}", entry_name, object, e);
+                                continue;
+                            }
+                        };
+                        builder.add(glob);
+                        rules.push(Rule {
+                            components: components(&pattern),
+                            stream: stream.to_string(),
+                            root_depth,
+                            max_depth: search_config.max_depth,
+                            settings: settings.len() - 1,
+                        });
+                    }
+                }
+            }
+        }
+
+        let set = builder.build().unwrap_or_else(|e| {
+            dprintln!("[ERROR] Config: {}", e);
+            GlobSet::empty()
+        });
+        let selection_order = section_config
+            .selection_order
+            .or(get_config().selection_order)
+            .unwrap_or(SelectionOrder::EntryOrder);
+        Matcher {
+            set,
+            rules,
+            settings,
+            budget,
+            selection_order,
+            section_config: section_config.clone(),
+        }
+    }
+
+    /// Check if the task or the whole collection has a budget, the files are then selected after the walk
+    pub fn has_budget(&self) -> bool {
+        self.budget.is_limited() || get_global_budget().is_some()
+    }
+
+    /// Reserve room for a file in the task and global budgets, return the reason when one is exceeded
+    pub fn reserve(&self, size: u64) -> Result<(), String> {
+        self.budget.reserve(size)?;
+        if let Some(global_budget) = get_global_budget() {
+            if let Err(e) = global_budget.reserve(size) {
+                self.budget.release(size);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Give back the room reserved for a file that was not saved
+    pub fn release(&self, size: u64) {
+        self.budget.release(size);
+        if let Some(global_budget) = get_global_budget() {
+            global_budget.release(size);
+        }
+    }
+
+    /// States of the rules at the volume root
+    pub fn root_states(&self) -> Vec<RuleState> {
+        (0..self.rules.len())
+            .map(|rule| RuleState { rule, next: Some(0) })
+            .collect()
+    }
+
+    /// States of the rules inside a sub folder, empty when the folder can't hold any match
+    pub fn enter(&self, states: &[RuleState], name: &str, path: &str, depth: usize) -> Vec<RuleState> {
+        let mut sub_states = Vec::new();
+        let mut excluded = false;
+        for state in states {
+            let rule = &self.rules[state.rule];
+            if let Some(max_depth) = rule.max_depth {
+                if depth > rule.root_depth + max_depth {
+                    continue;
+                }
+            }
+            let next = match state.next {
+                None => None,
+                Some(i) => match rule.components.get(i) {
+                    Some(Component::AnyDepth) => None,
+                    // The last component is the file name, a folder matching it has no match inside
+                    Some(c) if i + 1 < rule.components.len() && c.matches(name) => Some(i + 1),
+                    _ => continue,
+                },
+            };
+            if self.settings[rule.settings].filters.is_excluded(path) {
+                excluded = true;
+                continue;
+            }
+            sub_states.push(RuleState { rule: state.rule, next });
+        }
+        if sub_states.is_empty() && excluded {
+            dprintln!("[INFO] Skip {} because it is excluded", path);
+        }
+        sub_states
+    }
+
+    /// Rules of the walked folder matching a file path
+    pub fn file_matches(&self, states: &[RuleState], path: &str) -> Vec<(&Rule, &EntrySettings)> {
+        if states.is_empty() {
+            return Vec::new();
+        }
+        let mut matches = Vec::new();
+        for index in self.set.matches(path) {
+            if !states.iter().any(|state| state.rule == index) {
+                continue;
+            }
+            let rule = &self.rules[index];
+            let settings = &self.settings[rule.settings];
+            if !settings.filters.matches_regex(path) {
+                continue;
+            }
+            if settings.filters.is_excluded(path) {
+                dprintln!("[INFO] Skip {} because it is excluded", path);
+                continue;
+            }
+            matches.push((rule, settings));
+        }
+        matches
+    }
+}
+
+/// Split a full glob into components, a brace group spanning several components matches any depth
+fn components(pattern: &str) -> Vec<Component> {
+    let mut components = Vec::new();
+    let mut spanning_group = false;
+    for part in pattern.trim_start_matches('/').split('/') {
+        spanning_group = spanning_group || part.matches('{').count() != part.matches('}').count();
+        components.push(if spanning_group { Component::AnyDepth } else { component(part) });
+    }
+    components
+}
+
+fn component(part: &str) -> Component {
+    if part == "**" {
+        return Component::AnyDepth;
+    }
+    if !part.contains(['*', '?', '[', ']', '{', '}']) {
+        return Component::Literal(part.to_string());
+    }
+    match build_glob(part) {
+        Ok(glob) => Component::Glob(glob.compile_matcher()),
+        Err(_) => Component::AnyDepth,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(task: &str) -> Matcher {
+        let yaml = format!("priority: 1\ntype: collect\n{}", task);
+        Matcher::new(&serde_yaml::from_str(&yaml).unwrap())
+    }
+
+    /// States of the rules in the folder of `path`, entered one folder at a time like the walker
+    fn states(matcher: &Matcher, path: &str) -> Vec<RuleState> {
+        let mut states = matcher.root_states();
+        let mut current = String::new();
+        let names: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        for (depth, name) in names[..names.len() - 1].iter().enumerate() {
+            current = format!("{}/{}", current, name);
+            states = matcher.enter(&states, name, &current, depth + 1);
+        }
+        states
+    }
+
+    fn is_match(matcher: &Matcher, path: &str) -> bool {
+        !matcher.file_matches(&states(matcher, path), path).is_empty()
+    }
+
+    /// Check if the walker prunes the folder of `path`
+    fn is_pruned(matcher: &Matcher, path: &str) -> bool {
+        states(matcher, path).is_empty()
+    }
+
+    #[test]
+    fn gives_back_the_task_room_when_the_global_budget_refuses_a_file() {
+        // The global budget is shared by the whole test binary, only this test fills it
+        crate::budget::set_global_budget(Budget::new("global", Some(1 << 40), None));
+        let matcher = matcher("max_files: 1\nentries: {}\n");
+        assert!(matcher.has_budget());
+        assert!(matcher.reserve(2 << 40).unwrap_err().starts_with("global max_total_size"));
+        assert!(matcher.reserve(10).is_ok());
+        assert_eq!(matcher.reserve(10).unwrap_err(), "task max_files of 1 reached");
+        matcher.release(10);
+        assert!(matcher.reserve(10).is_ok());
+    }
+
+    #[test]
+    fn matches_any_depth_in_the_middle_of_a_path() {
+        let matcher = matcher(
+            r#"
+entries:
+  logs:
+    - root_path: '\Windows'
+      objects: ['System32\**\*.evtx']
+"#,
+        );
+        assert!(is_match(&matcher, "/Windows/System32/Security.evtx"));
+        assert!(is_match(&matcher, "/Windows/System32/winevt/Logs/Security.evtx"));
+        assert!(!is_match(&matcher, "/Windows/System32/winevt/Logs/Security.log"));
+        assert!(!is_match(&matcher, "/Windows/Temp/Security.evtx"));
+        assert!(is_pruned(&matcher, "/Windows/Temp/Security.evtx"));
+    }
+
+    #[test]
+    fn matches_brace_alternations() {
+        let matcher = matcher(
+            r#"
+entries:
+  logs:
+    - root_path: '\Logs'
+      objects: ['*.{evtx,etl}']
+"#,
+        );
+        assert!(is_match(&matcher, "/Logs/System.evtx"));
+        assert!(is_match(&matcher, "/Logs/Boot.etl"));
+        assert!(!is_match(&matcher, "/Logs/Setup.log"));
+        assert!(!is_match(&matcher, "/Logs/Old/System.evtx"));
+    }
+
+    #[test]
+    fn walks_braces_spanning_folders() {
+        let matcher = matcher(
+            r#"
+entries:
+  logs:
+    - root_path: '\Windows'
+      objects: ['{System32\config,SysWOW64}\*.log']
+"#,
+        );
+        assert!(is_match(&matcher, "/Windows/System32/config/SYSTEM.LOG"));
+        assert!(is_match(&matcher, "/Windows/SysWOW64/setup.log"));
+        assert!(!is_match(&matcher, "/Windows/System32/setup.log"));
+        assert!(!is_match(&matcher, "/Windows/SysWOW64/config/SYSTEM.LOG"));
+        assert!(is_pruned(&matcher, "/Program Files/setup.log"));
+    }
+
+    #[test]
+    fn ignores_the_case_of_the_paths() {
+        let matcher = matcher(
+            r#"
+entries:
+  hives:
+    - root_path: '\WINDOWS\System32'
+      objects: ['Config\SAM', 'config\*.LOG1']
+"#,
+        );
+        assert!(is_match(&matcher, "/Windows/system32/config/sam"));
+        assert!(is_match(&matcher, "/windows/SYSTEM32/CONFIG/SAM"));
+        assert!(is_match(&matcher, "/Windows/System32/config/system.log1"));
+        assert!(!is_match(&matcher, "/Windows/System32/config/sam.bak"));
+    }
+
+    #[test]
+    fn prunes_the_folders_deeper_than_max_depth() {
+        let matcher = matcher(
+            r#"
+entries:
+  data:
+    - root_path: '\Data'
+      objects: ['**\*.txt']
+      max_depth: 1
+"#,
+        );
+        assert!(is_match(&matcher, "/Data/notes.txt"));
+        assert!(is_match(&matcher, "/Data/a/notes.txt"));
+        assert!(!is_match(&matcher, "/Data/a/b/notes.txt"));
+        assert!(is_pruned(&matcher, "/Data/a/b/notes.txt"));
+    }
+
+    #[test]
+    fn prunes_the_excluded_folders() {
+        let matcher = matcher(
+            r#"
+exclude: ['\Data\Cache\**']
+entries:
+  data:
+    - root_path: '\Data'
+      objects: ['**\*.txt']
+      exclude: ['Temp\**', '**\*.tmp.txt']
+"#,
+        );
+        assert!(is_match(&matcher, "/Data/a/notes.txt"));
+        assert!(!is_match(&matcher, "/Data/a/notes.tmp.txt"));
+        assert!(is_pruned(&matcher, "/Data/Temp/notes.txt"));
+        assert!(is_pruned(&matcher, "/Data/temp/a/notes.txt"));
+        assert!(is_pruned(&matcher, "/Data/Cache/notes.txt"));
+        assert!(!is_pruned(&matcher, "/Data/Cached/notes.txt"));
+    }
+
+    #[test]
+    fn ignores_a_trailing_separator_of_the_root_path() {
+        for root_path in [r"\Windows\Prefetch", r"\Windows\Prefetch\"] {
+            let matcher = matcher(&format!(
+                "entries:\n  prefetch:\n    - root_path: '{}'\n      objects: ['*.pf']\n      exclude: ['CMD*']\n      max_depth: 0\n",
+                root_path
+            ));
+            assert!(is_match(&matcher, "/Windows/Prefetch/NOTEPAD.EXE-1234.pf"), "{}", root_path);
+            assert!(!is_match(&matcher, "/Windows/Prefetch/CMD.EXE-1234.pf"), "{}", root_path);
+            assert!(!is_match(&matcher, "/Windows/Prefetch/Old/NOTEPAD.EXE-1234.pf"), "{}", root_path);
+            assert!(!is_match(&matcher, "/Windows/NOTEPAD.EXE-1234.pf"), "{}", root_path);
+        }
+    }
+}
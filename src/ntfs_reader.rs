@@ -6,516 +6,335 @@
 // Author(s): Areg Baghinyan
 //
 
+use crate::conditions::{Condition, VolumeFacts};
 use crate::config::SectionConfig;
-use crate::sector_reader::SectorReader;
+use crate::ext4_reader;
+use crate::fat_reader;
+use crate::matcher::Matcher;
+use crate::partition_table::list_partitions;
+use crate::profiles::ProfileList;
+use crate::sector_reader::{read_boot_area, Geometry, VolumeReader};
 use crate::utils::{
-    ensure_directory_exists, get, split_path
+    ensure_directory_exists, flush_log_buffer, get, nt_timestamp_to_system_time, start_log_buffer
 };
+use crate::walker::{explore, EntryInfo, Volume};
 use anyhow::Result;
-use glob::Pattern;
+use ntfs::structured_values::NtfsFileNamespace;
 use ntfs::Ntfs;
 use ntfs::NtfsFile;
-use std::collections::HashMap;
-use std::collections::HashSet;
+use ntfs::NtfsReadSeek;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::env;
 use std::fs::File;
 use std::io;
-use std::io::BufReader;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::mem;
+use std::os::windows::io::AsRawHandle;
 use std::path::Path;
-use std::u64;
+use std::ptr;
+use std::sync::OnceLock;
+use winapi::shared::minwindef::DWORD;
+use winapi::um::ioapiset::DeviceIoControl;
+use winapi::um::winioctl::{IOCTL_STORAGE_GET_DEVICE_NUMBER, STORAGE_DEVICE_NUMBER};
 
 const NTFS_SIGNATURE: &[u8] = b"NTFS    ";
 
-#[derive(Debug)]
-struct Entry {
-    name: String,
-    file_record_number: u64,
+/// Profiles of the running system, read once from the SOFTWARE hive of its drive
+static SYSTEM_PROFILES: OnceLock<Option<ProfileList>> = OnceLock::new();
+
+/// A directory entry of an NTFS volume, described by its index key
+#[derive(Debug, Clone)]
+struct NtfsEntry {
+    info: EntryInfo,
 }
 
-fn process_all_directory(
-    fs: &mut BufReader<SectorReader<File>>,
-    ntfs: &Ntfs,
-    file: &NtfsFile<'_>,
-    obj_name: String,
-    current_path: &str,
-    destination_folder: &str,
-    drive: &str,
-    encrypt: Option<String>,
-    max_size: Option<u64>,
-    success_files_count: &mut u32
-) -> Result<HashSet<String>> {
-    let index = file.directory_index(fs)?;
-    let mut iter = index.entries();
-    let mut entries = Vec::new();
-    let mut local_visited_files: HashSet<String> = HashSet::new();
-    // Collect all entries into a vector
-    while let Some(entry_result) = iter.next(fs) {
-        match entry_result {
-            Ok(entry) => {
-                let name = entry
-                    .key()
-                    .unwrap()
-                    .unwrap()
-                    .name()
-                    .to_string_lossy()
-                    .to_string();
-                let file_record_number = entry.file_reference().file_record_number();
-                if name != "." {
-                    entries.push(Entry {
-                        name,
-                        file_record_number,
-                    });
-                }
+/// NTFS volume walked with the rules of a collect task
+struct NtfsReader {
+    fs: VolumeReader,
+    ntfs: Ntfs,
+    drive: String,
+}
+
+impl Volume for NtfsReader {
+    type Entry = NtfsEntry;
+
+    fn read_directory(&mut self, directory: Option<&NtfsEntry>) -> Result<Vec<NtfsEntry>> {
+        let file = match directory {
+            Some(entry) => self.ntfs.file(&mut self.fs, entry.info.id)?,
+            None => self.ntfs.root_directory(&mut self.fs)?,
+        };
+        let index = file.directory_index(&mut self.fs)?;
+        let mut iter = index.entries();
+        let mut entries = Vec::new();
+        while let Some(entry_result) = iter.next(&mut self.fs) {
+            let entry = entry_result?;
+            let key = match entry.key() {
+                Some(Ok(key)) => key,
+                _ => continue,
+            };
+            // A file with a long name has a second entry for its DOS short name
+            if key.namespace() == NtfsFileNamespace::Dos {
+                continue;
             }
-            Err(e) => return Err(e.into()),
-        }
-    }
-    for entry in entries {
-        let new_path = format!("{}/{}", current_path, entry.name);
-        if let Ok(sub_file) = ntfs.file(fs, entry.file_record_number) {
-            if sub_file.is_directory() {
-                if let Err(e) = process_all_directory(
-                    fs,
-                    ntfs,
-                    &sub_file,
-                    obj_name.clone(),
-                    &new_path,
-                    destination_folder,
-                    drive,
-                    encrypt.clone(),
-                    max_size,
-                    success_files_count
-                ){
-                    dprintln!("[ERROR] Processing subdirectory: {:?}", e);
-                }
-            } else {
-                let obj_name_parts = obj_name.split_once(':');
-                let (obj_name_san, ads) = match obj_name_parts {
-                    Some((left, right)) => {
-                        let left_string = left.to_string(); // Create a variable for the `String`
-                        (left_string, right) // Return the `String` itself, not a reference to it
-                    }
-                    None => (obj_name.to_string(), ""), // Ensure consistency with String type
-                };
-                let mut path_check = new_path.clone();
-                if !(ads.is_empty() || ads == "") {
-                    path_check = format!("{}:{}", path_check, ads);
-                }
-                if Pattern::new(&obj_name_san.as_str().to_lowercase())
-                    .unwrap()
-                    .matches(&path_check.as_str().to_lowercase())
-                {
-                    // check size
-                    let mut size_ok = true;
-                    if let Some(msize) = max_size {
-                        if get_file_size(&sub_file, fs) as u64 > msize {
-                            dprintln!("[WARN] Skip {} because the size exceeds {} bytes", &new_path, &max_size.unwrap_or(0));
-                            size_ok = false;
-                        }
-                    }
-                    if size_ok {
-                        match get(&sub_file, &new_path, destination_folder, fs, encrypt.as_ref(), ads, drive) {
-                            Ok(saved) => {
-                                local_visited_files.insert(path_check);
-                                if saved {
-                                    *success_files_count += 1;
-                                }
-                            }
-                            Err(e) => dprintln!("{}", e.to_string()),
-                        }
-                    }
-                }
+            let name = key.name().to_string_lossy();
+            if name == "." {
+                continue;
             }
+            let modified = nt_timestamp_to_system_time(key.modification_time().nt_timestamp());
+            let created = nt_timestamp_to_system_time(key.creation_time().nt_timestamp());
+            entries.push(NtfsEntry {
+                info: EntryInfo {
+                    name,
+                    is_directory: key.is_directory(),
+                    deleted: false,
+                    id: entry.file_reference().file_record_number(),
+                    size: 0,
+                    modified: Some(modified),
+                    created: Some(created),
+                    file_name_modified: Some(modified),
+                    file_name_created: Some(created),
+                },
+            });
         }
+        Ok(entries)
     }
 
-    Ok(local_visited_files)
-}
+    fn info(entry: &NtfsEntry) -> &EntryInfo {
+        &entry.info
+    }
 
-/// Recursively process NTFS directories and files and apply glob matching
-fn process_directory(
-    fs: &mut BufReader<SectorReader<File>>,
-    ntfs: &Ntfs,
-    file: &NtfsFile<'_>,
-    config_tree: &mut Node,
-    current_path: &str,
-    parent: &Entry,
-    destination_folder: &str,
-    visited_files: &mut HashSet<String>,
-    drive: &str,
-    success_files_count: &mut u32
-) -> Result<u32> {
-    let index = file.directory_index(fs)?;
-    let mut iter = index.entries();
-    let mut entries = Vec::new();
-    let mut first_elements = config_tree.get_first_level_items();
-    // Collect all entries into a vector
-    while let Some(entry_result) = iter.next(fs) {
-        match entry_result {
-            Ok(entry) => {
-                let name = entry
-                    .key()
-                    .unwrap()
-                    .unwrap()
-                    .name()
-                    .to_string_lossy()
-                    .to_string();
-                let file_record_number = entry.file_reference().file_record_number();
-                if name != "." {
-                    entries.push(Entry {
-                        name,
-                        file_record_number,
-                    });
-                }
-            }
-            Err(e) => return Err(e.into()),
-        }
+    /// Read the size and the $STANDARD_INFORMATION timestamps from the file record
+    fn file_info(&mut self, entry: &NtfsEntry) -> Result<EntryInfo> {
+        let file = self.ntfs.file(&mut self.fs, entry.info.id)?;
+        let si = file.info()?;
+        let mut info = entry.info.clone();
+        info.size = get_file_size(&file, &mut self.fs);
+        info.modified = Some(nt_timestamp_to_system_time(si.modification_time().nt_timestamp()));
+        info.created = Some(nt_timestamp_to_system_time(si.creation_time().nt_timestamp()));
+        Ok(info)
     }
-    for entry in &entries {
-        let new_path = format!("{}/{}", current_path, entry.name);
-        if let Ok(sub_file) = ntfs.file(fs, entry.file_record_number) {
-            for (obj_name, obj_node) in &mut first_elements {
-                if obj_node.all {
-                    if !visited_files.contains(&current_path.to_string()) {
-                        if let Ok(parent_obj) = ntfs.file(fs, parent.file_record_number) {
-                            match process_all_directory (
-                                fs,
-                                ntfs,
-                                &parent_obj,
-                                obj_name.to_string(),
-                                &current_path,
-                                destination_folder,
-                                drive,
-                                obj_node.encrypt.clone(),
-                                obj_node.max_size,
-                                success_files_count
-                                
-                            ) {
-                                Ok(current_visited_files) => {
-                                    visited_files.extend(current_visited_files);
-                                },
-                                Err(e) => dprintln!("[ERROR] Problem to process the entire folder: {}", e.to_string()),
-                            }
-                        }
-                    }
-                } else {
-                    let (obj_name_san, ads) = match obj_name.split_once(':') {
-                        Some((left, right)) => {
-                            let left_string = left.to_string(); 
-                            (left_string, right) 
-                        }
-                        None => (obj_name.to_string(), ""), 
-                    };
-                    let mut path_check = new_path.clone();
-                    if !(ads.is_empty() || ads == "") {
-                        path_check = format!("{}:{}", path_check, ads);
-                    }
-                    if !visited_files.contains(&path_check)
-                        && Pattern::new(&obj_name_san.as_str().to_lowercase())
-                            .unwrap()
-                            .matches(&new_path.as_str().to_lowercase())
-                    {
-                        if !&obj_name.contains("*") && !obj_node.all {
-                            obj_node.checked = true;
-                        }
-    
-                        if sub_file.is_directory() {
-                            if let Err(e) = process_directory (
-                                fs,
-                                ntfs,
-                                &sub_file,
-                                obj_node,
-                                &new_path,
-                                entry,
-                                destination_folder,
-                                visited_files,
-                                drive,
-                                success_files_count
-                            ){
-                                dprintln!("[ERROR] Problem to process the folder {:?} {}", &sub_file, e.to_string());
-                            }
-                        }
-                        let mut size_ok = true;
-                        // check size
-                        if let Some(msize) = obj_node.max_size {
-                            if get_file_size(&sub_file, fs) as u64 > msize {
-                                dprintln!("[WARN] Skip {} because the size exceeds {} bytes", &new_path, &obj_node.max_size.unwrap_or(0));
-                                size_ok = false;
-                            }
-                        }
-
-                        if size_ok && obj_node.children.is_empty() && !sub_file.is_directory() {
-                            match get(
-                                &sub_file,
-                                &path_check,
-                                destination_folder,
-                                fs,
-                                obj_node.encrypt.as_ref(),
-                                ads,
-                                drive,
-                            ) {
-                                Ok(saved) => {
-                                    visited_files.insert(path_check);
-                                    if saved {
-                                        *success_files_count += 1;
-                                    }
-                                }
-                                Err(e) => dprintln!("{}", e.to_string()),
-                            }
-                        }
-                    }
-                }
+
+    fn read_header(&mut self, entry: &NtfsEntry, stream: &str, length: usize) -> Result<Vec<u8>> {
+        let file = self.ntfs.file(&mut self.fs, entry.info.id)?;
+        let data_item = match file.data(&mut self.fs, stream) {
+            Some(item) => item?,
+            None => return Ok(Vec::new()),
+        };
+        let data_attribute = data_item.to_attribute()?;
+        let mut data_value = data_attribute.value(&mut self.fs)?;
+        let mut header = vec![0u8; length];
+        let mut read = 0;
+        while read < header.len() {
+            let n = data_value.read(&mut self.fs, &mut header[read..])?;
+            if n == 0 {
+                break;
             }
+            read += n;
         }
+        header.truncate(read);
+        Ok(header)
+    }
 
-        if first_elements.iter().all(|(_, node)| node.checked) {
-            break;
-        }
+    fn save(
+        &mut self,
+        entry: &NtfsEntry,
+        stream: &str,
+        file_name: &str,
+        destination_folder: &str,
+        encrypt: Option<&String>,
+    ) -> Result<bool> {
+        let file = self.ntfs.file(&mut self.fs, entry.info.id)?;
+        let file_name = if stream.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}:{}", file_name, stream)
+        };
+        get(&file, &file_name, destination_folder, &mut self.fs, encrypt, stream, &self.drive)
     }
 
-    Ok(*success_files_count)
+    /// Profiles of any drive of the running system are listed in the hive of the system drive
+    fn live_profiles(&mut self) -> Option<(char, ProfileList)> {
+        let drive = self.drive.chars().next()?;
+        let profile_list = SYSTEM_PROFILES.get_or_init(|| {
+            let system_drive = env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+            match open_drive(&system_drive) {
+                Ok(mut reader) => VolumeFacts::new(&mut reader).profile_list(),
+                Err(e) => {
+                    dprintln!("[ERROR] Problem to read the profiles of the system drive `{}`: {}", system_drive, e);
+                    None
+                }
+            }
+        });
+        Some((drive, profile_list.clone()?))
+    }
 }
 
-fn get_file_size(file: &NtfsFile, mut fs:  &mut BufReader<SectorReader<File>>) -> u64 {
-    let file_size = file.data(&mut fs, "").map_or(0, |data_item| {
+fn get_file_size(file: &NtfsFile, fs: &mut VolumeReader) -> u64 {
+    file.data(fs, "").map_or(0, |data_item| {
         data_item.map_or(0, |d| d.to_attribute().map_or(0, |a| a.value_length()))
-    });
-    file_size 
+    })
+}
+
+/// Read the sector and cluster geometry from the NTFS boot sector
+fn ntfs_geometry(boot_sector: &[u8], offset: u64) -> Geometry {
+    let bytes_per_sector = u16::from_le_bytes([boot_sector[11], boot_sector[12]]) as usize;
+    let sectors_per_cluster = match boot_sector[13] {
+        // Values above 128 are negative powers of two, used for clusters of 64 KiB and more.
+        // Shifts too large for a usize are left to `Geometry::new` as an invalid value.
+        raw if raw > 128 => 1usize.checked_shl(256 - raw as u32).unwrap_or(0),
+        raw => raw as usize,
+    };
+    Geometry::new(offset, bytes_per_sector, sectors_per_cluster)
 }
 
 /// Entry point for parsing the NTFS partition and applying glob matching
-fn explorer(ntfs_path: &str, config_tree: &mut Node, destination_folder: &str, drive: &str) -> Result<()> {
-    // Open the NTFS partition for reading
-    let file = File::open(ntfs_path)?;
-    let sr = SectorReader::new(file, 4096)?;
-    let mut fs = BufReader::new(sr);
+fn explorer(ntfs_path: &str, offset: u64, matcher: &Matcher, destination_folder: &str, drive: &str) -> Result<()> {
+    // Open the NTFS partition for reading with the geometry of its boot sector
+    let boot_area = read_boot_area(ntfs_path, offset)?;
+    let geometry = ntfs_geometry(&boot_area, offset);
+    dprintln!(
+        "[INFO] NTFS volume with sectors of {} bytes and clusters of {} bytes",
+        geometry.bytes_per_sector,
+        geometry.cluster_size()
+    );
+    let mut fs = geometry.open(ntfs_path)?;
 
     // Initialize NTFS parser
     let ntfs = initialize_ntfs(&mut fs)?;
 
-    // Process the root directory
-    let root_dir = ntfs.root_directory(&mut fs)?;
-
-    // Start processing directories from root
-    let mut visited_files: HashSet<String> = HashSet::new();
-
-    let file_record_number = root_dir.file_record_number();
-    let parent = Entry {
-        name: "\\".to_string(),
-        file_record_number,
+    let mut reader = NtfsReader {
+        fs,
+        ntfs,
+        drive: drive.to_string(),
     };
-    let mut success_files_count: u32 = 0;
-
-    match process_directory(
-        &mut fs,
-        &ntfs,
-        &root_dir,
-        config_tree,
-        "",
-        &parent,
-        destination_folder,
-        &mut visited_files,
-        drive,
-        &mut success_files_count
-    ) {
-        Ok(count) => {
-            dprintln!(
-                "[INFO] Collection completed with {} collected files",
-                count
-            );
-        },
-        Err(e) => dprintln!("[ERROR] Problem to process the folder: {:?}", e),
-    }
-
-    Ok(())
+    explore(&mut reader, matcher, destination_folder)
 }
 
-// Define the structure for the file tree
-#[derive(Debug)]
-struct Node {
-    children: HashMap<String, Node>,
-    checked: bool,
-    all: bool, // if there is an **
-    encrypt: Option<String>,
-    max_size: Option<u64>,
+/// Open a live NTFS drive to read its files outside of a collection
+fn open_drive(drive: &str) -> Result<NtfsReader> {
+    let drive_letter = drive.chars().next().unwrap_or('C');
+    let ntfs_path = format!("\\\\.\\{}:", drive_letter);
+    let boot_area = read_boot_area(&ntfs_path, 0)?;
+    let mut fs = ntfs_geometry(&boot_area, 0).open(&ntfs_path)?;
+    let ntfs = initialize_ntfs(&mut fs)?;
+    Ok(NtfsReader {
+        fs,
+        ntfs,
+        drive: drive.to_string(),
+    })
 }
 
-impl Node {
-    fn new_directory(all: bool, encrypt: Option<String>, max_size: Option<u64>) -> Self {
-        Node {
-            children: HashMap::new(),
-            checked: false,
-            all,
-            encrypt,
-            max_size
-        }
-    }
-
-    fn insert(&mut self, path: &str, files: Vec<String>, encrypt: Option<String>, max_size: Option<u64>) {
-        let parts: Vec<&str> = path
-            .trim_matches('/')
-            .split('/')
-            .filter(|s| !s.is_empty())
-            .collect();
-
-        if parts.is_empty() {
-            for file in files {
-                let file_path = format!("/{}", file);
-                self.children.insert(
-                    file_path.clone(),
-                    Node {
-                        children: HashMap::new(),
-                        checked: false,
-                        all: false,
-                        encrypt: encrypt.clone(),
-                        max_size
-                    },
-                );
-            }
-            return;
-        }
-
-        let mut current = self;
-        let mut current_path = String::new();
-        let mut all = false;
-        for (i, part) in parts.iter().enumerate() {
-            if *part == "**" {
-                all = true;
-                // Get the rest of the parts from the current index onward
-                let remaining_parts: Vec<_> = parts[i..].iter().cloned().collect();
-                for file in &files {
-                    let file_path =
-                        format!("{}/{}/{}", current_path, remaining_parts.join("/"), file);
-                    current.children.insert(
-                        file_path.clone(),
-                        Node {
-                            children: HashMap::new(),
-                            checked: false,
-                            all: true,
-                            encrypt: encrypt.clone(),
-                            max_size
-                        },
-                    );
-                }
-            } else {
-                current_path.push('/');
-                current_path.push_str(part);
-                current = current
-                    .children
-                    .entry(current_path.clone())
-                    .or_insert_with(|| {
-                        Node::new_directory( *part == "**" || current.all, encrypt.clone(), max_size)
-                    });
-            }
-        }
-        if !all {
-            for file in files {
-                let file_path = format!("{}/{}", current_path, file);
-                current.children.insert(
-                    file_path.clone(),
-                    Node {
-                        children: HashMap::new(),
-                        checked: false,
-                        all: current.all || file == "**",
-                        encrypt: encrypt.clone(),
-                        max_size
-                    },
-                );
-            }
-        }
-    }
-
-    fn get_first_level_items(&mut self) -> Vec<(&String, &mut Node)> {
-        self.children
-            .iter_mut()
-            .map(|(name, node)| (name, node))
-            .collect()
-    }
+/// Evaluate the `when` clause of a task on an NTFS drive, return the reason when it is false
+pub fn check_drive_condition(drive: &str, condition: &Condition) -> Result<(), String> {
+    let mut reader = open_drive(drive).map_err(|e| format!("the drive `{}` can't be read: {}", drive, e))?;
+    condition.check(&mut VolumeFacts::new(&mut reader))
 }
 
 pub fn process_drive_artifacts(
     drive: &str,
-    section_config: &mut SectionConfig,
+    section_config: &SectionConfig,
     output_path: &str,
 ) -> Result<()> {
+    let matcher = Matcher::new(section_config);
+
+    collect_drive(drive, &matcher, output_path)
+}
+
+fn collect_drive(drive: &str, matcher: &Matcher, output_path: &str) -> Result<()> {
     let drive_letter = drive.chars().next().unwrap();
 
     let ntfs_path: &str = &format!("\\\\.\\{}:", drive_letter);
 
-    let mut config_entries: HashMap<String, (Vec<String>, Option<String>, Option<u64>)> = HashMap::new();
-
-    section_config
-        .entries
-        .iter_mut()
-        .for_each(|(_, search_config_vec)| {
-            search_config_vec.iter_mut().for_each(|search_config| {
-                search_config
-                    .sanitize()
-                    .expect("[ERROR] Config sanitization failed");
-                let encrypt_option = search_config.encrypt.clone();
-                let max_size = search_config.get_max_size(section_config.max_size);
-                search_config.objects.iter().flatten().for_each(|object| {
-                    let c_obj = split_path(&object.replace("\\", "/"));
-                    let d_p: String = if c_obj.0.is_empty() {
-                        let d = search_config.root_path.clone().unwrap_or("/".to_string());
-                        if d.is_empty() {
-                            "/".to_string()
-                        } else {
-                            format!(
-                                "{}",
-                                search_config.root_path.clone().unwrap_or("/".to_string())
-                            )
-                        }
-                    } else {
-                        format!(
-                            "{}/{}",
-                            search_config.root_path.clone().unwrap_or("/".to_string()),
-                            c_obj.0
-                        )
-                    };
-                    let f_p = c_obj.1;
-                    config_entries
-                    .entry(d_p)
-                    .or_insert_with(|| (Vec::new(), encrypt_option.clone(), max_size))
-                    .0
-                    .push(f_p);
-                });
-            });
-        });
+    process_volume(ntfs_path, 0, drive, matcher, output_path)
+}
 
-    let mut tree = Node::new_directory(false, None, None);
+/// Collect the artifacts from a raw disk or partition image
+pub fn process_image_artifacts(
+    image_path: &str,
+    section_config: &SectionConfig,
+    output_path: &str,
+) -> Result<()> {
+    if !Path::new(image_path).exists() {
+        dprintln!("[ERROR] Image `{}` does not exist", image_path);
+        return Ok(());
+    }
 
-    // Populate the tree with the updated config_entries
-    for (path, (files, encrypt, max_size)) in config_entries {
-        tree.insert(&path, files, encrypt, max_size);
+    // A partition image holds the file system directly, a disk image has a partition table
+    let matcher = Matcher::new(section_config);
+    let boot_area = read_boot_area(image_path, 0)?;
+    if is_supported_volume(&boot_area)? {
+        return process_volume(image_path, 0, "", &matcher, output_path);
     }
 
-    explorer(ntfs_path, &mut tree, &output_path.replace("\\", "/"), drive)?;
+    let partitions = list_partitions(image_path)?;
+    if partitions.is_empty() {
+        dprintln!("[ERROR] `{}` has neither a supported file system nor a partition table", image_path);
+    }
+    for partition in partitions {
+        dprintln!(
+            "[INFO] Partition {} of `{}` at offset {} ({} bytes)",
+            partition.index,
+            image_path,
+            partition.offset,
+            partition.length
+        );
+        let partition_output = format!("{}/p{}", output_path, partition.index);
+        ensure_directory_exists(&partition_output)?;
+        if let Err(e) = process_volume(image_path, partition.offset, "", &matcher, &partition_output) {
+            dprintln!("[ERROR] Problem to process partition {}: {}", partition.index, e);
+        }
+    }
 
     Ok(())
 }
 
-pub fn list_ntfs_drives() -> io::Result<Vec<String>> {
-    let mut ntfs_drives = Vec::new();
+/// Choose the file system reader from the boot sector and collect the volume
+fn process_volume(volume_path: &str, offset: u64, drive: &str, matcher: &Matcher, output_path: &str) -> Result<()> {
+    let destination_folder = output_path.replace("\\", "/");
+    let mut boot_area = Cursor::new(read_boot_area(volume_path, offset)?);
+
+    if is_ntfs_partition(&mut boot_area)? {
+        explorer(volume_path, offset, matcher, &destination_folder, drive)?;
+    } else if fat_reader::is_fat_partition(&mut boot_area)? {
+        fat_reader::explorer(volume_path, offset, matcher, &destination_folder)?;
+    } else if ext4_reader::is_ext_partition(&mut boot_area)? {
+        ext4_reader::explorer(volume_path, offset, matcher, &destination_folder)?;
+    } else {
+        dprintln!("[ERROR] `{}` has an unsupported file system", volume_path);
+    }
+
+    Ok(())
+}
 
-    // Loop through the drives from A to Z and check if they are NTFS
+/// Check the boot area of a volume for a supported file system
+fn is_supported_volume(boot_area: &[u8]) -> io::Result<bool> {
+    let mut boot_area = Cursor::new(boot_area);
+    Ok(is_ntfs_partition(&mut boot_area)?
+        || fat_reader::is_fat_partition(&mut boot_area)?
+        || ext4_reader::is_ext_partition(&mut boot_area)?)
+}
+
+/// List the drives with a supported file system (NTFS, FAT12/16/32 and exFAT)
+pub fn list_supported_drives() -> io::Result<Vec<String>> {
+    let mut drives = Vec::new();
+
+    // Loop through the drives from A to Z and check their file system
     for letter in 'A'..='Z' {
         let drive = format!("{}:\\", letter);
 
         // Check if the drive exists before trying to open it
         if Path::new(&drive).exists() {
-            // Try to open the drive in raw mode to check if it's NTFS
+            // Try to open the drive in raw mode to read the boot sector
             let drive_path = format!("\\\\.\\{}:", letter);
-            if let Ok(mut file) = File::open(&drive_path) {
-                // Check if the partition is NTFS
-                if is_ntfs_partition(&mut file)? {
-                    // If it's NTFS, add it to the list
-                    ntfs_drives.push(drive);
+            if let Ok(boot_area) = read_boot_area(&drive_path, 0) {
+                let mut volume = Cursor::new(boot_area);
+                if is_ntfs_partition(&mut volume)? || fat_reader::is_fat_partition(&mut volume)? {
+                    drives.push(drive);
                 }
             }
         }
     }
-    Ok(ntfs_drives)
+    Ok(drives)
 }
 
 /// Function to check if a partition is NTFS by looking for the NTFS signature
@@ -525,9 +344,9 @@ fn is_ntfs_partition<T: Read + Seek>(reader: &mut T) -> io::Result<bool> {
     // Seek to the start of the partition and read the first 512 bytes (the boot sector)
     reader.seek(SeekFrom::Start(0))?;
     match reader.read_exact(&mut boot_sector) {
-        Ok(_) => return Ok(&boot_sector[3..11] == NTFS_SIGNATURE),
-        Err(_) => return Ok(false),
-    };
+        Ok(_) => Ok(&boot_sector[3..11] == NTFS_SIGNATURE),
+        Err(_) => Ok(false),
+    }
 }
 
 pub fn initialize_ntfs<T: Read + Seek>(fs: &mut T) -> Result<Ntfs> {
@@ -536,27 +355,100 @@ pub fn initialize_ntfs<T: Read + Seek>(fs: &mut T) -> Result<Ntfs> {
     Ok(ntfs)
 }
 
-/// Process all NTFS drives except the C drive
-pub fn process_all_drives(section_config: &mut SectionConfig, root_output: &str) -> Result<()> {
-    let ntfs_drives = list_ntfs_drives()?;
+/// Process all NTFS, FAT and exFAT drives except the excluded ones.
+/// With `parallel_devices`, the drives of different physical devices are collected at the same time.
+pub fn process_all_drives(section_config: &SectionConfig, root_output: &str) -> Result<()> {
+    let drives: Vec<String> = list_supported_drives()?
+        .into_iter()
+        .filter(|drive| {
+            !section_config
+                .exclude_drives
+                .as_ref()
+                .is_some_and(|excluded| excluded.iter().any(|d| drive.starts_with(d)))
+        })
+        .collect();
+
+    // The drives share the matcher, and so the budget of the task
+    let matcher = Matcher::new(section_config);
+    let parallel_devices = section_config.parallel_devices.unwrap_or(1);
+    if parallel_devices <= 1 {
+        for drive in drives {
+            process_one_drive(&drive, &matcher, root_output);
+        }
+        return Ok(());
+    }
 
-    'for_drive: for drive in ntfs_drives {
-        if let Some(iter_drives) = &section_config.exclude_drives {
-            for iter_drive in iter_drives {
-                if drive.starts_with(iter_drive) {
-                    continue 'for_drive;
-                }
-            }
+    // Volumes of the same device are read one after another to avoid seeking between them
+    let mut devices: Vec<(Option<u32>, Vec<String>)> = Vec::new();
+    for drive in drives {
+        let device = physical_device_number(&drive);
+        match devices.iter_mut().find(|(d, _)| device.is_some() && *d == device) {
+            Some((_, device_drives)) => device_drives.push(drive),
+            None => devices.push((device, vec![drive])),
         }
-        let drive_letter = drive.chars().next().unwrap();
-        let output_folder  = if root_output.contains("{{drive}}") {
-            root_output.replace("{{drive}}", &drive_letter.to_string())
-        } else {
-            format!("{}\\{}", root_output, drive_letter)
-        };
-        ensure_directory_exists(&output_folder)?;
-        process_drive_artifacts(&drive, section_config, &output_folder)?;
     }
+    dprintln!(
+        "[INFO] Collecting {} physical devices with up to {} in parallel",
+        devices.len(),
+        parallel_devices
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(parallel_devices)
+        .build()?;
+    pool.install(|| {
+        devices.par_iter().for_each(|(_, device_drives)| {
+            for drive in device_drives {
+                // Every volume opens its own reader and writes its log lines as one block
+                start_log_buffer();
+                process_one_drive(drive, &matcher, root_output);
+                flush_log_buffer();
+            }
+        });
+    });
 
     Ok(())
 }
+
+/// Collect one drive of `process_all_drives`, errors are logged so the other drives are still processed
+fn process_one_drive(drive: &str, matcher: &Matcher, root_output: &str) {
+    let drive_letter = drive.chars().next().unwrap();
+    let output_folder = if root_output.contains("{{drive}}") {
+        root_output.replace("{{drive}}", &drive_letter.to_string())
+    } else {
+        format!("{}\\{}", root_output, drive_letter)
+    };
+    dprintln!("[INFO] Processing drive `{}`", drive);
+    if let Err(e) = ensure_directory_exists(&output_folder) {
+        dprintln!("[ERROR] Problem to create the folder `{}`: {}", output_folder, e);
+        return;
+    }
+    if let Err(e) = collect_drive(drive, matcher, &output_folder) {
+        dprintln!("[ERROR] Problem to process the drive `{}`: {}", drive, e);
+    }
+}
+
+/// Number of the physical device holding a drive, `None` if it can't be found
+/// (e.g. a volume spanning several disks)
+fn physical_device_number(drive: &str) -> Option<u32> {
+    let drive_letter = drive.chars().next()?;
+    let volume = File::open(format!("\\\\.\\{}:", drive_letter)).ok()?;
+    let mut device_number: STORAGE_DEVICE_NUMBER = unsafe { mem::zeroed() };
+    let mut bytes_returned: DWORD = 0;
+    let result = unsafe {
+        DeviceIoControl(
+            volume.as_raw_handle() as _,
+            IOCTL_STORAGE_GET_DEVICE_NUMBER,
+            ptr::null_mut(),
+            0,
+            &mut device_number as *mut _ as _,
+            mem::size_of::<STORAGE_DEVICE_NUMBER>() as DWORD,
+            &mut bytes_returned,
+            ptr::null_mut(),
+        )
+    };
+    if result == 0 {
+        return None;
+    }
+    Some(device_number.DeviceNumber)
+}
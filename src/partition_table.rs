@@ -0,0 +1,246 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::sector_reader::SectorReader;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+const GPT_SIGNATURE: &[u8] = b"EFI PART";
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xEE;
+const MBR_EXTENDED_TYPES: [u8; 3] = [0x05, 0x0F, 0x85];
+const MAX_PARTITIONS: usize = 128;
+/// Largest GPT entry size accepted, entries are 128 bytes times a power of two
+const MAX_GPT_ENTRY_SIZE: usize = 4096;
+/// Largest number of extended boot records followed, including the ones without a partition
+const MAX_EBR_CHAIN: usize = 4 * MAX_PARTITIONS;
+
+/// A partition found in the partition table of a disk image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    pub index: usize,
+    pub offset: u64,
+    pub length: u64,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_sectors<T: Read + Seek>(reader: &mut T, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; length];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// List the partitions of a disk image from its GPT or MBR partition table
+pub fn list_partitions(path: &str) -> io::Result<Vec<Partition>> {
+    let mut reader = SectorReader::new(File::open(path)?, 512)?;
+    let mbr = read_sectors(&mut reader, 0, 512)?;
+    if mbr[510] != 0x55 || mbr[511] != 0xAA {
+        return Ok(Vec::new());
+    }
+
+    let mbr_types: Vec<u8> = (0..4).map(|i| mbr[446 + i * 16 + 4]).collect();
+    if mbr_types.contains(&MBR_TYPE_GPT_PROTECTIVE) {
+        // The GPT header is in the second logical block, which is 512 or 4096 bytes long
+        for lba_size in [512u64, 4096] {
+            if let Ok(partitions) = list_gpt_partitions(&mut reader, lba_size) {
+                if !partitions.is_empty() {
+                    return Ok(partitions);
+                }
+            }
+        }
+        return Ok(Vec::new());
+    }
+
+    list_mbr_partitions(&mut reader, &mbr)
+}
+
+fn list_gpt_partitions<T: Read + Seek>(reader: &mut T, lba_size: u64) -> io::Result<Vec<Partition>> {
+    let header = read_sectors(reader, lba_size, 512)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        return Ok(Vec::new());
+    }
+    let entries_lba = read_u64(&header, 72);
+    let entries_count = (read_u32(&header, 80) as usize).min(MAX_PARTITIONS);
+    let entry_size = read_u32(&header, 84) as usize;
+    if !(128..=MAX_GPT_ENTRY_SIZE).contains(&entry_size) || !entry_size.is_power_of_two() {
+        return Ok(Vec::new());
+    }
+    let Some(entries_offset) = entries_lba.checked_mul(lba_size) else {
+        return Ok(Vec::new());
+    };
+
+    let table = read_sectors(reader, entries_offset, entries_count * entry_size)?;
+    let mut partitions = Vec::new();
+    for (i, entry) in table.chunks_exact(entry_size).enumerate() {
+        // An all-zero partition type GUID marks an unused entry
+        if entry[0..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first_lba = read_u64(entry, 32);
+        let last_lba = read_u64(entry, 40);
+        if last_lba < first_lba {
+            continue;
+        }
+        let (Some(offset), Some(length)) = (
+            first_lba.checked_mul(lba_size),
+            (last_lba - first_lba).checked_add(1).and_then(|sectors| sectors.checked_mul(lba_size)),
+        ) else {
+            continue;
+        };
+        partitions.push(Partition {
+            index: i + 1,
+            offset,
+            length,
+        });
+    }
+    Ok(partitions)
+}
+
+fn list_mbr_partitions<T: Read + Seek>(reader: &mut T, mbr: &[u8]) -> io::Result<Vec<Partition>> {
+    let mut partitions = Vec::new();
+    for i in 0..4 {
+        let entry = &mbr[446 + i * 16..446 + (i + 1) * 16];
+        let partition_type = entry[4];
+        let start = read_u32(entry, 8) as u64;
+        let sectors = read_u32(entry, 12) as u64;
+        if partition_type == 0 || sectors == 0 {
+            continue;
+        }
+        if MBR_EXTENDED_TYPES.contains(&partition_type) {
+            list_logical_partitions(reader, start, &mut partitions)?;
+            continue;
+        }
+        partitions.push(Partition {
+            index: i + 1,
+            offset: start * 512,
+            length: sectors * 512,
+        });
+    }
+    Ok(partitions)
+}
+
+/// Follow the chain of extended boot records of an extended partition
+fn list_logical_partitions<T: Read + Seek>(reader: &mut T, extended_start: u64, partitions: &mut Vec<Partition>) -> io::Result<()> {
+    let mut ebr_lba = extended_start;
+    // Logical partitions are numbered from 5, as on Linux
+    let mut index = 5;
+    // A corrupt chain may loop back to a previous record, or go on through empty records
+    let mut visited = HashSet::new();
+    for _ in 0..MAX_EBR_CHAIN {
+        if partitions.len() >= MAX_PARTITIONS || !visited.insert(ebr_lba) {
+            break;
+        }
+        let ebr = read_sectors(reader, ebr_lba * 512, 512)?;
+        if ebr[510] != 0x55 || ebr[511] != 0xAA {
+            break;
+        }
+        let start = read_u32(&ebr, 446 + 8) as u64;
+        let sectors = read_u32(&ebr, 446 + 12) as u64;
+        if ebr[446 + 4] != 0 && sectors != 0 {
+            partitions.push(Partition {
+                index,
+                offset: (ebr_lba + start) * 512,
+                length: sectors * 512,
+            });
+            index += 1;
+        }
+        let next = read_u32(&ebr, 462 + 8) as u64;
+        if next == 0 || ebr[462 + 4] == 0 {
+            break;
+        }
+        ebr_lba = extended_start + next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn set_entry(sector: &mut [u8], slot: usize, partition_type: u8, start: u32, sectors: u32) {
+        let entry = 446 + slot * 16;
+        sector[entry + 4] = partition_type;
+        sector[entry + 8..entry + 12].copy_from_slice(&start.to_le_bytes());
+        sector[entry + 12..entry + 16].copy_from_slice(&sectors.to_le_bytes());
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+    }
+
+    #[test]
+    fn lists_primary_and_logical_partitions() {
+        let mut disk = vec![0u8; 16 * 512];
+        set_entry(&mut disk[..512], 0, 0x07, 63, 1000);
+        set_entry(&mut disk[..512], 1, 0x0F, 4, 2000);
+        // The first EBR at sector 4 links to the second one at sector 4 + 6
+        set_entry(&mut disk[4 * 512..5 * 512], 0, 0x83, 2, 100);
+        set_entry(&mut disk[4 * 512..5 * 512], 1, 0x05, 6, 200);
+        set_entry(&mut disk[10 * 512..11 * 512], 0, 0x83, 2, 50);
+
+        let mbr = disk[..512].to_vec();
+        let partitions = list_mbr_partitions(&mut Cursor::new(disk), &mbr).unwrap();
+        assert_eq!(
+            partitions,
+            [
+                Partition { index: 1, offset: 63 * 512, length: 1000 * 512 },
+                Partition { index: 5, offset: 6 * 512, length: 100 * 512 },
+                Partition { index: 6, offset: 12 * 512, length: 50 * 512 },
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_on_a_looping_ebr_chain() {
+        let mut disk = vec![0u8; 8 * 512];
+        // An empty EBR at sector 2 which links back to itself
+        set_entry(&mut disk[2 * 512..3 * 512], 1, 0x05, 0, 10);
+        disk[2 * 512 + 446 + 4] = 0;
+        let mut partitions = Vec::new();
+        list_logical_partitions(&mut Cursor::new(disk), 2, &mut partitions).unwrap();
+        assert!(partitions.is_empty());
+    }
+
+    fn gpt_disk(entry_size: u32, first_lba: u64, last_lba: u64) -> Vec<u8> {
+        let mut disk = vec![0u8; 8 * 512];
+        let header = &mut disk[512..1024];
+        header[0..8].copy_from_slice(GPT_SIGNATURE);
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&1u32.to_le_bytes());
+        header[84..88].copy_from_slice(&entry_size.to_le_bytes());
+        let entry = &mut disk[1024..1024 + 128];
+        entry[0] = 0xA2;
+        entry[32..40].copy_from_slice(&first_lba.to_le_bytes());
+        entry[40..48].copy_from_slice(&last_lba.to_le_bytes());
+        disk
+    }
+
+    #[test]
+    fn lists_gpt_partitions() {
+        let partitions = list_gpt_partitions(&mut Cursor::new(gpt_disk(128, 34, 99)), 512).unwrap();
+        assert_eq!(partitions, [Partition { index: 1, offset: 34 * 512, length: 66 * 512 }]);
+    }
+
+    #[test]
+    fn rejects_invalid_gpt_entries() {
+        for entry_size in [0, 127, 192, 8192, u32::MAX] {
+            let partitions = list_gpt_partitions(&mut Cursor::new(gpt_disk(entry_size, 34, 99)), 512).unwrap();
+            assert!(partitions.is_empty(), "entry size {}", entry_size);
+        }
+        let partitions = list_gpt_partitions(&mut Cursor::new(gpt_disk(128, u64::MAX / 2, u64::MAX)), 512).unwrap();
+        assert!(partitions.is_empty());
+    }
+}
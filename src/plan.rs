@@ -0,0 +1,120 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use once_cell::sync::{Lazy, OnceCell};
+use std::sync::Mutex;
+
+/// Suffix of the plan file written next to the output in dry run
+const PLAN_FILE_SUFFIX: &str = "_plan.csv";
+
+static DRY_RUN: OnceCell<bool> = OnceCell::new();
+
+/// Walk the volumes and plan the tools without copying, launching or writing anything but the plan
+pub fn set_dry_run() {
+    let _ = DRY_RUN.set(true);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.get().copied().unwrap_or(false)
+}
+
+/// A file or a tool of the plan
+#[derive(Debug, Clone)]
+struct PlannedItem {
+    task: String,
+    /// `file`, or the type of the tool (`external`, `system`, `internal`)
+    kind: String,
+    /// Path of the file on the volume, or name of the tool
+    path: String,
+    size: Option<u64>,
+    args: Vec<String>,
+    /// Folder of the collected file, or output file of the tool
+    output: String,
+}
+
+#[derive(Default)]
+struct Plan {
+    /// Tasks in the order they ran, the last one is the current task
+    tasks: Vec<String>,
+    items: Vec<PlannedItem>,
+}
+
+static PLAN: Lazy<Mutex<Plan>> = Lazy::new(|| Mutex::new(Plan::default()));
+
+/// Set the task the next planned files and tools belong to
+pub fn set_plan_task(task: &str) {
+    PLAN.lock().unwrap().tasks.push(task.to_string());
+}
+
+pub fn add_planned_file(folder: &str, path: &str, size: u64) {
+    let mut plan = PLAN.lock().unwrap();
+    let task = plan.tasks.last().cloned().unwrap_or_default();
+    plan.items.push(PlannedItem {
+        task,
+        kind: "file".to_string(),
+        path: path.to_string(),
+        size: Some(size),
+        args: Vec::new(),
+        output: folder.to_string(),
+    });
+}
+
+pub fn add_planned_tool(exec_type: &str, name: &str, args: &[String], output_file: &str) {
+    let mut plan = PLAN.lock().unwrap();
+    let task = plan.tasks.last().cloned().unwrap_or_default();
+    plan.items.push(PlannedItem {
+        task,
+        kind: exec_type.to_string(),
+        path: name.to_string(),
+        size: None,
+        args: args.to_vec(),
+        output: output_file.to_string(),
+    });
+}
+
+/// Write the plan file, then print the tools and the totals of each task
+pub fn save_plan(root_output: &str) -> Result<()> {
+    let plan = PLAN.lock().unwrap();
+    let plan_path = format!("{}{}", root_output, PLAN_FILE_SUFFIX);
+    let mut writer = csv::Writer::from_path(&plan_path)?;
+    writer.write_record(["task", "type", "path", "size", "args", "output"])?;
+    for item in &plan.items {
+        writer.write_record([
+            item.task.as_str(),
+            item.kind.as_str(),
+            item.path.as_str(),
+            &item.size.map(|size| size.to_string()).unwrap_or_default(),
+            &item.args.join(" "),
+            item.output.as_str(),
+        ])?;
+    }
+    writer.flush()?;
+
+    // Files and bytes of each task, in the order the tasks ran
+    let mut totals: IndexMap<&str, (u64, u64)> = plan.tasks.iter().map(|task| (task.as_str(), (0, 0))).collect();
+    for item in &plan.items {
+        let total = totals.entry(item.task.as_str()).or_default();
+        if let Some(size) = item.size {
+            total.0 += 1;
+            total.1 += size;
+        }
+    }
+    println!("== Dry run ==");
+    for (task, (files, bytes)) in &totals {
+        println!("Task `{}`: {} files, {} bytes", task, files, bytes);
+        for item in plan.items.iter().filter(|item| item.task == *task && item.size.is_none()) {
+            println!("  ({}) {} {} -> {}", item.kind, item.path, item.args.join(" "), item.output);
+        }
+    }
+    let total_bytes: u64 = totals.values().map(|(_, bytes)| bytes).sum();
+    println!("Total: {} bytes", total_bytes);
+    println!("The plan is written in `{}`", plan_path);
+    Ok(())
+}
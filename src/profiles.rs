@@ -0,0 +1,194 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use anyhow::Result;
+use nt_hive::Hive;
+
+const PROFILE_LIST_KEY: &str = "Microsoft\\Windows NT\\CurrentVersion\\ProfileList";
+/// Key of the SOFTWARE hive with the version and the folders of Windows
+pub const CURRENT_VERSION_KEY: &str = "Microsoft\\Windows NT\\CurrentVersion";
+
+/// Drive of Windows when the hive does not give it
+const DEFAULT_SYSTEM_DRIVE: char = 'C';
+
+/// Variables of a `root_path` expanded to every user profile, with their folder in the profile
+const PROFILE_VARS: [(&str, &str); 3] = [
+    ("{{user_profile}}", ""),
+    ("{{appdata}}", "\\AppData\\Roaming"),
+    ("{{localappdata}}", "\\AppData\\Local"),
+];
+
+/// Profile used in place of the real ones to validate the configuration
+const PLACEHOLDER_PROFILE: &str = "\\Users\\profile";
+
+pub fn uses_profile_vars(path: &str) -> bool {
+    PROFILE_VARS.iter().any(|(var, _)| path.contains(var))
+}
+
+/// Expand a path with profile variables to one path per profile, the path is kept as is without variables
+pub fn expand_profile_vars(path: &str, profiles: &[String]) -> Vec<String> {
+    if !uses_profile_vars(path) {
+        return vec![path.to_string()];
+    }
+    profiles
+        .iter()
+        .map(|profile| {
+            PROFILE_VARS.iter().fold(path.to_string(), |expanded, (var, folder)| {
+                expanded.replace(var, &format!("{}{}", profile, folder))
+            })
+        })
+        .collect()
+}
+
+/// Expand the profile variables with a placeholder profile, to check a `root_path` without a volume
+pub fn expand_with_placeholder(path: &str) -> String {
+    expand_profile_vars(path, &[PLACEHOLDER_PROFILE.to_string()]).remove(0)
+}
+
+/// Folders of the profiles of a Windows installation, with their drive (e.g. `D:\Users\bob`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileList {
+    /// Drive of Windows, holding the SOFTWARE hive the profiles come from
+    pub system_drive: char,
+    pub profiles: Vec<String>,
+}
+
+impl ProfileList {
+    /// Profiles stored on a drive, as paths of its volume (e.g. `\Users\bob`)
+    pub fn on_drive(&self, drive: char) -> Vec<String> {
+        self.profiles
+            .iter()
+            .filter_map(|profile| match split_drive(profile) {
+                (Some(letter), path) if letter.eq_ignore_ascii_case(&drive) => Some(path),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Read the folders of the profiles listed in the `ProfileList` key of an offline SOFTWARE hive
+pub fn list_profiles(software_hive: &[u8]) -> Result<ProfileList> {
+    // The hive of a running system is often dirty, its pending logs are not needed for the profiles
+    let hive = Hive::without_validation(software_hive)?;
+    let root_key_node = hive.root_key_node()?;
+
+    let system_root = match root_key_node.subpath(CURRENT_VERSION_KEY) {
+        Some(key_node) => key_node?
+            .value("SystemRoot")
+            .and_then(|value| value.ok())
+            .and_then(|value| value.string_data().ok()),
+        None => None,
+    };
+    let (system_drive, system_root) = match system_root.as_deref().map(split_drive) {
+        Some((drive, path)) => (drive.unwrap_or(DEFAULT_SYSTEM_DRIVE), path),
+        None => (DEFAULT_SYSTEM_DRIVE, "\\Windows".to_string()),
+    };
+
+    let mut profiles = Vec::new();
+    let profile_list = match root_key_node.subpath(PROFILE_LIST_KEY) {
+        Some(key_node) => key_node?,
+        None => return Ok(ProfileList { system_drive, profiles }),
+    };
+    let subkeys = match profile_list.subkeys() {
+        Some(subkeys) => subkeys?,
+        None => return Ok(ProfileList { system_drive, profiles }),
+    };
+    for key_node in subkeys {
+        let key_node = key_node?;
+        let sid = key_node.name()?.to_string();
+        let image_path = match key_node.value("ProfileImagePath") {
+            Some(value) => value?.string_data()?,
+            None => continue,
+        };
+        let profile = profile_path(&image_path, system_drive, &system_root);
+        dprintln!("[INFO] Profile of `{}` found in `{}`", sid, profile);
+        if !profiles.contains(&profile) {
+            profiles.push(profile);
+        }
+    }
+
+    Ok(ProfileList { system_drive, profiles })
+}
+
+/// Resolve a `ProfileImagePath` to a path with a drive, a path without drive being on the system drive
+fn profile_path(image_path: &str, system_drive: char, system_root: &str) -> String {
+    let system_drive = format!("{}:", system_drive.to_ascii_uppercase());
+    let path = replace_case_insensitive(
+        &replace_case_insensitive(image_path, "%SystemRoot%", &format!("{}{}", system_drive, system_root)),
+        "%SystemDrive%",
+        &system_drive,
+    );
+    let (drive, path) = split_drive(&path);
+    let drive = drive.map_or(system_drive, |letter| format!("{}:", letter.to_ascii_uppercase()));
+    format!("{}{}", drive, path)
+}
+
+/// Split the drive letter of a path, the path keeps a single leading `\` and no trailing one
+fn split_drive(path: &str) -> (Option<char>, String) {
+    let (drive, path) = match path.as_bytes() {
+        [letter, b':', ..] if letter.is_ascii_alphabetic() => (Some(*letter as char), &path[2..]),
+        _ => (None, path),
+    };
+    (drive, format!("\\{}", path.trim_matches('\\')))
+}
+
+fn replace_case_insensitive(value: &str, from: &str, to: &str) -> String {
+    match value.to_lowercase().find(&from.to_lowercase()) {
+        Some(start) if value.is_char_boundary(start) && value.is_char_boundary(start + from.len()) => {
+            format!("{}{}{}", &value[..start], to, &value[start + from.len()..])
+        }
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_drive_of_the_profiles() {
+        for (image_path, expected) in [
+            ("C:\\Users\\alice", "C:\\Users\\alice"),
+            ("d:\\Users\\bob\\", "D:\\Users\\bob"),
+            ("%SystemDrive%\\Users\\carol", "E:\\Users\\carol"),
+            ("%systemroot%\\system32\\config\\systemprofile", "E:\\Windows\\system32\\config\\systemprofile"),
+            ("\\Users\\dave", "E:\\Users\\dave"),
+        ] {
+            assert_eq!(profile_path(image_path, 'E', "\\Windows"), expected, "{}", image_path);
+        }
+    }
+
+    #[test]
+    fn selects_the_profiles_of_a_drive() {
+        let profile_list = ProfileList {
+            system_drive: 'C',
+            profiles: vec![
+                "C:\\Users\\alice".to_string(),
+                "D:\\Users\\bob".to_string(),
+                "C:\\Windows\\ServiceProfiles\\LocalService".to_string(),
+            ],
+        };
+        assert_eq!(profile_list.on_drive('C'), ["\\Users\\alice", "\\Windows\\ServiceProfiles\\LocalService"]);
+        assert_eq!(profile_list.on_drive('d'), ["\\Users\\bob"]);
+        assert!(profile_list.on_drive('E').is_empty());
+    }
+
+    #[test]
+    fn expands_the_profile_variables() {
+        let profiles = ["\\Users\\alice".to_string(), "\\Users\\bob".to_string()];
+        assert_eq!(
+            expand_profile_vars("{{appdata}}\\Microsoft\\Windows\\Recent", &profiles),
+            [
+                "\\Users\\alice\\AppData\\Roaming\\Microsoft\\Windows\\Recent",
+                "\\Users\\bob\\AppData\\Roaming\\Microsoft\\Windows\\Recent",
+            ]
+        );
+        assert_eq!(expand_profile_vars("\\Windows\\Prefetch", &profiles), ["\\Windows\\Prefetch"]);
+        assert!(expand_profile_vars("{{user_profile}}\\NTUSER.DAT", &[]).is_empty());
+    }
+}
@@ -70,7 +70,7 @@ pub fn add_resource(
         return Err(io::Error::last_os_error());
     }
 
-    return Ok(());
+    Ok(())
 }
 
 pub fn remove_resource(resource_name: &str, output_path: &str) -> io::Result<()> {
@@ -103,7 +103,7 @@ pub fn remove_resource(resource_name: &str, output_path: &str) -> io::Result<()>
 
     // Copy the current executable to the specified output file
     let current_exe = env::current_exe().expect("Failed to get current executable path");
-    fs::copy(&current_exe, &output_path)?;
+    fs::copy(&current_exe, output_path)?;
 
     // Open the executable for resource updates
     let output_cstr = CString::new(output_path)
@@ -228,6 +228,6 @@ pub fn extract_resource(
 
         let data_slice = std::slice::from_raw_parts(resource_data as *const u8, resource_size as usize);
 
-        return Ok(data_slice.to_vec());
+        Ok(data_slice.to_vec())
     }
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::config::SectionConfig;
+use anyhow::Result;
+use std::any::Any;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/// Number of tasks running at the same time without `max_parallel_tasks`
+pub const DEFAULT_MAX_PARALLEL_TASKS: usize = 1;
+
+struct State {
+    /// Tasks waiting to start, in priority order
+    pending: Vec<(String, SectionConfig)>,
+    running: HashSet<String>,
+    exclusive_running: bool,
+    error: Option<anyhow::Error>,
+}
+
+impl State {
+    /// A task is ready when none of its dependencies is waiting or running
+    fn is_ready(&self, section_config: &SectionConfig) -> bool {
+        section_config.depends_on.iter().flatten().all(|dependency| {
+            !self.running.contains(dependency) && !self.pending.iter().any(|(name, _)| name == dependency)
+        })
+    }
+
+    /// Position of the next task to start, `None` if no task can start now
+    fn next_task(&self, max_parallel: usize) -> Option<usize> {
+        if self.exclusive_running || self.running.len() >= max_parallel {
+            return None;
+        }
+        let index = self
+            .pending
+            .iter()
+            .position(|(_, section_config)| self.is_ready(section_config))?;
+        // An exclusive task waits for the running tasks to finish, the next tasks wait for it
+        if self.pending[index].1.exclusive == Some(true) && !self.running.is_empty() {
+            return None;
+        }
+        Some(index)
+    }
+}
+
+/// Run the tasks as soon as their `depends_on` tasks are done, with at most `max_parallel` tasks
+/// at the same time. The ready tasks start in the order of `tasks`, which is the priority order.
+/// No task starts after a failed one, the first error is returned when the running tasks are done.
+/// A task which panics fails like a task returning an error.
+pub fn run_tasks<F>(tasks: Vec<(String, SectionConfig)>, max_parallel: usize, run: F) -> Result<()>
+where
+    F: Fn(&str, &SectionConfig) -> Result<()> + Sync,
+{
+    let state = Mutex::new(State {
+        pending: tasks,
+        running: HashSet::new(),
+        exclusive_running: false,
+        error: None,
+    });
+    let finished = Condvar::new();
+    let max_parallel = max_parallel.max(1);
+    let run = &run;
+
+    thread::scope(|scope| {
+        let mut guard = state.lock().unwrap();
+        loop {
+            if guard.error.is_some() {
+                guard.pending.clear();
+            }
+            if guard.pending.is_empty() && guard.running.is_empty() {
+                break;
+            }
+            let Some(index) = guard.next_task(max_parallel) else {
+                if guard.running.is_empty() {
+                    // Only a loop of dependencies leaves waiting tasks that can never start
+                    let names: Vec<String> = guard.pending.iter().map(|(name, _)| name.clone()).collect();
+                    guard.error = Some(anyhow::anyhow!(
+                        "[ERROR] The tasks {} wait for each other",
+                        names.join(", ")
+                    ));
+                    continue;
+                }
+                guard = finished.wait(guard).unwrap();
+                continue;
+            };
+
+            let (name, section_config) = guard.pending.remove(index);
+            let exclusive = section_config.exclusive == Some(true);
+            guard.running.insert(name.clone());
+            guard.exclusive_running = exclusive;
+            let (state, finished) = (&state, &finished);
+            scope.spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| run(&name, &section_config)))
+                    .unwrap_or_else(|payload| Err(anyhow::anyhow!("[ERROR] Panic: {}", panic_message(&*payload))));
+                let mut guard = state.lock().unwrap();
+                guard.running.remove(&name);
+                if exclusive {
+                    guard.exclusive_running = false;
+                }
+                if let Err(e) = result {
+                    dprintln!("[ERROR] Task `{}` failed: {}", name, e);
+                    guard.error.get_or_insert(e);
+                }
+                finished.notify_all();
+            });
+        }
+    });
+
+    match state.into_inner().unwrap().error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, yaml: &str) -> (String, SectionConfig) {
+        let yaml = format!("{{priority: 1, type: collect, entries: {{}}, {}}}", yaml);
+        (name.to_string(), serde_yaml::from_str(&yaml).unwrap())
+    }
+
+    #[test]
+    fn runs_the_dependencies_first() {
+        let order = Mutex::new(Vec::new());
+        let tasks = vec![task("b", "depends_on: [a]"), task("a", ""), task("c", "depends_on: [b]")];
+        run_tasks(tasks, 2, |name, _| {
+            order.lock().unwrap().push(name.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(order.into_inner().unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn reports_a_panicking_task_as_failed() {
+        for max_parallel in [1, 2] {
+            let ran = Mutex::new(Vec::new());
+            let tasks = vec![task("a", ""), task("b", "depends_on: [a]")];
+            let result = run_tasks(tasks, max_parallel, |name, _| {
+                ran.lock().unwrap().push(name.to_string());
+                if name == "a" {
+                    panic!("task a panics");
+                }
+                Ok(())
+            });
+            assert_eq!(result.unwrap_err().to_string(), "[ERROR] Panic: task a panics");
+            assert_eq!(ran.into_inner().unwrap(), ["a"]);
+        }
+    }
+
+    #[test]
+    fn reports_a_loop_of_dependencies() {
+        let tasks = vec![task("a", "depends_on: [b]"), task("b", "depends_on: [a]")];
+        let result = run_tasks(tasks, DEFAULT_MAX_PARALLEL_TASKS, |_, _| Ok(()));
+        assert_eq!(result.unwrap_err().to_string(), "[ERROR] The tasks a, b wait for each other");
+    }
+}
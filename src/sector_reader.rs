@@ -9,8 +9,83 @@
 // Copyright 2021 Colin Finck <colin@reactos.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use lru::LruCache;
+use std::fs::File;
 use std::io;
 use std::io::{Read, Seek, SeekFrom};
+use std::num::NonZeroUsize;
+
+/// Size of the area read at the start of a volume to identify its file system.
+/// It is a multiple of every supported sector size.
+pub const BOOT_AREA_SIZE: usize = 4096;
+
+/// Minimum size of the reads issued to the device, rounded to whole clusters
+const MIN_READ_SIZE: usize = 64 * 1024;
+
+/// Memory used by the cluster cache of a volume
+const CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Largest cluster size accepted from a boot sector, NTFS and exFAT clusters are at most 2 MiB
+const MAX_CLUSTER_SIZE: usize = 2 * 1024 * 1024;
+
+/// Reader of a volume: sector aligned reads behind an LRU cache of clusters
+pub type VolumeReader = ClusterCache<File>;
+
+/// Sector and cluster geometry of a volume, and its offset in the underlying device or image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub offset: u64,
+    pub bytes_per_sector: usize,
+    pub sectors_per_cluster: usize,
+}
+
+impl Geometry {
+    /// Build a geometry from boot sector values, falling back to safe values when they are invalid
+    pub fn new(offset: u64, bytes_per_sector: usize, sectors_per_cluster: usize) -> Self {
+        let bytes_per_sector = if bytes_per_sector.is_power_of_two() && (512..=BOOT_AREA_SIZE).contains(&bytes_per_sector) {
+            bytes_per_sector
+        } else {
+            BOOT_AREA_SIZE
+        };
+        let sectors_per_cluster = if sectors_per_cluster.is_power_of_two()
+            && bytes_per_sector.saturating_mul(sectors_per_cluster) <= MAX_CLUSTER_SIZE
+        {
+            sectors_per_cluster
+        } else {
+            1
+        };
+        Geometry {
+            offset,
+            bytes_per_sector,
+            sectors_per_cluster,
+        }
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    /// Size of the reads issued to the device, always a whole number of clusters
+    pub fn read_size(&self) -> usize {
+        let cluster_size = self.cluster_size();
+        cluster_size * (MIN_READ_SIZE / cluster_size).max(1)
+    }
+
+    /// Open a volume for sector aligned reads cached in clusters and read ahead in cluster sized runs
+    pub fn open(&self, path: &str) -> io::Result<VolumeReader> {
+        let sr = SectorReader::with_offset(File::open(path)?, self.bytes_per_sector, self.offset)?;
+        Ok(ClusterCache::new(sr, self.cluster_size(), self.read_size() / self.cluster_size(), CACHE_SIZE))
+    }
+}
+
+/// Read the first bytes of a volume to identify its file system and geometry.
+/// The read is aligned on `BOOT_AREA_SIZE`, so it works before the sector size is known.
+pub fn read_boot_area(path: &str, offset: u64) -> io::Result<Vec<u8>> {
+    let mut sr = SectorReader::with_offset(File::open(path)?, BOOT_AREA_SIZE, offset)?;
+    let mut boot_area = vec![0u8; BOOT_AREA_SIZE];
+    sr.read_exact(&mut boot_area)?;
+    Ok(boot_area)
+}
 
 /// `SectorReader` encapsulates any reader and only performs read and seek operations on it
 /// on boundaries of the given sector size.
@@ -30,6 +105,10 @@ where
     inner: R,
     /// The sector size set at creation.
     sector_size: usize,
+    /// Offset of the volume in the inner reader (e.g. a partition in a disk image), aligned down on the sector size.
+    offset: u64,
+    /// Distance between the aligned `offset` and the start of the volume.
+    skew: u64,
     /// The current stream position as requested by the caller through `read` or `seek`.
     /// The implementation will internally make sure to only read/seek on sector boundaries.
     stream_position: u64,
@@ -42,19 +121,28 @@ where
     R: Read + Seek,
 {
     pub fn new(inner: R, sector_size: usize) -> io::Result<Self> {
+        Self::with_offset(inner, sector_size, 0)
+    }
+
+    /// Create a reader for a volume starting at `offset` bytes in the inner reader.
+    /// The reads stay aligned on the sector size of the inner reader when the offset is not,
+    /// e.g. for a MBR partition starting at sector 63 read with 4096 bytes sectors.
+    pub fn with_offset(inner: R, sector_size: usize, offset: u64) -> io::Result<Self> {
         if !sector_size.is_power_of_two() {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "sector_size is not a power of two",
-            ));
+            return Err(io::Error::other("sector_size is not a power of two"));
         }
+        let skew = offset % sector_size as u64;
 
-        Ok(Self {
+        let mut reader = Self {
             inner,
             sector_size,
+            offset: offset - skew,
+            skew,
             stream_position: 0,
             temp_buf: Vec::new(),
-        })
+        };
+        reader.inner.seek(SeekFrom::Start(reader.offset))?;
+        Ok(reader)
     }
 
     fn align_down_to_sector_size(&self, n: u64) -> u64 {
@@ -74,22 +162,41 @@ where
         // We can only read from a sector boundary, and `self.stream_position` specifies the position where the
         // caller thinks we are.
         // Align down to a sector boundary to determine the position where we really are (see our `seek` implementation).
-        let aligned_position = self.align_down_to_sector_size(self.stream_position);
+        let position = self.stream_position + self.skew;
+        let aligned_position = self.align_down_to_sector_size(position);
 
         // We have to read more bytes now to make up for the alignment difference.
         // We can also only read in multiples of the sector size, so align up to the next sector boundary.
-        let start = (self.stream_position - aligned_position) as usize;
+        let start = (position - aligned_position) as usize;
         let end = start + buf.len();
         let aligned_bytes_to_read = self.align_up_to_sector_size(end as u64) as usize;
 
         // Perform the sector-sized read and copy the actually requested bytes into the given buffer.
+        // The inner reader may return less, e.g. at the end of an image whose length is not aligned.
         self.temp_buf.resize(aligned_bytes_to_read, 0);
-        self.inner.read(&mut self.temp_buf)?;
-        buf.copy_from_slice(&self.temp_buf[start..end]);
+        let mut filled = 0;
+        while filled < aligned_bytes_to_read {
+            match self.inner.read(&mut self.temp_buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        if filled <= start {
+            return Ok(0);
+        }
+        let read = end.min(filled) - start;
+        buf[..read].copy_from_slice(&self.temp_buf[start..start + read]);
+
+        // Keep the inner reader on a sector boundary for the next read.
+        let new_position = self.stream_position + read as u64;
+        let aligned_new_position = self.align_down_to_sector_size(new_position + self.skew);
+        self.inner.seek(SeekFrom::Start(self.offset + aligned_new_position))?;
 
         // We are done.
-        self.stream_position += buf.len() as u64;
-        Ok(buf.len())
+        self.stream_position = new_position;
+        Ok(read)
     }
 }
 
@@ -104,10 +211,7 @@ where
                 // This is unsupported, because it's not safely possible under Windows.
                 // We cannot seek to the end to determine the raw partition size.
                 // Which makes it impossible to set `self.stream_position`.
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "SeekFrom::End is unsupported for SectorReader",
-                ));
+                return Err(io::Error::other("SeekFrom::End is unsupported for SectorReader"));
             }
             SeekFrom::Current(n) => {
                 if n >= 0 {
@@ -118,11 +222,11 @@ where
             }
         };
 
-        match new_pos {
+        match new_pos.filter(|n| n.checked_add(self.skew).is_some()) {
             Some(n) => {
                 // We can only seek on sector boundaries, so align down the requested seek position and seek to that.
-                let aligned_n = self.align_down_to_sector_size(n);
-                self.inner.seek(SeekFrom::Start(aligned_n))?;
+                let aligned_n = self.align_down_to_sector_size(n + self.skew);
+                self.inner.seek(SeekFrom::Start(self.offset + aligned_n))?;
 
                 // Make the caller believe that we seeked to the actually requested position.
                 // Our `read` implementation will cover the difference.
@@ -135,4 +239,178 @@ where
             )),
         }
     }
-}
\ No newline at end of file
+}
+
+/// `ClusterCache` keeps the most recently used clusters of a volume in memory.
+///
+/// Small reads (MFT and index records, directory blocks...) are served from an LRU of clusters
+/// filled by read-ahead runs of `read_ahead` clusters, and seeking never touches the device.
+/// Reads of at least `read_ahead` clusters come from the streaming of file content, which reads
+/// whole data runs: they go straight to the device and do not evict the metadata from the cache.
+pub struct ClusterCache<R>
+where
+    R: Read + Seek,
+{
+    inner: SectorReader<R>,
+    cluster_size: usize,
+    read_ahead: usize,
+    clusters: LruCache<u64, Vec<u8>>,
+    position: u64,
+    /// Number of reads served from the cache and from the device, for diagnostics and benchmarks.
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<R> ClusterCache<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(inner: SectorReader<R>, cluster_size: usize, read_ahead: usize, cache_size: usize) -> Self {
+        let capacity = NonZeroUsize::new((cache_size / cluster_size).max(read_ahead).max(1)).unwrap();
+        Self {
+            inner,
+            cluster_size,
+            read_ahead: read_ahead.max(1),
+            clusters: LruCache::new(capacity),
+            position: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Load a run of clusters starting at `cluster` in the cache
+    fn load(&mut self, cluster: u64) -> io::Result<()> {
+        let run_size = self.read_ahead * self.cluster_size;
+        let mut run = vec![0u8; run_size];
+        self.inner.seek(SeekFrom::Start(cluster * self.cluster_size as u64))?;
+
+        let mut filled = 0;
+        while filled < run_size {
+            match self.inner.read(&mut run[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        run.truncate(filled);
+
+        for (i, data) in run.chunks(self.cluster_size).enumerate() {
+            // Keep the clusters already cached, they may be more recent than the read-ahead
+            if i == 0 || !self.clusters.contains(&(cluster + i as u64)) {
+                self.clusters.put(cluster + i as u64, data.to_vec());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R> Read for ClusterCache<R>
+where
+    R: Read + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Streaming path for file content
+        if buf.len() >= self.read_ahead * self.cluster_size {
+            self.misses += 1;
+            self.inner.seek(SeekFrom::Start(self.position))?;
+            let n = self.inner.read(buf)?;
+            self.position += n as u64;
+            return Ok(n);
+        }
+
+        let cluster = self.position / self.cluster_size as u64;
+        let start = (self.position % self.cluster_size as u64) as usize;
+        if self.clusters.contains(&cluster) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            self.load(cluster)?;
+        }
+
+        let n = match self.clusters.get(&cluster) {
+            Some(data) if start < data.len() => {
+                let n = buf.len().min(data.len() - start);
+                buf[..n].copy_from_slice(&data[start..start + n]);
+                n
+            }
+            // End of the volume
+            _ => 0,
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Seek for ClusterCache<R>
+where
+    R: Read + Seek,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(_n) => {
+                return Err(io::Error::other("SeekFrom::End is unsupported for ClusterCache"));
+            }
+            SeekFrom::Current(n) => {
+                if n >= 0 {
+                    self.position.checked_add(n as u64)
+                } else {
+                    self.position.checked_sub(n.wrapping_neg() as u64)
+                }
+            }
+        };
+
+        match new_pos {
+            Some(n) => {
+                self.position = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn disk() -> Vec<u8> {
+        (0..64 * 1024).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn reads_a_volume_at_an_unaligned_offset() {
+        // A MBR partition starting at sector 63, read with 4096 bytes sectors
+        let disk = disk();
+        let offset = 63 * 512;
+        let mut sr = SectorReader::with_offset(Cursor::new(disk.clone()), BOOT_AREA_SIZE, offset as u64).unwrap();
+        let mut buf = vec![0u8; BOOT_AREA_SIZE];
+        sr.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, disk[offset..offset + BOOT_AREA_SIZE]);
+
+        sr.seek(SeekFrom::Start(5000)).unwrap();
+        let mut buf = vec![0u8; 300];
+        sr.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, disk[offset + 5000..offset + 5300]);
+    }
+
+    #[test]
+    fn clamps_invalid_geometries() {
+        assert_eq!(Geometry::new(0, 512, 8).cluster_size(), 4096);
+        assert_eq!(Geometry::new(0, 4096, 512).cluster_size(), 2 * 1024 * 1024);
+        // Clusters larger than 2 MiB, and values which are not powers of two
+        assert_eq!(Geometry::new(0, 4096, 1024).sectors_per_cluster, 1);
+        assert_eq!(Geometry::new(0, 512, 0).sectors_per_cluster, 1);
+        assert_eq!(Geometry::new(0, 512, usize::MAX).sectors_per_cluster, 1);
+        assert_eq!(Geometry::new(0, 1000, 1).bytes_per_sector, BOOT_AREA_SIZE);
+    }
+}
@@ -7,26 +7,33 @@
 //
 use aes_gcm::aead::{Aead, KeyInit, OsRng};
 use aes_gcm::{Aes256Gcm, Key, Nonce}; // AES-GCM cipher
+use crate::archive::{create_output, OutputFile};
+use crate::config::get_config;
+use crate::plan::is_dry_run;
 use anyhow::{Error, Result};
 use chrono::{DateTime, Local};
-use filetime::{set_file_handle_times, FileTime};
 use ntfs::{NtfsAttribute, NtfsAttributeType, NtfsFile, NtfsReadSeek};
 use rand::RngCore;
 use regex::Regex;
 use sha2::{Digest, Sha256};
+use std::cell::RefCell;
 use std::env;
 use std::fs;
 use std::fs::File;
-use std::fs::{create_dir_all, OpenOptions};
+use std::fs::OpenOptions;
 use std::io;
-use std::io::ErrorKind;
 use std::io::SeekFrom;
 use std::io::{Read, Seek, Write};
 use std::path::Path;
+use std::sync::Mutex;
 use std::time::Duration;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
+/// Size of the chunks used to stream file content, large enough to read whole data runs
+/// and bypass the cluster cache of the volume reader
+const STREAM_BUFFER_SIZE: usize = 1024 * 1024;
+
 pub fn get<T>(
     file: &NtfsFile,
     file_name: &str,
@@ -40,48 +47,29 @@ where
     T: Read + Seek,
 {
     // Check if encryption is required and construct the output file name
-    let mut output_file_name = if let Some(ref password) = encrypt {
-        if !password.is_empty() {
-            let path = Path::new(&file_name);
-            let new_file_name = if let Some(extension) = path.extension() {
-                format!("{}.enc", path.with_extension(extension).to_string_lossy())
-            } else {
-                format!("{}.enc", path.to_string_lossy())
-            };
-            format!("{}{}", out_dir, new_file_name)
-        } else {
-            format!("{}{}", out_dir, file_name)
-        }
-    } else {
-        format!("{}{}", out_dir, file_name)
+    let output_name = match encrypt {
+        Some(password) if !password.is_empty() => format!("{}.enc", file_name),
+        _ => file_name.to_string(),
     };
+    let is_ads = !ads.is_empty();
 
-    // Try to create the directory, log error if it fails
-    if let Err(e) = create_dir_all(
-        output_file_name
-            .rfind('/')
-            .map(|pos| &output_file_name[..pos])
-            .unwrap_or(""),
-    ) {
-        return Err(anyhow::anyhow!(
-            "[ERROR] Failed to create directory `{}`: {}",
-            out_dir,
-            e
-        ));
-    }
-    let is_ads = !(ads.is_empty() || ads == "");
+    // Escape the `:` of the Alternate Data Stream (ADS) name, not the one of the output drive
+    let output_file_name = format!("{}{}", out_dir, output_name.replace(":", "%3A"));
 
-    // Append the Alternate Data Stream (ADS) name if it's not empty
-    output_file_name = output_file_name.replace(":", "%3A");
+    // Modification time of the saved file
+    let modified = file.info().ok().map(|file_std_info| {
+        let modified_time: DateTime<Local> =
+            nt_timestamp_to_datetime(file_std_info.modification_time().nt_timestamp());
+        add_timezone_offset_to_system_time(
+            modified_time.into(),
+            modified_time.offset().local_minus_utc().into(),
+        )
+    });
 
     // Try to open the file for writing, log error if it fails
-    let mut output_file = match OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&output_file_name)
-    {
-        Ok(f) => f,
-        Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+    let mut output_file = match create_output(&output_file_name, modified) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
             return Ok(false);
         }
         Err(e) => {
@@ -159,10 +147,10 @@ where
     );
 
     // Buffer for reading chunks of the file
-    let mut read_buf = [0u8; 4096];
+    let mut read_buf = vec![0u8; STREAM_BUFFER_SIZE];
 
     // Stream data based on encryption
-    if let Some(ref password) = encrypt {
+    if let Some(password) = encrypt {
         if !password.is_empty() {
             // Derive the encryption key using SHA256
             let mut hasher = Sha256::new();
@@ -195,13 +183,9 @@ where
                             current_file_size += bytes_read as u64;
                             if current_file_size > valid_data_length {
                                 // Write remaining data (including current read buffer) to a "slack" file
-                                let mut slack_file = match OpenOptions::new()
-                                    .write(true)
-                                    .create_new(true)
-                                    .open(&format!("{}.FileSlack", output_file_name))
-                                {
-                                    Ok(f) => f,
-                                    Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                                let mut slack_file = match create_output(&format!("{}.FileSlack", output_file_name), None) {
+                                    Ok(Some(f)) => f,
+                                    Ok(None) => {
                                         return Ok(false);
                                     }
                                     Err(e) => {
@@ -232,11 +216,12 @@ where
                                     padding = vec![0; slack_bytes_read];
                                     output_file.write_all(&padding)?;
                                 }
+                                slack_file.finish()?;
                                 break;
                             }
                         }
 
-                        let chunk = if is_ads && read_buf.iter().all(|&b| b == 0) {
+                        let chunk = if is_ads && read_buf[..bytes_read].iter().all(|&b| b == 0) {
                             continue;
                         } else {
                             &read_buf[..bytes_read]
@@ -266,8 +251,9 @@ where
         }
     } else {
         // No encryption, write the file normally in chunks
-        if file_name == "/$Boot" {
-            output_file.write_all(&get_boot(&drive).unwrap()).unwrap();
+        // Raw images have no drive letter, their $Boot is read as a regular file
+        if file_name == "/$Boot" && !drive.is_empty() {
+            output_file.write_all(&get_boot(drive).unwrap()).unwrap();
         } else {
             let mut current_file_size: u64 = 0;
             loop {
@@ -281,13 +267,9 @@ where
                             // Check if the Valid data is reached
                             if current_file_size > valid_data_length {
                                 // Write remaining data (including current read buffer) to a "slack" file
-                                let mut slack_file = match OpenOptions::new()
-                                    .write(true)
-                                    .create_new(true)
-                                    .open(&format!("{}.FileSlack", output_file_name))
-                                {
-                                    Ok(f) => f,
-                                    Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+                                let mut slack_file = match create_output(&format!("{}.FileSlack", output_file_name), None) {
+                                    Ok(Some(f)) => f,
+                                    Ok(None) => {
                                         return Ok(false);
                                     }
                                     Err(e) => {
@@ -318,10 +300,11 @@ where
                                     padding = vec![0; slack_bytes_read];
                                     output_file.write_all(&padding)?;
                                 }
+                                slack_file.finish()?;
                                 break;
                             }
                         }
-                        let chunk = if is_ads && read_buf.iter().all(|&b| b == 0) {
+                        let chunk = if is_ads && read_buf[..bytes_read].iter().all(|&b| b == 0) {
                             continue;
                         } else {
                             &read_buf[..bytes_read]
@@ -341,39 +324,26 @@ where
             }
         }
     }
-    // Retrieve timestamps from NtfsFile (replace these method calls with the actual methods from NtfsFile)
-    if let Ok(file_std_info) = file.info() {
-        let modified_time: DateTime<Local> =
-            nt_timestamp_to_datetime(file_std_info.modification_time().nt_timestamp());
-
-        let modified_file_time = FileTime::from_system_time(add_timezone_offset_to_system_time(
-            modified_time.into(),
-            modified_time.offset().local_minus_utc().into(),
-        ));
-
-        set_file_handle_times(&output_file, None, Some(modified_file_time))
-            .map_err(|e| anyhow::anyhow!("[ERROR] Failed to set file timestamps: {}", e))?;
-    }
-    match output_file.flush() {
+    match output_file.finish() {
         Ok(_) => {
             dprintln!("[INFO] Data successfully saved to `{}`", output_file_name);
-            return Ok(true);
+            Ok(true)
         }
         Err(e) => {
-            return Err(anyhow::anyhow!(
+            Err(anyhow::anyhow!(
                 "[ERROR] Problem to save `{}` file: {:?}",
                 output_file_name,
                 e
             ))
         }
-    };
+    }
 }
 
 fn get_valid_data_length<T>(fs: &mut T, attribut: &NtfsAttribute) -> Result<u64, Error>
 where
     T: Read + Seek,
 {
-    return match &attribut.ty()? {
+    match &attribut.ty()? {
         NtfsAttributeType::Data => match attribut.position().value() {
             Some(data_attr_position) => {
                 let mut buff = vec![0u8; 64];
@@ -392,7 +362,7 @@ where
             None => Err(anyhow::anyhow!("[ERROR] $DATA position not found")),
         },
         _ => Err(anyhow::anyhow!("[ERROR] Wrong attribut type")),
-    };
+    }
 }
 
 fn get_attr<T>(attr: &NtfsAttribute, fs: &mut T, output_file_name: &str) -> Result<(), Error>
@@ -408,13 +378,9 @@ where
     let attr_path = format!("{}%3A{}.idx", output_file_name, &attr_name);
     let mut attr_value = attr.value(fs)?;
 
-    let mut output_file = match OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(&attr_path)
-    {
-        Ok(f) => f,
-        Err(ref e) if e.kind() == ErrorKind::AlreadyExists => {
+    let mut output_file = match create_output(&attr_path, None) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
             return Ok(());
         }
         Err(e) => {
@@ -444,12 +410,92 @@ where
             ));
         }
     }
+    output_file.finish()?;
 
     Ok(())
 }
 
+/// Save a plain data stream (non NTFS file systems) in the output folder
+pub fn save_stream<R>(
+    reader: &mut R,
+    file_name: &str,
+    out_dir: &str,
+    encrypt: Option<&String>,
+    modified: Option<SystemTime>,
+) -> Result<bool, Error>
+where
+    R: Read,
+{
+    let password = encrypt.filter(|p| !p.is_empty());
+    let output_name = match password {
+        Some(_) => format!("{}.enc", file_name),
+        None => file_name.to_string(),
+    };
+    let output_file_name = format!("{}{}", out_dir, output_name.replace(":", "%3A"));
+
+    let mut output_file = match create_output(&output_file_name, modified) {
+        Ok(Some(f)) => f,
+        Ok(None) => {
+            return Ok(false);
+        }
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "[ERROR] Failed to open file `{}` for writing: {}",
+                output_file_name,
+                e
+            ));
+        }
+    };
+
+    let mut read_buf = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut total: u64 = 0;
+
+    if let Some(password) = password {
+        let mut hasher = Sha256::new();
+        hasher.update(password.as_bytes());
+        let key_bytes = hasher.finalize();
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key_bytes[..32]));
+
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let nonce = Nonce::from_slice(&nonce);
+        output_file.write_all(nonce)?;
+
+        loop {
+            let bytes_read = reader.read(&mut read_buf)?;
+            if bytes_read == 0 {
+                break;
+            }
+            let encrypted_chunk = cipher
+                .encrypt(nonce, &read_buf[..bytes_read])
+                .map_err(|e| anyhow::anyhow!("[ERROR] Encryption failed: {}", e))?;
+            output_file.write_all(&encrypted_chunk)?;
+            total += bytes_read as u64;
+        }
+    } else {
+        loop {
+            let bytes_read = reader.read(&mut read_buf)?;
+            if bytes_read == 0 {
+                break;
+            }
+            output_file.write_all(&read_buf[..bytes_read])?;
+            total += bytes_read as u64;
+        }
+    }
+
+    dprintln!(
+        "[INFO] Saving {} bytes of data in `{}`",
+        total,
+        output_file_name
+    );
+
+    output_file.finish()?;
+    dprintln!("[INFO] Data successfully saved to `{}`", output_file_name);
+    Ok(true)
+}
+
 // Function to convert NT timestamp (u64) to SystemTime
-fn nt_timestamp_to_system_time(nt_timestamp: u64) -> SystemTime {
+pub fn nt_timestamp_to_system_time(nt_timestamp: u64) -> SystemTime {
     // NT Epoch: January 1, 1601 -> UNIX Epoch: January 1, 1970 (difference in seconds)
     let nt_epoch_to_unix_epoch = Duration::from_secs(11644473600); // 369 years in seconds
     let timestamp_duration = Duration::from_nanos(nt_timestamp * 100); // Convert to nanoseconds
@@ -490,6 +536,10 @@ fn get_boot(drive_letter: &str) -> Result<Vec<u8>, Error> {
 }
 
 pub fn ensure_directory_exists(path: &str) -> std::io::Result<()> {
+    // Nothing is written in dry run
+    if is_dry_run() {
+        return Ok(());
+    }
     let path = Path::new(path);
     if !path.exists() {
         fs::create_dir_all(path)?;
@@ -515,6 +565,70 @@ pub fn replace_env_vars(input: &str) -> String {
     replaced_str.to_string()
 }
 
+// Size units of the configuration, the decimal ones are powers of 1000
+// and the binary ones (`KiB`, or a single letter like `K`) powers of 1024
+const SIZE_UNITS: [(&str, u64); 13] = [
+    ("b", 1),
+    ("k", 1 << 10),
+    ("kb", 1_000),
+    ("kib", 1 << 10),
+    ("m", 1 << 20),
+    ("mb", 1_000_000),
+    ("mib", 1 << 20),
+    ("g", 1 << 30),
+    ("gb", 1_000_000_000),
+    ("gib", 1 << 30),
+    ("t", 1 << 40),
+    ("tb", 1_000_000_000_000),
+    ("tib", 1 << 40),
+];
+
+/// Parse a size in bytes, either a number of bytes or a value with a unit like `10MB`, `5 MiB` or `1.5G`
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+    let unit = unit.trim().to_lowercase();
+
+    let multiplier = SIZE_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit || (unit.is_empty() && *name == "b"))
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| format!("invalid size `{}`, expected a size like `1048576`, `10MB`, `5 MiB` or `1G`", value))?;
+
+    if let Ok(bytes) = number.parse::<u64>() {
+        return bytes
+            .checked_mul(multiplier)
+            .ok_or_else(|| format!("size `{}` is too large", value));
+    }
+    match number.parse::<f64>() {
+        Ok(number) if number.is_finite() => {
+            let bytes = (number * multiplier as f64).round();
+            if bytes > u64::MAX as f64 {
+                return Err(format!("size `{}` is too large", value));
+            }
+            Ok(bytes as u64)
+        }
+        _ => Err(format!("invalid size `{}`, expected a size like `1048576`, `10MB`, `5 MiB` or `1G`", value)),
+    }
+}
+
+/// Format a size with the largest unit that keeps it exact (`10 MiB`, `1 GB`), in bytes otherwise
+pub fn format_size(bytes: u64) -> String {
+    for unit in ["TiB", "TB", "GiB", "GB", "MiB", "MB", "KiB", "KB"] {
+        let multiplier = SIZE_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit.to_lowercase())
+            .map_or(1, |(_, multiplier)| *multiplier);
+        if bytes >= multiplier && bytes.is_multiple_of(multiplier) {
+            return format!("{} {}", bytes / multiplier, unit);
+        }
+    }
+    format!("{} B", bytes)
+}
+
 pub fn remove_dir_all(path: &str) -> io::Result<()> {
     let path = Path::new(path); // Convert the string to a Path
     if path.is_dir() {
@@ -545,14 +659,151 @@ pub fn remove_dir_all(path: &str) -> io::Result<()> {
     Ok(())
 }
 
-pub fn remove_trailing_slash(input: String) -> String {
-    input.strip_suffix('/').unwrap_or(&input).to_string()
+/// Destination of the log lines
+enum LogSink {
+    File,
+    /// Output of an archive encrypted to recipients, the log never reaches the disk in plaintext
+    Archive(OutputFile),
+    /// The log is in the encrypted archive, the next lines are only printed in debug mode
+    Closed,
 }
 
-pub fn split_path(input: &str) -> (String, String) {
-    if let Some((path, last_segment)) = input.rsplit_once('/') {
-        (path.to_string(), last_segment.to_string())
-    } else {
-        (String::new(), input.to_string())
+// Serialize the writes to the log between threads
+static LOG_SINK: Mutex<LogSink> = Mutex::new(LogSink::File);
+
+thread_local! {
+    // Lines logged by the current thread while it collects a volume in parallel
+    static LOG_BUFFER: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Append log lines to the log file, or to the log of an encrypted archive, and print them in debug mode
+pub fn write_log_lines(lines: &[String]) {
+    let mut sink = LOG_SINK.lock().unwrap_or_else(|e| e.into_inner());
+    let debug = cfg!(debug_assertions) || env::var("DEBUG_MODE").is_ok();
+    if debug {
+        lines.iter().for_each(|line| println!("{}", line));
+    }
+    // The log file is not written in dry run
+    if is_dry_run() {
+        return;
+    }
+    match &mut *sink {
+        LogSink::File => (),
+        LogSink::Archive(output) => {
+            for line in lines {
+                writeln!(output, "{}", line).unwrap_or_else(|e| panic!("Unable to write the log in the archive: {}", e));
+            }
+            return;
+        }
+        LogSink::Closed => return,
+    }
+    let tmp_logfile = get_config().get_output_filename();
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(&tmp_logfile)
+        .unwrap_or_else(|_| panic!("[ERROR] Unable to open or create {}", tmp_logfile));
+
+    for line in lines {
+        writeln!(file, "{}", line).unwrap_or_else(|_| panic!("Unable to write to {}", tmp_logfile));
+    }
+}
+
+/// Write the log in an output of the encrypted archive from now on, with the lines of the log file
+pub fn start_archive_log(mut output: OutputFile) -> Result<()> {
+    let mut sink = LOG_SINK.lock().unwrap_or_else(|e| e.into_inner());
+    let tmp_logfile = get_config().get_output_filename();
+    if Path::new(&tmp_logfile).exists() {
+        output.write_all(&fs::read(&tmp_logfile)?)?;
+        fs::remove_file(&tmp_logfile)?;
+    }
+    *sink = LogSink::Archive(output);
+    Ok(())
+}
+
+/// Add the log to the encrypted archive, the next lines are only printed in debug mode
+pub fn finish_archive_log() -> Result<()> {
+    let sink = std::mem::replace(&mut *LOG_SINK.lock().unwrap_or_else(|e| e.into_inner()), LogSink::Closed);
+    if let LogSink::Archive(output) = sink {
+        output.finish()?;
+    }
+    Ok(())
+}
+
+/// Keep the log lines of the current thread in memory until `flush_log_buffer` is called
+pub fn start_log_buffer() {
+    LOG_BUFFER.with(|buffer| *buffer.borrow_mut() = Some(Vec::new()));
+}
+
+/// Store a log line in the buffer of the current thread, return false if there is no buffer
+pub fn buffer_log_line(line: &str) -> bool {
+    LOG_BUFFER.with(|buffer| match buffer.borrow_mut().as_mut() {
+        Some(lines) => {
+            lines.push(line.to_string());
+            true
+        }
+        None => false,
+    })
+}
+
+/// Write the buffered log lines of the current thread as one block
+pub fn flush_log_buffer() {
+    if let Some(lines) = LOG_BUFFER.with(|buffer| buffer.borrow_mut().take()) {
+        write_log_lines(&lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_binary_and_decimal_sizes() {
+        for (value, bytes) in [
+            ("0", 0),
+            ("0 MB", 0),
+            ("1048576", 1 << 20),
+            ("512B", 512),
+            ("1k", 1 << 10),
+            ("1KiB", 1 << 10),
+            ("1KB", 1_000),
+            ("10MB", 10_000_000),
+            ("5 MiB", 5 << 20),
+            ("5 mib", 5 << 20),
+            ("1G", 1 << 30),
+            ("1GB", 1_000_000_000),
+            ("1.5G", 3 << 29),
+            ("2 TiB", 2 << 40),
+            (" 4tb ", 4_000_000_000_000),
+        ] {
+            assert_eq!(parse_size(value), Ok(bytes), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        for value in ["", "MB", "10 XB", "-1", "1.2.3G", "20000000T", "1e3"] {
+            assert!(parse_size(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn formats_sizes_with_the_largest_exact_unit() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1 << 10), "1 KiB");
+        assert_eq!(format_size(1_000), "1 KB");
+        assert_eq!(format_size(10_000_000), "10 MB");
+        assert_eq!(format_size(5 << 20), "5 MiB");
+        assert_eq!(format_size(3 << 29), "1536 MiB");
+        assert_eq!(format_size(1 << 40), "1 TiB");
+        assert_eq!(format_size(1_500), "1500 B");
+    }
+
+    #[test]
+    fn formats_sizes_which_parse_back() {
+        for bytes in [0, 1, 1_000, 1 << 20, 25_000_000, 3 << 30, 7 << 40] {
+            assert_eq!(parse_size(&format_size(bytes)), Ok(bytes));
+        }
     }
 }
@@ -0,0 +1,441 @@
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Copyright © 2025 Areg Baghinyan. All Rights Reserved.
+//
+// Author(s): Areg Baghinyan
+//
+
+use crate::budget::{add_skipped_file, SkippedFile};
+use crate::conditions::VolumeFacts;
+use crate::config::SelectionOrder;
+use crate::filters::MAGIC_HEADER_SIZE;
+use crate::matcher::{EntrySettings, Matcher, Rule, RuleState};
+use crate::plan::{add_planned_file, is_dry_run};
+use crate::profiles::ProfileList;
+use crate::utils::format_size;
+use anyhow::Result;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+/// Metadata of a directory entry, shared by every file system
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    pub name: String,
+    pub is_directory: bool,
+    pub deleted: bool,
+    /// Identifier of the entry on the volume (file record, first cluster, inode number...)
+    pub id: u64,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub created: Option<SystemTime>,
+    /// Timestamps of the $FILE_NAME attribute (NTFS only)
+    pub file_name_modified: Option<SystemTime>,
+    pub file_name_created: Option<SystemTime>,
+}
+
+/// A volume that can be walked with the rules of a collect task
+pub trait Volume {
+    type Entry: Clone;
+
+    /// List a directory, `None` being the root directory
+    fn read_directory(&mut self, directory: Option<&Self::Entry>) -> Result<Vec<Self::Entry>>;
+
+    fn info(entry: &Self::Entry) -> &EntryInfo;
+
+    /// Complete metadata of a file about to be collected, when listing the directory gives only a part of it
+    fn file_info(&mut self, entry: &Self::Entry) -> Result<EntryInfo> {
+        Ok(Self::info(entry).clone())
+    }
+
+    /// Read the first `length` bytes of a file entry, or of its alternate data `stream` (NTFS only)
+    fn read_header(&mut self, entry: &Self::Entry, stream: &str, length: usize) -> Result<Vec<u8>>;
+
+    /// Save the content of a file entry, return false if it was already saved
+    fn save(
+        &mut self,
+        entry: &Self::Entry,
+        stream: &str,
+        file_name: &str,
+        destination_folder: &str,
+        encrypt: Option<&String>,
+    ) -> Result<bool>;
+
+    /// Drive letter of a live volume and the profiles of the running system,
+    /// `None` for an image whose profiles come from its own SOFTWARE hive
+    fn live_profiles(&mut self) -> Option<(char, ProfileList)> {
+        None
+    }
+}
+
+/// A matching file waiting for the budget selection
+struct Candidate<'a, E> {
+    entry: E,
+    path: String,
+    rule: &'a Rule,
+    settings: &'a EntrySettings,
+    info: EntryInfo,
+}
+
+struct Walker<'a, V: Volume> {
+    volume: &'a mut V,
+    matcher: &'a Matcher,
+    destination_folder: &'a str,
+    ancestors: Vec<u64>,
+    visited_files: HashSet<String>,
+    candidates: Vec<Candidate<'a, V::Entry>>,
+    success_files_count: u32,
+}
+
+impl<'a, V: Volume> Walker<'a, V> {
+    /// Check the filters of a matching file, return its metadata if it should be collected
+    fn check(&mut self, entry: &V::Entry, path: &str, rule: &Rule, settings: &EntrySettings) -> Option<EntryInfo> {
+        let path_check = if rule.stream.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}:{}", path, rule.stream)
+        };
+        // A file matching several rules is collected once
+        if !self.visited_files.insert(path_check) {
+            return None;
+        }
+
+        let info = match self.volume.file_info(entry) {
+            Ok(info) => info,
+            Err(e) => {
+                dprintln!("[ERROR] Problem to read the metadata of `{}`: {}", path, e);
+                return None;
+            }
+        };
+        let filters = &settings.filters;
+        if let Some(window) = &filters.time_window {
+            let in_window = window.matches(info.modified, info.created)
+                || (window.file_name_timestamps
+                    && window.matches(info.file_name_modified, info.file_name_created));
+            if !in_window {
+                dprintln!("[INFO] Skip {} because it is outside of the time window", path);
+                return None;
+            }
+        }
+        if let Some(msize) = settings.max_size {
+            if info.size > msize {
+                dprintln!("[WARN] Skip {} because the size exceeds {}", path, format_size(msize));
+                return None;
+            }
+        }
+        if filters.magic.is_some() {
+            let header = self
+                .volume
+                .read_header(entry, &rule.stream, MAGIC_HEADER_SIZE)
+                .unwrap_or_default();
+            if !filters.matches_magic(&header) {
+                dprintln!("[INFO] Skip {} because its content does not match the magic signatures", path);
+                return None;
+            }
+        }
+
+        Some(info)
+    }
+
+    /// Save a file, return false if it was not saved
+    fn save(&mut self, entry: &V::Entry, path: &str, rule: &Rule, settings: &EntrySettings, info: &EntryInfo) -> bool {
+        let output_name = if info.deleted {
+            dprintln!("[INFO] Recovering deleted entry `{}`", path);
+            format!("{}.deleted", path)
+        } else {
+            path.to_string()
+        };
+        if is_dry_run() {
+            let planned_path = if rule.stream.is_empty() {
+                output_name
+            } else {
+                format!("{}:{}", output_name, rule.stream)
+            };
+            add_planned_file(self.destination_folder, &planned_path, info.size);
+            self.success_files_count += 1;
+            return true;
+        }
+        match self.volume.save(
+            entry,
+            &rule.stream,
+            &output_name,
+            self.destination_folder,
+            settings.encrypt.as_ref(),
+        ) {
+            Ok(saved) => {
+                if saved {
+                    self.success_files_count += 1;
+                }
+                saved
+            }
+            Err(e) => {
+                dprintln!("{}", e.to_string());
+                false
+            }
+        }
+    }
+
+    /// Save the candidates in the selection order until the task or global budget is exhausted
+    fn save_candidates(&mut self) {
+        let mut candidates = std::mem::take(&mut self.candidates);
+        match self.matcher.selection_order {
+            SelectionOrder::Newest => candidates.sort_by_key(|c| Reverse(c.info.modified)),
+            SelectionOrder::Smallest => candidates.sort_by_key(|c| c.info.size),
+            SelectionOrder::EntryOrder => candidates.sort_by_key(|c| c.rule.entry_index()),
+        }
+
+        for candidate in candidates {
+            let path = if candidate.rule.stream.is_empty() {
+                candidate.path.clone()
+            } else {
+                format!("{}:{}", candidate.path, candidate.rule.stream)
+            };
+            if let Err(reason) = self.matcher.reserve(candidate.info.size) {
+                dprintln!("[WARN] Skip {} because the {}", path, reason);
+                add_skipped_file(SkippedFile {
+                    folder: self.destination_folder.to_string(),
+                    path,
+                    size: candidate.info.size,
+                    reason,
+                });
+                continue;
+            }
+            if !self.save(&candidate.entry, &candidate.path, candidate.rule, candidate.settings, &candidate.info) {
+                self.matcher.release(candidate.info.size);
+            }
+        }
+    }
+
+    /// Recursively walk the folders that can hold a match and collect the matching files
+    fn process_directory(
+        &mut self,
+        directory: Option<&V::Entry>,
+        current_path: &str,
+        depth: usize,
+        states: &[RuleState],
+    ) -> Result<()> {
+        let matcher = self.matcher;
+        let entries = self.volume.read_directory(directory)?;
+
+        for entry in &entries {
+            let info = V::info(entry);
+            let new_path = format!("{}/{}", current_path, info.name);
+            if info.is_directory {
+                let sub_states = matcher.enter(states, &info.name, &new_path, depth + 1);
+                // Protect against directory loops (e.g. a deleted entry pointing to a parent)
+                if sub_states.is_empty() || self.ancestors.contains(&info.id) {
+                    continue;
+                }
+                self.ancestors.push(info.id);
+                if let Err(e) = self.process_directory(Some(entry), &new_path, depth + 1, &sub_states) {
+                    dprintln!("[ERROR] Problem to process the folder `{}`: {}", new_path, e.to_string());
+                }
+                self.ancestors.pop();
+            } else {
+                for (rule, settings) in matcher.file_matches(states, &new_path) {
+                    let Some(info) = self.check(entry, &new_path, rule, settings) else {
+                        continue;
+                    };
+                    if matcher.has_budget() {
+                        self.candidates.push(Candidate {
+                            entry: entry.clone(),
+                            path: new_path.clone(),
+                            rule,
+                            settings,
+                            info,
+                        });
+                    } else {
+                        self.save(entry, &new_path, rule, settings, &info);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Find a file or a folder of a volume from its path, case insensitive, `None` if it does not exist
+pub fn find_entry<V: Volume>(volume: &mut V, path: &str) -> Result<Option<V::Entry>> {
+    let mut current: Option<V::Entry> = None;
+    let components: Vec<&str> = path.split(['\\', '/']).filter(|c| !c.is_empty()).collect();
+    for (i, component) in components.iter().enumerate() {
+        let is_last = i + 1 == components.len();
+        let found = volume
+            .read_directory(current.as_ref())?
+            .into_iter()
+            .find(|entry| {
+                let info = V::info(entry);
+                !info.deleted && (is_last || info.is_directory) && info.name.eq_ignore_ascii_case(component)
+            });
+        match found {
+            Some(entry) => current = Some(entry),
+            None => return Ok(None),
+        }
+    }
+    Ok(current)
+}
+
+/// Read a whole file of a volume from its path (e.g. `\Windows\System32\config\SOFTWARE`),
+/// `None` if it does not exist
+pub fn read_file<V: Volume>(volume: &mut V, path: &str) -> Result<Option<Vec<u8>>> {
+    match find_entry(volume, path)? {
+        Some(entry) if !V::info(&entry).is_directory => {
+            let size = volume.file_info(&entry)?.size;
+            Ok(Some(volume.read_header(&entry, "", size as usize)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Walk a volume from its root directory and collect the entries matching the rules
+pub fn explore<V: Volume>(volume: &mut V, matcher: &Matcher, destination_folder: &str) -> Result<()> {
+    // Conditions and profile variables are resolved with the files of the volume
+    let volume_matcher;
+    let matcher = if matcher.needs_volume() {
+        match matcher.for_volume(&mut VolumeFacts::new(&mut *volume)) {
+            Some(m) => {
+                volume_matcher = m;
+                &volume_matcher
+            }
+            None => return Ok(()),
+        }
+    } else {
+        matcher
+    };
+
+    let mut walker = Walker {
+        volume,
+        matcher,
+        destination_folder,
+        ancestors: Vec::new(),
+        visited_files: HashSet::new(),
+        candidates: Vec::new(),
+        success_files_count: 0,
+    };
+
+    let result = walker.process_directory(None, "", 0, &matcher.root_states());
+    // With a budget, the files found before an error are still selected and saved
+    walker.save_candidates();
+    match result {
+        Ok(_) => dprintln!(
+            "[INFO] Collection completed with {} collected files",
+            walker.success_files_count
+        ),
+        Err(e) => dprintln!("[ERROR] Problem to process the folder: {:?}", e),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Files of a root directory, `failing` ones can't be saved
+    struct MockVolume {
+        files: Vec<EntryInfo>,
+        failing: Vec<&'static str>,
+        saved: Vec<String>,
+    }
+
+    impl Volume for MockVolume {
+        type Entry = EntryInfo;
+
+        fn read_directory(&mut self, directory: Option<&EntryInfo>) -> Result<Vec<EntryInfo>> {
+            Ok(if directory.is_none() { self.files.clone() } else { Vec::new() })
+        }
+
+        fn info(entry: &EntryInfo) -> &EntryInfo {
+            entry
+        }
+
+        fn read_header(&mut self, _entry: &EntryInfo, _stream: &str, _length: usize) -> Result<Vec<u8>> {
+            Ok(Vec::new())
+        }
+
+        fn save(
+            &mut self,
+            entry: &EntryInfo,
+            _stream: &str,
+            file_name: &str,
+            _destination_folder: &str,
+            _encrypt: Option<&String>,
+        ) -> Result<bool> {
+            if self.failing.contains(&entry.name.as_str()) {
+                return Err(anyhow::anyhow!("[ERROR] Problem to save `{}`", file_name));
+            }
+            self.saved.push(entry.name.clone());
+            Ok(true)
+        }
+    }
+
+    fn file(id: u64, name: &str, size: u64, modified: u64) -> EntryInfo {
+        EntryInfo {
+            name: name.to_string(),
+            is_directory: false,
+            deleted: false,
+            id,
+            size,
+            modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(modified)),
+            created: None,
+            file_name_modified: None,
+            file_name_created: None,
+        }
+    }
+
+    /// Collect four files with a budget of two files, return the saved ones
+    fn collect(selection_order: &str, failing: Vec<&'static str>) -> Vec<String> {
+        let yaml = format!(
+            r#"
+priority: 1
+type: collect
+max_files: 2
+selection_order: {}
+entries:
+  logs:
+    - root_path: '\'
+      objects: ['*.log']
+  texts:
+    - root_path: '\'
+      objects: ['*.txt']
+"#,
+            selection_order
+        );
+        let matcher = Matcher::new(&serde_yaml::from_str(&yaml).unwrap());
+        let mut volume = MockVolume {
+            files: vec![
+                file(1, "a.txt", 30, 3),
+                file(2, "b.log", 20, 1),
+                file(3, "c.txt", 10, 2),
+                file(4, "d.log", 40, 4),
+            ],
+            failing,
+            saved: Vec::new(),
+        };
+        explore(&mut volume, &matcher, "out").unwrap();
+        volume.saved
+    }
+
+    #[test]
+    fn selects_the_newest_files() {
+        assert_eq!(collect("newest", Vec::new()), ["d.log", "a.txt"]);
+    }
+
+    #[test]
+    fn selects_the_smallest_files() {
+        assert_eq!(collect("smallest", Vec::new()), ["c.txt", "b.log"]);
+    }
+
+    #[test]
+    fn selects_the_files_in_the_order_of_the_entries() {
+        assert_eq!(collect("entry_order", Vec::new()), ["b.log", "d.log"]);
+    }
+
+    #[test]
+    fn gives_back_the_room_of_a_file_that_was_not_saved() {
+        assert_eq!(collect("entry_order", vec!["b.log"]), ["d.log", "a.txt"]);
+    }
+}